@@ -0,0 +1,34 @@
+--
+-- Pending email address changes, applied once the new address is confirmed.
+--
+
+CREATE TABLE public.email_change (
+    id integer NOT NULL,
+    id_user integer NOT NULL,
+    new_email character varying(128) NOT NULL,
+    chain character varying(32) DEFAULT md5((random())::text) NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    confirmed_stamp timestamp without time zone
+);
+
+ALTER TABLE public.email_change OWNER TO cnm;
+
+CREATE SEQUENCE public.email_change_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.email_change_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.email_change_id_seq OWNED BY public.email_change.id;
+
+ALTER TABLE ONLY public.email_change ALTER COLUMN id SET DEFAULT nextval('public.email_change_id_seq'::regclass);
+
+ALTER TABLE ONLY public.email_change
+    ADD CONSTRAINT email_change_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.email_change
+    ADD CONSTRAINT email_change_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
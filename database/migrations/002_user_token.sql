@@ -0,0 +1,51 @@
+--
+-- Single-use, expiring tokens replacing cnm_user.verify_chain. Only the
+-- SHA-256 of each token is stored.
+--
+
+CREATE TYPE public.token_purpose AS ENUM (
+    'verify',
+    'reset',
+    'email_change'
+);
+
+ALTER TYPE public.token_purpose OWNER TO cnm;
+
+CREATE TABLE public.user_token (
+    id integer NOT NULL,
+    id_user integer NOT NULL,
+    purpose public.token_purpose NOT NULL,
+    token_hash character varying(64) NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    expiry_stamp timestamp without time zone NOT NULL,
+    used_stamp timestamp without time zone
+);
+
+ALTER TABLE public.user_token OWNER TO cnm;
+
+CREATE SEQUENCE public.user_token_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.user_token_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.user_token_id_seq OWNED BY public.user_token.id;
+
+ALTER TABLE ONLY public.user_token ALTER COLUMN id SET DEFAULT nextval('public.user_token_id_seq'::regclass);
+
+ALTER TABLE ONLY public.user_token
+    ADD CONSTRAINT user_token_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.user_token
+    ADD CONSTRAINT user_token_token_hash_key UNIQUE (token_hash);
+
+ALTER TABLE ONLY public.user_token
+    ADD CONSTRAINT user_token_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
+
+ALTER TABLE public.email_change DROP COLUMN chain;
+
+ALTER TABLE public.cnm_user DROP COLUMN verify_chain;
@@ -0,0 +1,45 @@
+--
+-- Personal API tokens used by scripts and integrations. Only the SHA-256 of
+-- each token is stored.
+--
+
+CREATE TABLE public.api_token (
+    id integer NOT NULL,
+    id_user integer NOT NULL,
+    name character varying(128) NOT NULL,
+    token_hash character varying(64) NOT NULL,
+    scopes text[] DEFAULT '{}'::text[] NOT NULL,
+    id_band integer,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    expiry_stamp timestamp without time zone,
+    last_used_stamp timestamp without time zone,
+    revoked_stamp timestamp without time zone
+);
+
+ALTER TABLE public.api_token OWNER TO cnm;
+
+CREATE SEQUENCE public.api_token_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.api_token_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.api_token_id_seq OWNED BY public.api_token.id;
+
+ALTER TABLE ONLY public.api_token ALTER COLUMN id SET DEFAULT nextval('public.api_token_id_seq'::regclass);
+
+ALTER TABLE ONLY public.api_token
+    ADD CONSTRAINT api_token_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.api_token
+    ADD CONSTRAINT api_token_token_hash_key UNIQUE (token_hash);
+
+ALTER TABLE ONLY public.api_token
+    ADD CONSTRAINT api_token_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.api_token
+    ADD CONSTRAINT api_token_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
@@ -0,0 +1,54 @@
+--
+-- OpenID Connect logins: identities linked to local accounts and
+-- authorization requests waiting for the provider callback.
+--
+
+CREATE TABLE public.user_identity (
+    id integer NOT NULL,
+    id_user integer NOT NULL,
+    issuer character varying(256) NOT NULL,
+    subject character varying(256) NOT NULL,
+    email character varying(128),
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.user_identity OWNER TO cnm;
+
+CREATE SEQUENCE public.user_identity_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.user_identity_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.user_identity_id_seq OWNED BY public.user_identity.id;
+
+ALTER TABLE ONLY public.user_identity ALTER COLUMN id SET DEFAULT nextval('public.user_identity_id_seq'::regclass);
+
+ALTER TABLE ONLY public.user_identity
+    ADD CONSTRAINT user_identity_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.user_identity
+    ADD CONSTRAINT user_identity_issuer_subject_key UNIQUE (issuer, subject);
+
+ALTER TABLE ONLY public.user_identity
+    ADD CONSTRAINT user_identity_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
+
+CREATE TABLE public.oidc_login (
+    state character varying(64) NOT NULL,
+    nonce character varying(64) NOT NULL,
+    code_verifier character varying(128) NOT NULL,
+    id_user integer,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.oidc_login OWNER TO cnm;
+
+ALTER TABLE ONLY public.oidc_login
+    ADD CONSTRAINT oidc_login_pkey PRIMARY KEY (state);
+
+ALTER TABLE ONLY public.oidc_login
+    ADD CONSTRAINT oidc_login_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
@@ -0,0 +1,51 @@
+--
+-- Invitations to join a band, sent by email to people who may not have an
+-- account yet. Only the SHA-256 of the invitation token is stored.
+--
+
+CREATE TYPE public.invitation_status AS ENUM (
+    'pending',
+    'accepted',
+    'declined',
+    'revoked'
+);
+
+ALTER TYPE public.invitation_status OWNER TO cnm;
+
+CREATE TABLE public.band_invitation (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    id_inviter integer NOT NULL,
+    email character varying(128) NOT NULL,
+    is_admin boolean DEFAULT false NOT NULL,
+    token_hash character varying(64) NOT NULL,
+    status public.invitation_status DEFAULT 'pending'::public.invitation_status NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    expiry_stamp timestamp without time zone NOT NULL,
+    answer_stamp timestamp without time zone
+);
+
+ALTER TABLE public.band_invitation OWNER TO cnm;
+
+CREATE SEQUENCE public.band_invitation_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.band_invitation_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.band_invitation_id_seq OWNED BY public.band_invitation.id;
+
+ALTER TABLE ONLY public.band_invitation ALTER COLUMN id SET DEFAULT nextval('public.band_invitation_id_seq'::regclass);
+
+ALTER TABLE ONLY public.band_invitation
+    ADD CONSTRAINT band_invitation_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.band_invitation
+    ADD CONSTRAINT band_invitation_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.band_invitation
+    ADD CONSTRAINT band_invitation_id_inviter_fkey FOREIGN KEY (id_inviter) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
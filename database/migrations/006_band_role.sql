@@ -0,0 +1,81 @@
+--
+-- Named band roles replacing user_band.is_admin. Built-in roles have no
+-- band, custom roles belong to the band that defined them.
+--
+
+CREATE TABLE public.band_role (
+    id integer NOT NULL,
+    id_band integer,
+    name character varying(64) NOT NULL,
+    permissions text[] DEFAULT '{}'::text[] NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.band_role OWNER TO cnm;
+
+CREATE SEQUENCE public.band_role_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.band_role_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.band_role_id_seq OWNED BY public.band_role.id;
+
+ALTER TABLE ONLY public.band_role ALTER COLUMN id SET DEFAULT nextval('public.band_role_id_seq'::regclass);
+
+ALTER TABLE ONLY public.band_role
+    ADD CONSTRAINT band_role_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.band_role
+    ADD CONSTRAINT band_role_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+CREATE UNIQUE INDEX band_role_builtin_name_idx ON public.band_role (name) WHERE id_band IS NULL;
+
+CREATE UNIQUE INDEX band_role_band_name_idx ON public.band_role (id_band, name) WHERE id_band IS NOT NULL;
+
+INSERT INTO public.band_role(name, permissions) VALUES
+    ('owner', '{read_orgs,tag_orgs,manage_contacts,write_notes,delete_notes,manage_members,manage_roles,manage_band,delete_band}'),
+    ('admin', '{read_orgs,tag_orgs,manage_contacts,write_notes,delete_notes,manage_members,manage_roles,manage_band}'),
+    ('booker', '{read_orgs,tag_orgs,manage_contacts,write_notes}'),
+    ('viewer', '{read_orgs}');
+
+--
+-- Existing memberships: band creators become owners, administrators keep
+-- their rights and every other member keeps being able to book.
+--
+
+ALTER TABLE public.user_band ADD COLUMN id_role integer;
+
+UPDATE public.user_band ub SET id_role = (
+    SELECT br.id FROM public.band_role br
+    WHERE br.id_band IS NULL AND br.name = CASE
+        WHEN EXISTS (SELECT 1 FROM public.band b WHERE b.id = ub.id_band AND b.id_creator = ub.id_user) THEN 'owner'
+        WHEN ub.is_admin THEN 'admin'
+        ELSE 'booker'
+    END
+);
+
+ALTER TABLE public.user_band ALTER COLUMN id_role SET NOT NULL;
+
+ALTER TABLE ONLY public.user_band
+    ADD CONSTRAINT user_band_id_role_fkey FOREIGN KEY (id_role) REFERENCES public.band_role(id);
+
+ALTER TABLE public.user_band DROP COLUMN is_admin;
+
+ALTER TABLE public.band_invitation ADD COLUMN id_role integer;
+
+UPDATE public.band_invitation bi SET id_role = (
+    SELECT br.id FROM public.band_role br
+    WHERE br.id_band IS NULL AND br.name = CASE WHEN bi.is_admin THEN 'admin' ELSE 'booker' END
+);
+
+ALTER TABLE public.band_invitation ALTER COLUMN id_role SET NOT NULL;
+
+ALTER TABLE ONLY public.band_invitation
+    ADD CONSTRAINT band_invitation_id_role_fkey FOREIGN KEY (id_role) REFERENCES public.band_role(id) ON DELETE CASCADE;
+
+ALTER TABLE public.band_invitation DROP COLUMN is_admin;
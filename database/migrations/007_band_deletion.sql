@@ -0,0 +1,35 @@
+--
+-- Deleted bands are kept for a grace period before being purged, so that
+-- their members, assignments, notes and contacts can still be recovered.
+--
+
+ALTER TABLE public.band ADD COLUMN deleted_stamp timestamp without time zone;
+
+ALTER TABLE public.band ADD COLUMN id_deleter integer;
+
+ALTER TABLE ONLY public.band
+    ADD CONSTRAINT band_id_deleter_fkey FOREIGN KEY (id_deleter) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+--
+-- Purging a band removes everything it owns.
+--
+
+ALTER TABLE ONLY public.contact DROP CONSTRAINT contact_id_band_fkey;
+
+ALTER TABLE ONLY public.contact
+    ADD CONSTRAINT contact_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.note DROP CONSTRAINT note_id_band_fkey;
+
+ALTER TABLE ONLY public.note
+    ADD CONSTRAINT note_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.org_assign DROP CONSTRAINT org_assign_id_band_fkey;
+
+ALTER TABLE ONLY public.org_assign
+    ADD CONSTRAINT org_assign_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.user_band DROP CONSTRAINT user_band_id_band_fkey;
+
+ALTER TABLE ONLY public.user_band
+    ADD CONSTRAINT user_band_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
@@ -0,0 +1,6 @@
+--
+-- Archived bands are on hiatus: hidden by default and read-only until an
+-- owner brings them back.
+--
+
+ALTER TABLE public.band ADD COLUMN archived_stamp timestamp without time zone;
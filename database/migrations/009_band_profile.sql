@@ -0,0 +1,78 @@
+--
+-- Band profile used when pitching venues, and the files (press kit,
+-- technical rider) attached to it. File contents live in the storage
+-- directory, only their description is kept here.
+--
+
+CREATE TABLE public.band_profile (
+    id_band integer NOT NULL,
+    genres text[] DEFAULT '{}'::text[] NOT NULL,
+    short_bio character varying(280),
+    long_bio text,
+    website character varying(256),
+    social_links text[] DEFAULT '{}'::text[] NOT NULL,
+    streaming_links text[] DEFAULT '{}'::text[] NOT NULL,
+    lineup_size integer,
+    home_city character varying(128),
+    home_postal_code character varying(16),
+    fee_min integer,
+    fee_max integer,
+    fee_currency character varying(3) DEFAULT 'EUR' NOT NULL,
+    fee_notes text,
+    update_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.band_profile OWNER TO cnm;
+
+ALTER TABLE ONLY public.band_profile
+    ADD CONSTRAINT band_profile_pkey PRIMARY KEY (id_band);
+
+ALTER TABLE ONLY public.band_profile
+    ADD CONSTRAINT band_profile_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+CREATE TYPE public.band_file_kind AS ENUM (
+    'press_kit',
+    'rider'
+);
+
+ALTER TYPE public.band_file_kind OWNER TO cnm;
+
+CREATE TABLE public.band_file (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    id_uploader integer,
+    kind public.band_file_kind NOT NULL,
+    filename character varying(256) NOT NULL,
+    content_type character varying(128) NOT NULL,
+    size bigint NOT NULL,
+    storage_key character varying(64) NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.band_file OWNER TO cnm;
+
+CREATE SEQUENCE public.band_file_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.band_file_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.band_file_id_seq OWNED BY public.band_file.id;
+
+ALTER TABLE ONLY public.band_file ALTER COLUMN id SET DEFAULT nextval('public.band_file_id_seq'::regclass);
+
+ALTER TABLE ONLY public.band_file
+    ADD CONSTRAINT band_file_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.band_file
+    ADD CONSTRAINT band_file_storage_key_key UNIQUE (storage_key);
+
+ALTER TABLE ONLY public.band_file
+    ADD CONSTRAINT band_file_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.band_file
+    ADD CONSTRAINT band_file_id_uploader_fkey FOREIGN KEY (id_uploader) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
@@ -0,0 +1,17 @@
+--
+-- Assignment is decoupled from status: an org keeps its status for the band
+-- while it has no assignee, and each org has at most one row per band.
+--
+
+DELETE FROM public.org_assign oa
+WHERE EXISTS (
+    SELECT 1 FROM public.org_assign newer
+    WHERE newer.id_org = oa.id_org AND newer.id_band = oa.id_band AND newer.id > oa.id
+);
+
+ALTER TABLE public.org_assign ALTER COLUMN id_user DROP NOT NULL;
+
+CREATE UNIQUE INDEX org_assign_org_band_idx ON public.org_assign (id_org, id_band);
+
+UPDATE public.band_role SET permissions = array_append(permissions, 'assign_orgs')
+WHERE id_band IS NULL AND name IN ('owner', 'admin');
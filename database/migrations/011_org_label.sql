@@ -0,0 +1,54 @@
+--
+-- Band-scoped labels put on orgs, independently of their pipeline status.
+--
+
+CREATE TABLE public.band_label (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    name character varying(64) NOT NULL,
+    color character varying(7) DEFAULT '#9e9e9e'::character varying NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.band_label OWNER TO cnm;
+
+CREATE SEQUENCE public.band_label_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.band_label_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.band_label_id_seq OWNED BY public.band_label.id;
+
+ALTER TABLE ONLY public.band_label ALTER COLUMN id SET DEFAULT nextval('public.band_label_id_seq'::regclass);
+
+ALTER TABLE ONLY public.band_label
+    ADD CONSTRAINT band_label_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.band_label
+    ADD CONSTRAINT band_label_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+CREATE UNIQUE INDEX band_label_band_name_idx ON public.band_label (id_band, name);
+
+CREATE TABLE public.org_label (
+    id_label integer NOT NULL,
+    id_org integer NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.org_label OWNER TO cnm;
+
+ALTER TABLE ONLY public.org_label
+    ADD CONSTRAINT org_label_pkey PRIMARY KEY (id_label, id_org);
+
+ALTER TABLE ONLY public.org_label
+    ADD CONSTRAINT org_label_id_label_fkey FOREIGN KEY (id_label) REFERENCES public.band_label(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.org_label
+    ADD CONSTRAINT org_label_id_org_fkey FOREIGN KEY (id_org) REFERENCES public.org(id) ON DELETE CASCADE;
+
+CREATE INDEX org_label_id_org_idx ON public.org_label (id_org);
@@ -0,0 +1,60 @@
+--
+-- Named org listing filters, private to their owner or shared with the band,
+-- and the last time each user opened them.
+--
+
+CREATE TABLE public.saved_search (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    id_owner integer NOT NULL,
+    name character varying(128) NOT NULL,
+    filters text DEFAULT '[]'::text NOT NULL,
+    labels integer[] DEFAULT '{}'::integer[] NOT NULL,
+    sort_key character varying(32) DEFAULT 'name'::character varying NOT NULL,
+    sort_desc boolean DEFAULT false NOT NULL,
+    shared boolean DEFAULT false NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    update_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.saved_search OWNER TO cnm;
+
+CREATE SEQUENCE public.saved_search_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.saved_search_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.saved_search_id_seq OWNED BY public.saved_search.id;
+
+ALTER TABLE ONLY public.saved_search ALTER COLUMN id SET DEFAULT nextval('public.saved_search_id_seq'::regclass);
+
+ALTER TABLE ONLY public.saved_search
+    ADD CONSTRAINT saved_search_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.saved_search
+    ADD CONSTRAINT saved_search_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.saved_search
+    ADD CONSTRAINT saved_search_id_owner_fkey FOREIGN KEY (id_owner) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
+
+CREATE TABLE public.saved_search_view (
+    id_search integer NOT NULL,
+    id_user integer NOT NULL,
+    opened_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.saved_search_view OWNER TO cnm;
+
+ALTER TABLE ONLY public.saved_search_view
+    ADD CONSTRAINT saved_search_view_pkey PRIMARY KEY (id_search, id_user);
+
+ALTER TABLE ONLY public.saved_search_view
+    ADD CONSTRAINT saved_search_view_id_search_fkey FOREIGN KEY (id_search) REFERENCES public.saved_search(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.saved_search_view
+    ADD CONSTRAINT saved_search_view_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
@@ -0,0 +1,59 @@
+--
+-- Domain events of a band, shown to its members as an activity feed.
+--
+
+CREATE TYPE public.band_event_kind AS ENUM (
+    'org_tagged',
+    'contact_added',
+    'contact_edited',
+    'contact_removed',
+    'note_created',
+    'note_edited',
+    'note_deleted',
+    'member_joined',
+    'member_left',
+    'member_kicked'
+);
+
+ALTER TYPE public.band_event_kind OWNER TO cnm;
+
+CREATE TABLE public.band_event (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    id_actor integer,
+    kind public.band_event_kind NOT NULL,
+    id_org integer,
+    id_target integer,
+    details jsonb DEFAULT '{}'::jsonb NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.band_event OWNER TO cnm;
+
+CREATE SEQUENCE public.band_event_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.band_event_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.band_event_id_seq OWNED BY public.band_event.id;
+
+ALTER TABLE ONLY public.band_event ALTER COLUMN id SET DEFAULT nextval('public.band_event_id_seq'::regclass);
+
+ALTER TABLE ONLY public.band_event
+    ADD CONSTRAINT band_event_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.band_event
+    ADD CONSTRAINT band_event_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.band_event
+    ADD CONSTRAINT band_event_id_actor_fkey FOREIGN KEY (id_actor) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+ALTER TABLE ONLY public.band_event
+    ADD CONSTRAINT band_event_id_org_fkey FOREIGN KEY (id_org) REFERENCES public.org(id) ON DELETE SET NULL;
+
+CREATE INDEX band_event_band_stamp_idx ON public.band_event (id_band, creation_stamp DESC);
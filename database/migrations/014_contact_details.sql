@@ -0,0 +1,43 @@
+--
+-- Role, job title, preferred channel and language of a contact, with
+-- typed lists of emails, phones and social handles. The email and phone
+-- columns keep the primary value of each list.
+--
+
+CREATE TYPE public.contact_role AS ENUM (
+    'programmer',
+    'booker',
+    'technician',
+    'manager',
+    'press',
+    'other'
+);
+
+ALTER TYPE public.contact_role OWNER TO cnm;
+
+CREATE TYPE public.contact_channel AS ENUM (
+    'email',
+    'phone',
+    'social',
+    'post'
+);
+
+ALTER TYPE public.contact_channel OWNER TO cnm;
+
+ALTER TABLE public.contact
+    ALTER COLUMN email TYPE character varying(254),
+    ADD COLUMN role public.contact_role,
+    ADD COLUMN job_title character varying(128),
+    ADD COLUMN preferred_channel public.contact_channel,
+    ADD COLUMN language character varying(8),
+    ADD COLUMN emails jsonb DEFAULT '[]'::jsonb NOT NULL,
+    ADD COLUMN phones jsonb DEFAULT '[]'::jsonb NOT NULL,
+    ADD COLUMN socials jsonb DEFAULT '[]'::jsonb NOT NULL;
+
+UPDATE public.contact
+SET emails = jsonb_build_array(jsonb_build_object('kind', 'work', 'value', email))
+WHERE email IS NOT NULL AND email <> '';
+
+UPDATE public.contact
+SET phones = jsonb_build_array(jsonb_build_object('kind', 'work', 'value', phone))
+WHERE phone IS NOT NULL AND phone <> '';
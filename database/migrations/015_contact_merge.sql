@@ -0,0 +1,5 @@
+--
+-- Merging duplicate contacts is recorded in the band feed.
+--
+
+ALTER TYPE public.band_event_kind ADD VALUE 'contact_merged' AFTER 'contact_removed';
@@ -0,0 +1,81 @@
+--
+-- Structured log of the calls, emails and meetings with the contacts of an
+-- org, the latest one being the date the org was last contacted.
+--
+
+CREATE TYPE public.interaction_kind AS ENUM (
+    'call',
+    'email',
+    'meeting',
+    'message',
+    'other'
+);
+
+ALTER TYPE public.interaction_kind OWNER TO cnm;
+
+CREATE TYPE public.interaction_direction AS ENUM (
+    'inbound',
+    'outbound'
+);
+
+ALTER TYPE public.interaction_direction OWNER TO cnm;
+
+CREATE TYPE public.interaction_outcome AS ENUM (
+    'reached',
+    'no_answer',
+    'left_message',
+    'follow_up',
+    'booked',
+    'declined'
+);
+
+ALTER TYPE public.interaction_outcome OWNER TO cnm;
+
+CREATE TABLE public.interaction (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    id_org integer NOT NULL,
+    id_contact integer,
+    id_user integer,
+    kind public.interaction_kind NOT NULL,
+    direction public.interaction_direction NOT NULL,
+    outcome public.interaction_outcome,
+    summary text,
+    happened_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.interaction OWNER TO cnm;
+
+CREATE SEQUENCE public.interaction_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.interaction_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.interaction_id_seq OWNED BY public.interaction.id;
+
+ALTER TABLE ONLY public.interaction ALTER COLUMN id SET DEFAULT nextval('public.interaction_id_seq'::regclass);
+
+ALTER TABLE ONLY public.interaction
+    ADD CONSTRAINT interaction_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.interaction
+    ADD CONSTRAINT interaction_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.interaction
+    ADD CONSTRAINT interaction_id_org_fkey FOREIGN KEY (id_org) REFERENCES public.org(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.interaction
+    ADD CONSTRAINT interaction_id_contact_fkey FOREIGN KEY (id_contact) REFERENCES public.contact(id) ON DELETE SET NULL;
+
+ALTER TABLE ONLY public.interaction
+    ADD CONSTRAINT interaction_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+CREATE INDEX interaction_band_org_stamp_idx ON public.interaction (id_band, id_org, happened_stamp DESC);
+
+CREATE INDEX interaction_contact_idx ON public.interaction (id_contact);
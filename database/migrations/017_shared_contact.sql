@@ -0,0 +1,82 @@
+--
+-- Directory of contacts shared between bands. A band contributes a copy of
+-- one of its contacts, which every band sees on the org once a platform
+-- admin approved it. Band contacts which are not contributed stay private.
+--
+
+ALTER TABLE public.cnm_user
+    ADD COLUMN platform_admin boolean DEFAULT false NOT NULL;
+
+CREATE TYPE public.shared_contact_status AS ENUM (
+    'pending',
+    'approved',
+    'rejected'
+);
+
+ALTER TYPE public.shared_contact_status OWNER TO cnm;
+
+CREATE TABLE public.shared_contact (
+    id integer NOT NULL,
+    id_org integer NOT NULL,
+    id_contact integer NOT NULL,
+    id_band integer NOT NULL,
+    id_contributor integer,
+    status public.shared_contact_status DEFAULT 'pending' NOT NULL,
+    name character varying(64) NOT NULL,
+    firstname character varying(64),
+    email character varying(254),
+    phone character varying(64),
+    address character varying(512),
+    zip_code character varying(16),
+    city character varying(60),
+    role public.contact_role,
+    job_title character varying(128),
+    preferred_channel public.contact_channel,
+    language character varying(8),
+    emails jsonb DEFAULT '[]'::jsonb NOT NULL,
+    phones jsonb DEFAULT '[]'::jsonb NOT NULL,
+    socials jsonb DEFAULT '[]'::jsonb NOT NULL,
+    id_moderator integer,
+    moderation_reason text,
+    moderation_stamp timestamp without time zone,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.shared_contact OWNER TO cnm;
+
+CREATE SEQUENCE public.shared_contact_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.shared_contact_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.shared_contact_id_seq OWNED BY public.shared_contact.id;
+
+ALTER TABLE ONLY public.shared_contact ALTER COLUMN id SET DEFAULT nextval('public.shared_contact_id_seq'::regclass);
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_id_contact_key UNIQUE (id_contact);
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_id_org_fkey FOREIGN KEY (id_org) REFERENCES public.org(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_id_contact_fkey FOREIGN KEY (id_contact) REFERENCES public.contact(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_id_contributor_fkey FOREIGN KEY (id_contributor) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+ALTER TABLE ONLY public.shared_contact
+    ADD CONSTRAINT shared_contact_id_moderator_fkey FOREIGN KEY (id_moderator) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+CREATE INDEX shared_contact_org_status_idx ON public.shared_contact (id_org, status);
@@ -0,0 +1,29 @@
+--
+-- Legal basis and source of the personal data held on each contact, and
+-- the date a contact was flagged as kept past the retention period. The
+-- update stamp tells when the contact was last edited or reviewed.
+--
+
+CREATE TYPE public.legal_basis AS ENUM (
+    'consent',
+    'legitimate_interest',
+    'contract',
+    'legal_obligation'
+);
+
+ALTER TYPE public.legal_basis OWNER TO cnm;
+
+ALTER TABLE public.contact
+    ADD COLUMN legal_basis public.legal_basis,
+    ADD COLUMN data_source character varying(256),
+    ADD COLUMN basis_stamp timestamp without time zone,
+    ADD COLUMN update_stamp timestamp without time zone,
+    ADD COLUMN stale_since timestamp without time zone;
+
+ALTER TABLE public.shared_contact
+    ADD COLUMN legal_basis public.legal_basis,
+    ADD COLUMN data_source character varying(256),
+    ADD COLUMN basis_stamp timestamp without time zone,
+    ADD COLUMN stale_since timestamp without time zone;
+
+CREATE INDEX contact_stale_since_idx ON public.contact USING btree (id_band, stale_since);
@@ -0,0 +1,80 @@
+--
+-- Notes are written in Markdown, their sanitised HTML rendering is kept
+-- next to the source. Band members mentioned with @pseudo get an in-app
+-- notification, and an email unless they opted out.
+--
+
+ALTER TABLE public.note
+    ADD COLUMN html text;
+
+ALTER TABLE public.cnm_user
+    ADD COLUMN mention_emails boolean DEFAULT true NOT NULL;
+
+CREATE TABLE public.note_mention (
+    id_note integer NOT NULL,
+    id_user integer NOT NULL
+);
+
+ALTER TABLE public.note_mention OWNER TO cnm;
+
+ALTER TABLE ONLY public.note_mention
+    ADD CONSTRAINT note_mention_pkey PRIMARY KEY (id_note, id_user);
+
+ALTER TABLE ONLY public.note_mention
+    ADD CONSTRAINT note_mention_id_note_fkey FOREIGN KEY (id_note) REFERENCES public.note(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.note_mention
+    ADD CONSTRAINT note_mention_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
+
+CREATE TYPE public.notification_kind AS ENUM (
+    'mention'
+);
+
+ALTER TYPE public.notification_kind OWNER TO cnm;
+
+CREATE TABLE public.notification (
+    id integer NOT NULL,
+    id_user integer NOT NULL,
+    id_band integer NOT NULL,
+    kind public.notification_kind NOT NULL,
+    id_note integer,
+    id_actor integer,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    read_stamp timestamp without time zone
+);
+
+ALTER TABLE public.notification OWNER TO cnm;
+
+CREATE SEQUENCE public.notification_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.notification_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.notification_id_seq OWNED BY public.notification.id;
+
+ALTER TABLE ONLY public.notification ALTER COLUMN id SET DEFAULT nextval('public.notification_id_seq'::regclass);
+
+ALTER TABLE ONLY public.notification
+    ADD CONSTRAINT notification_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.notification
+    ADD CONSTRAINT notification_id_user_kind_id_note_key UNIQUE (id_user, kind, id_note);
+
+ALTER TABLE ONLY public.notification
+    ADD CONSTRAINT notification_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.notification
+    ADD CONSTRAINT notification_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.notification
+    ADD CONSTRAINT notification_id_note_fkey FOREIGN KEY (id_note) REFERENCES public.note(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.notification
+    ADD CONSTRAINT notification_id_actor_fkey FOREIGN KEY (id_actor) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+CREATE INDEX notification_user_stamp_idx ON public.notification (id_user, creation_stamp DESC);
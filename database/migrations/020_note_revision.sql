@@ -0,0 +1,53 @@
+--
+-- Every revision of a note is kept with its author. Deleting a note only
+-- hides it, until it is restored.
+--
+
+ALTER TYPE public.band_event_kind ADD VALUE 'note_restored' AFTER 'note_deleted';
+
+ALTER TABLE public.note
+    ADD COLUMN edited_stamp timestamp without time zone,
+    ADD COLUMN deleted_stamp timestamp without time zone,
+    ADD COLUMN id_deleter integer;
+
+ALTER TABLE ONLY public.note
+    ADD CONSTRAINT note_id_deleter_fkey FOREIGN KEY (id_deleter) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+CREATE TABLE public.note_revision (
+    id integer NOT NULL,
+    id_note integer NOT NULL,
+    id_user integer,
+    note text NOT NULL,
+    html text,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.note_revision OWNER TO cnm;
+
+CREATE SEQUENCE public.note_revision_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.note_revision_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.note_revision_id_seq OWNED BY public.note_revision.id;
+
+ALTER TABLE ONLY public.note_revision ALTER COLUMN id SET DEFAULT nextval('public.note_revision_id_seq'::regclass);
+
+ALTER TABLE ONLY public.note_revision
+    ADD CONSTRAINT note_revision_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.note_revision
+    ADD CONSTRAINT note_revision_id_note_fkey FOREIGN KEY (id_note) REFERENCES public.note(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.note_revision
+    ADD CONSTRAINT note_revision_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+CREATE INDEX note_revision_id_note_idx ON public.note_revision (id_note, creation_stamp);
+
+INSERT INTO public.note_revision(id_note, id_user, note, html, creation_stamp)
+SELECT id, id_user, note, html, creation_stamp FROM public.note;
@@ -0,0 +1,39 @@
+--
+-- Notes of an activity form threads: a note may answer another one. Key
+-- notes are pinned at the top of the activity, and members react to notes
+-- with emojis.
+--
+
+ALTER TYPE public.band_event_kind ADD VALUE 'note_pinned' AFTER 'note_restored';
+ALTER TYPE public.band_event_kind ADD VALUE 'note_unpinned' AFTER 'note_pinned';
+
+ALTER TABLE public.note
+    ADD COLUMN id_parent integer,
+    ADD COLUMN pinned_stamp timestamp without time zone,
+    ADD COLUMN id_pinner integer;
+
+ALTER TABLE ONLY public.note
+    ADD CONSTRAINT note_id_parent_fkey FOREIGN KEY (id_parent) REFERENCES public.note(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.note
+    ADD CONSTRAINT note_id_pinner_fkey FOREIGN KEY (id_pinner) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+CREATE INDEX note_id_parent_idx ON public.note (id_parent);
+
+CREATE TABLE public.note_reaction (
+    id_note integer NOT NULL,
+    id_user integer NOT NULL,
+    emoji character varying(32) NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE public.note_reaction OWNER TO cnm;
+
+ALTER TABLE ONLY public.note_reaction
+    ADD CONSTRAINT note_reaction_pkey PRIMARY KEY (id_note, id_user, emoji);
+
+ALTER TABLE ONLY public.note_reaction
+    ADD CONSTRAINT note_reaction_id_note_fkey FOREIGN KEY (id_note) REFERENCES public.note(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.note_reaction
+    ADD CONSTRAINT note_reaction_id_user_fkey FOREIGN KEY (id_user) REFERENCES public.cnm_user(id) ON DELETE CASCADE;
@@ -0,0 +1,63 @@
+--
+-- Files attached by a band to one of its notes or contacts, or to an org.
+-- An attachment whose target is deleted is detached, and its file is
+-- removed from the storage by the periodic purge.
+--
+
+CREATE TABLE public.attachment (
+    id integer NOT NULL,
+    id_band integer NOT NULL,
+    id_uploader integer,
+    id_note integer,
+    id_contact integer,
+    id_org integer,
+    filename character varying(256) NOT NULL,
+    content_type character varying(128) NOT NULL,
+    size bigint NOT NULL,
+    storage_key character varying(64) NOT NULL,
+    creation_stamp timestamp without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT attachment_single_target CHECK (num_nonnulls(id_note, id_contact, id_org) <= 1)
+);
+
+ALTER TABLE public.attachment OWNER TO cnm;
+
+CREATE SEQUENCE public.attachment_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+ALTER TABLE public.attachment_id_seq OWNER TO cnm;
+
+ALTER SEQUENCE public.attachment_id_seq OWNED BY public.attachment.id;
+
+ALTER TABLE ONLY public.attachment ALTER COLUMN id SET DEFAULT nextval('public.attachment_id_seq'::regclass);
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_storage_key_key UNIQUE (storage_key);
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_id_band_fkey FOREIGN KEY (id_band) REFERENCES public.band(id) ON DELETE CASCADE;
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_id_uploader_fkey FOREIGN KEY (id_uploader) REFERENCES public.cnm_user(id) ON DELETE SET NULL;
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_id_note_fkey FOREIGN KEY (id_note) REFERENCES public.note(id) ON DELETE SET NULL;
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_id_contact_fkey FOREIGN KEY (id_contact) REFERENCES public.contact(id) ON DELETE SET NULL;
+
+ALTER TABLE ONLY public.attachment
+    ADD CONSTRAINT attachment_id_org_fkey FOREIGN KEY (id_org) REFERENCES public.org(id) ON DELETE SET NULL;
+
+CREATE INDEX attachment_id_note_idx ON public.attachment (id_note);
+
+CREATE INDEX attachment_id_contact_idx ON public.attachment (id_contact);
+
+CREATE INDEX attachment_id_band_id_org_idx ON public.attachment (id_band, id_org);
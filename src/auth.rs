@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use chrono::{Duration, Utc};
+use deadpool_postgres::Pool;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use warp::{
@@ -9,7 +10,17 @@ use warp::{
     Filter, Rejection,
 };
 
-use crate::{errors::Error, models::band::BandInterface};
+use crate::{
+    config::Config,
+    db_error_to_warp,
+    errors::Error,
+    models::{
+        api_token::{ApiToken, Scope, API_TOKEN_PREFIX},
+        band::{Band, BandInterface, BandState},
+        role::{Permission, Role, RoleInterface},
+        user::User,
+    },
+};
 const BEARER: &str = "Bearer ";
 const JWT_SECRET: &[u8] = b"kahloriz";
 
@@ -19,6 +30,105 @@ pub struct Claims {
     pub bands: Vec<BandInterface>,
     pub id_user: i32,
     pub exp: i64,
+    /// Only set when authenticated through a personal API token.
+    #[serde(default)]
+    pub scopes: Option<Vec<Scope>>,
+    #[serde(default)]
+    pub band_restriction: Option<i32>,
+}
+
+impl Claims {
+    pub fn is_session(&self) -> bool {
+        self.scopes.is_none()
+    }
+
+    pub fn allows(&self, scope: Scope, id_band: Option<i32>) -> bool {
+        let band_ok = match (self.band_restriction, id_band) {
+            (Some(restricted), Some(id_band)) => restricted == id_band,
+            _ => true,
+        };
+        let scope_ok = match &self.scopes {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        };
+        band_ok && scope_ok
+    }
+}
+
+pub fn require_scope(
+    claims: &Claims,
+    scope: Scope,
+    id_band: Option<i32>,
+) -> std::result::Result<(), Rejection> {
+    if claims.allows(scope, id_band) {
+        Ok(())
+    } else {
+        Err(warp::reject::custom(Error::Unauthorized))
+    }
+}
+
+/// Account management is reserved to sessions opened with a password.
+pub fn require_session(claims: &Claims) -> std::result::Result<(), Rejection> {
+    if claims.is_session() {
+        Ok(())
+    } else {
+        Err(warp::reject::custom(Error::Unauthorized))
+    }
+}
+
+/// Moderation and data subject requests are reserved to platform admins,
+/// from a password session.
+pub async fn require_platform_admin(
+    pool: Pool,
+    claims: &Claims,
+) -> std::result::Result<(), Rejection> {
+    require_session(claims)?;
+    if User::new(pool)
+        .is_platform_admin(claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        Ok(())
+    } else {
+        Err(warp::reject::custom(Error::Unauthorized))
+    }
+}
+
+/// Checks both the API token scope and the member's band role, returns the
+/// role so handlers can compare it with the one they act upon. Archived
+/// bands only accept reads and owner actions.
+pub async fn require_permission(
+    pool: Pool,
+    claims: &Claims,
+    id_band: i32,
+    permission: Permission,
+) -> std::result::Result<RoleInterface, Rejection> {
+    require_scope(claims, permission.scope(), Some(id_band))?;
+    let role = match Role::new(pool.clone())
+        .of_member(claims.id_user, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        Some(role) if role.has(permission) => role,
+        _ => return Err(warp::reject::custom(Error::Unauthorized)),
+    };
+    if !permission.allowed_when_archived() {
+        require_active_band(pool, id_band).await?;
+    }
+    Ok(role)
+}
+
+/// Archived bands are read-only.
+pub async fn require_active_band(pool: Pool, id_band: i32) -> std::result::Result<(), Rejection> {
+    if Band::new(pool)
+        .state(id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        == Some(BandState::Archived)
+    {
+        return Err(warp::reject::custom(Error::Archived));
+    }
+    Ok(())
 }
 
 pub fn create_jwt(id_user: i32, bands: Vec<BandInterface>) -> Result<String> {
@@ -31,37 +141,72 @@ pub fn create_jwt(id_user: i32, bands: Vec<BandInterface>) -> Result<String> {
         bands,
         id_user,
         exp,
+        scopes: None,
+        band_restriction: None,
     };
 
     let header = Header::new(Algorithm::HS512);
     Ok(encode(&header, &c, &EncodingKey::from_secret(JWT_SECRET))?)
 }
 
+async fn api_token_claims(token: &str, pool: Pool) -> Result<Claims> {
+    let api_token = ApiToken::new(pool.clone())
+        .authenticate(token)
+        .await?
+        .ok_or_else(|| anyhow!("Unknown API token"))?;
+    let bands = User::new(pool)
+        .get_bands(api_token.id_user, false)
+        .await?
+        .into_iter()
+        .filter(|b| api_token.id_band.is_none() || api_token.id_band == Some(b.id))
+        .collect();
+
+    Ok(Claims {
+        sub: api_token.id_user.to_string(),
+        bands,
+        id_user: api_token.id_user,
+        exp: api_token
+            .expiry_stamp
+            .map(|e| e.timestamp())
+            .unwrap_or(i64::MAX),
+        scopes: Some(api_token.scopes),
+        band_restriction: api_token.id_band,
+    })
+}
+
 pub async fn extract_jwt(
     headers: HeaderMap<HeaderValue>,
+    pool: Pool,
 ) -> std::result::Result<Claims, Rejection> {
     let h = match headers.get(AUTHORIZATION) {
         Some(v) => v,
         None => return Err(warp::reject::custom(Error::NoAuthHeader)),
     };
-    fn inter(h: &HeaderValue) -> Result<Claims> {
-        let auth = std::str::from_utf8(h.as_bytes())?;
-
-        if !auth.starts_with(BEARER) {
-            Err(anyhow!("Wrong auth header"))
-        } else {
-            Ok(decode::<Claims>(
-                auth.trim_start_matches(BEARER),
-                &DecodingKey::from_secret(JWT_SECRET),
-                &Validation::new(Algorithm::HS512),
-            )?
-            .claims)
-        }
+    let auth = std::str::from_utf8(h.as_bytes())
+        .map_err(|_| warp::reject::custom(Error::WrongAuthHeader))?;
+
+    if !auth.starts_with(BEARER) {
+        return Err(warp::reject::custom(Error::WrongAuthHeader));
     }
 
-    inter(h).map_err(|_| warp::reject::custom(Error::WrongAuthHeader))
+    let token = auth.trim_start_matches(BEARER);
+    if token.starts_with(API_TOKEN_PREFIX) {
+        api_token_claims(token, pool)
+            .await
+            .map_err(|_| warp::reject::custom(Error::WrongAuthHeader))
+    } else {
+        decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(JWT_SECRET),
+            &Validation::new(Algorithm::HS512),
+        )
+        .map(|data| data.claims)
+        .map_err(|_| warp::reject::custom(Error::WrongAuthHeader))
+    }
 }
 
-pub fn with_jwt() -> impl Filter<Extract = (Claims,), Error = Rejection> + Clone {
-    headers_cloned().and_then(extract_jwt)
+pub fn with_jwt(config: &Config) -> impl Filter<Extract = (Claims,), Error = Rejection> + Clone {
+    headers_cloned()
+        .and(config.with_pool())
+        .and_then(extract_jwt)
 }
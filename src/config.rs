@@ -1,4 +1,4 @@
-use std::{env, fs};
+use std::{convert::Infallible, env, fs};
 
 use anyhow::Result;
 use deadpool_postgres::{ManagerConfig, Pool, RecyclingMethod, Runtime};
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use tokio_postgres::NoTls;
 use warp::{Filter, Rejection};
 
-use crate::errors::Error;
+use crate::{errors::Error, password::PasswordPolicy};
 
 const DEFAULT_CONF_FILE: &str = "/etc/cnm/cnm.json";
 const ENV_CONF_KEY: &str = "CNM_CONFIG";
@@ -74,6 +74,28 @@ pub struct Mail {
     admin_mail: String,
     #[serde(rename = "forgotPasswordBaseUrl")]
     forgot_password_base_url: String,
+    #[serde(rename = "emailChangeMail")]
+    email_change_mail: String,
+    #[serde(rename = "emailChangeNoticeMail")]
+    email_change_notice_mail: String,
+    #[serde(rename = "emailChangeBaseUrl")]
+    email_change_base_url: String,
+    #[serde(rename = "invitationMail")]
+    invitation_mail: String,
+    #[serde(rename = "invitationBaseUrl")]
+    invitation_base_url: String,
+    #[serde(rename = "mentionMail", default = "default_mention_mail")]
+    mention_mail: String,
+    #[serde(rename = "noteBaseUrl", default = "default_note_base_url")]
+    note_base_url: String,
+}
+
+fn default_mention_mail() -> String {
+    "./etc/cnm/mentionmail.html".to_string()
+}
+
+fn default_note_base_url() -> String {
+    "http://localhost:3000/notes".to_string()
 }
 
 impl Mail {
@@ -104,15 +126,277 @@ impl Mail {
         self.admin_mail.clone()
     }
 
+    pub fn email_change_mail(&self) -> String {
+        self.email_change_mail.clone()
+    }
+
+    pub fn email_change_notice_mail(&self) -> String {
+        self.email_change_notice_mail.clone()
+    }
+
+    pub fn email_change_base_url(&self) -> String {
+        self.email_change_base_url.clone()
+    }
+
+    pub fn invitation_mail(&self) -> String {
+        self.invitation_mail.clone()
+    }
+
+    pub fn invitation_base_url(&self) -> String {
+        self.invitation_base_url.clone()
+    }
+
+    pub fn mention_mail(&self) -> String {
+        self.mention_mail.clone()
+    }
+
+    pub fn note_base_url(&self) -> String {
+        self.note_base_url.clone()
+    }
+
     pub fn address(&self) -> String {
         self.smtp_user.clone()
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Oidc {
+    issuer: String,
+    #[serde(rename = "clientId")]
+    client_id: String,
+    #[serde(rename = "clientSecret")]
+    client_secret: String,
+    #[serde(rename = "redirectUrl")]
+    redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    scopes: Vec<String>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
+}
+
+impl Oidc {
+    pub fn issuer(&self) -> String {
+        self.issuer.clone()
+    }
+
+    pub fn client_id(&self) -> String {
+        self.client_id.clone()
+    }
+
+    pub fn client_secret(&self) -> String {
+        self.client_secret.clone()
+    }
+
+    pub fn redirect_url(&self) -> String {
+        self.redirect_url.clone()
+    }
+
+    pub fn scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tokens {
+    #[serde(rename = "verifyTtlMinutes")]
+    verify_ttl_minutes: i32,
+    #[serde(rename = "resetTtlMinutes")]
+    reset_ttl_minutes: i32,
+    #[serde(rename = "emailChangeTtlMinutes")]
+    email_change_ttl_minutes: i32,
+    #[serde(rename = "invitationTtlMinutes")]
+    invitation_ttl_minutes: i32,
+}
+
+impl Default for Tokens {
+    fn default() -> Self {
+        Tokens {
+            verify_ttl_minutes: 2880,
+            reset_ttl_minutes: 60,
+            email_change_ttl_minutes: 1440,
+            invitation_ttl_minutes: 10080,
+        }
+    }
+}
+
+impl Tokens {
+    pub fn verify_ttl_minutes(&self) -> i32 {
+        self.verify_ttl_minutes
+    }
+
+    pub fn reset_ttl_minutes(&self) -> i32 {
+        self.reset_ttl_minutes
+    }
+
+    pub fn email_change_ttl_minutes(&self) -> i32 {
+        self.email_change_ttl_minutes
+    }
+
+    pub fn invitation_ttl_minutes(&self) -> i32 {
+        self.invitation_ttl_minutes
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bands {
+    #[serde(rename = "deletionGraceDays")]
+    deletion_grace_days: i32,
+    #[serde(rename = "purgeIntervalMinutes")]
+    purge_interval_minutes: u64,
+}
+
+impl Default for Bands {
+    fn default() -> Self {
+        Bands {
+            deletion_grace_days: 30,
+            purge_interval_minutes: 60,
+        }
+    }
+}
+
+impl Bands {
+    pub fn deletion_grace_days(&self) -> i32 {
+        self.deletion_grace_days
+    }
+
+    pub fn purge_interval_minutes(&self) -> u64 {
+        self.purge_interval_minutes
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Contacts {
+    #[serde(rename = "retentionDays")]
+    retention_days: i32,
+    #[serde(rename = "retentionIntervalMinutes")]
+    retention_interval_minutes: u64,
+}
+
+impl Default for Contacts {
+    fn default() -> Self {
+        Contacts {
+            retention_days: 730,
+            retention_interval_minutes: 1440,
+        }
+    }
+}
+
+impl Contacts {
+    pub fn retention_days(&self) -> i32 {
+        self.retention_days
+    }
+
+    pub fn retention_interval_minutes(&self) -> u64 {
+        self.retention_interval_minutes
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageBackend {
+    #[serde(rename = "local")]
+    Local,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Storage {
+    backend: StorageBackend,
+    path: String,
+    #[serde(rename = "maxUploadBytes")]
+    max_upload_bytes: u64,
+    /// Content types accepted for attachments.
+    #[serde(rename = "allowedContentTypes")]
+    allowed_content_types: Vec<String>,
+    /// Program and arguments reading an attachment on their standard input,
+    /// exiting with 0 when it is clean and 1 when it is infected.
+    #[serde(rename = "scanCommand")]
+    scan_command: Option<Vec<String>>,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Storage {
+            backend: StorageBackend::Local,
+            path: "./var/cnm/files".to_string(),
+            max_upload_bytes: 10 * 1024 * 1024,
+            allowed_content_types: [
+                "application/pdf",
+                "image/jpeg",
+                "image/png",
+                "text/plain",
+                "text/csv",
+                "application/msword",
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                "application/vnd.ms-excel",
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "application/vnd.oasis.opendocument.text",
+                "application/vnd.oasis.opendocument.spreadsheet",
+            ]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+            scan_command: None,
+        }
+    }
+}
+
+impl Storage {
+    pub fn backend(&self) -> StorageBackend {
+        self.backend
+    }
+
+    pub fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    pub fn max_upload_bytes(&self) -> u64 {
+        self.max_upload_bytes
+    }
+
+    /// Whether an attachment of this content type, parameters aside, is
+    /// accepted.
+    pub fn accepts(&self, content_type: &str) -> bool {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        self.allowed_content_types
+            .iter()
+            .any(|t| t.to_lowercase() == essence)
+    }
+
+    pub fn scan_command(&self) -> Option<Vec<String>> {
+        self.scan_command.clone()
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     database: Database,
     mail: Mail,
+    #[serde(default)]
+    password: PasswordPolicy,
+    #[serde(default)]
+    tokens: Tokens,
+    #[serde(default)]
+    oidc: Option<Oidc>,
+    #[serde(default)]
+    bands: Bands,
+    #[serde(default)]
+    contacts: Contacts,
+    #[serde(default)]
+    storage: Storage,
     #[serde(skip)]
     pool: Option<Pool>,
 }
@@ -125,6 +409,14 @@ async fn check_pool(pool: Option<Pool>) -> std::result::Result<Pool, Rejection>
     }
 }
 
+async fn check_oidc(oidc: Option<Oidc>) -> std::result::Result<Oidc, Rejection> {
+    if let Some(o) = oidc {
+        Ok(o)
+    } else {
+        Err(warp::reject::custom(Error::NotFound))
+    }
+}
+
 impl Config {
     pub fn retrieve(build_pool: bool) -> Result<Self> {
         let path = if let Some((_, v)) = env::vars().find(|(key, _)| key == ENV_CONF_KEY) {
@@ -134,6 +426,7 @@ impl Config {
         };
         let fcontents = fs::read_to_string(path)?;
         let mut config: Config = serde_json::from_str(&fcontents)?;
+        config.password.load_common_passwords()?;
 
         if build_pool {
             let mut dpconf = deadpool_postgres::Config::new();
@@ -185,6 +478,62 @@ impl Config {
         self.mail.forgot_password_mail()
     }
 
+    pub fn email_change_mail(&self) -> String {
+        self.mail.email_change_mail()
+    }
+
+    pub fn email_change_notice_mail(&self) -> String {
+        self.mail.email_change_notice_mail()
+    }
+
+    pub fn email_change_base_url(&self) -> String {
+        self.mail.email_change_base_url()
+    }
+
+    pub fn invitation_mail(&self) -> String {
+        self.mail.invitation_mail()
+    }
+
+    pub fn invitation_base_url(&self) -> String {
+        self.mail.invitation_base_url()
+    }
+
+    pub fn mention_mail(&self) -> String {
+        self.mail.mention_mail()
+    }
+
+    pub fn note_base_url(&self) -> String {
+        self.mail.note_base_url()
+    }
+
+    pub fn password_policy(&self) -> &PasswordPolicy {
+        &self.password
+    }
+
+    pub fn tokens(&self) -> &Tokens {
+        &self.tokens
+    }
+
+    pub fn oidc(&self) -> Option<&Oidc> {
+        self.oidc.as_ref()
+    }
+
+    pub fn bands(&self) -> &Bands {
+        &self.bands
+    }
+
+    pub fn contacts(&self) -> &Contacts {
+        &self.contacts
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn pool(&self) -> Option<Pool> {
+        self.pool.clone()
+    }
+
     fn set_pool(&mut self, pool: Pool) {
         self.pool = Some(pool);
     }
@@ -193,4 +542,31 @@ impl Config {
         let p = self.pool.clone();
         warp::any().map(move || p.clone()).and_then(check_pool)
     }
+
+    pub fn with_password_policy(
+        &self,
+    ) -> impl Filter<Extract = (PasswordPolicy,), Error = Infallible> + Clone {
+        let p = self.password.clone();
+        warp::any().map(move || p.clone())
+    }
+
+    pub fn with_tokens(&self) -> impl Filter<Extract = (Tokens,), Error = Infallible> + Clone {
+        let t = self.tokens.clone();
+        warp::any().map(move || t.clone())
+    }
+
+    pub fn with_bands(&self) -> impl Filter<Extract = (Bands,), Error = Infallible> + Clone {
+        let b = self.bands.clone();
+        warp::any().map(move || b.clone())
+    }
+
+    pub fn with_storage(&self) -> impl Filter<Extract = (Storage,), Error = Infallible> + Clone {
+        let s = self.storage.clone();
+        warp::any().map(move || s.clone())
+    }
+
+    pub fn with_oidc(&self) -> impl Filter<Extract = (Oidc,), Error = Rejection> + Clone {
+        let o = self.oidc.clone();
+        warp::any().map(move || o.clone()).and_then(check_oidc)
+    }
 }
@@ -22,6 +22,12 @@ pub enum Error {
     Auth,
     #[error("Not found")]
     NotFound,
+    #[error("Already exists")]
+    Conflict,
+    #[error("Validation error")]
+    Validation(Vec<String>),
+    #[error("Band is archived")]
+    Archived,
     #[error("misc")]
     Misc,
 }
@@ -32,8 +38,11 @@ impl Reject for Error {}
 struct ErrorMessage {
     message: String,
     code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Vec<String>>,
 }
 pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+    let mut details = None;
     let (code, message) = if err.is_not_found() {
         (StatusCode::NOT_FOUND, "Not Found".to_string())
     } else if let Some(e) = err.find::<Error>() {
@@ -45,6 +54,11 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
             Error::Unauthorized | Error::Auth => (StatusCode::UNAUTHORIZED, e.to_string()),
             Error::Database(m) => (StatusCode::EXPECTATION_FAILED, m.to_string()),
             Error::NotFound => (StatusCode::NOT_FOUND, e.to_string()),
+            Error::Conflict | Error::Archived => (StatusCode::CONFLICT, e.to_string()),
+            Error::Validation(failed) => {
+                details = Some(failed.clone());
+                (StatusCode::BAD_REQUEST, e.to_string())
+            }
             _ => (StatusCode::BAD_REQUEST, e.to_string()),
         }
     } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
@@ -62,6 +76,7 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     let json = warp::reply::json(&ErrorMessage {
         code: code.to_string(),
         message: message.clone(),
+        details,
     });
 
     eprintln!("Error : {}", message);
@@ -1,19 +1,36 @@
 use std::fmt::Display;
 
 use errors::Error;
+use tokio_postgres::error::SqlState;
 
 pub mod auth;
 pub mod config;
 pub mod errors;
 pub mod mailer;
+pub mod markdown;
 pub mod models;
+pub mod oidc;
 pub mod paginator;
+pub mod password;
 pub mod router;
+pub mod storage;
+pub mod vcard;
 
 pub fn db_error_to_warp(e: anyhow::Error) -> crate::Error {
     Error::Database(e.to_string())
 }
 
+/// Like `db_error_to_warp`, but a unique violation is a conflict.
+pub fn unique_error_to_warp(e: anyhow::Error) -> crate::Error {
+    match e
+        .downcast_ref::<tokio_postgres::Error>()
+        .and_then(|e| e.code())
+    {
+        Some(&SqlState::UNIQUE_VIOLATION) => Error::Conflict,
+        _ => db_error_to_warp(e),
+    }
+}
+
 pub fn etointlog(e: impl Display) -> crate::Error {
     eprintln!("{}", e);
     Error::Internal
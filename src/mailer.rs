@@ -1,6 +1,9 @@
 use std::fs;
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    models::band_profile::{BandProfile, BandTemplateContext},
+};
 use anyhow::{anyhow, Result};
 use deadpool_postgres::Pool;
 use lettre::{
@@ -16,20 +19,107 @@ struct VerifContext {
     pseudo: String,
     mail: String,
 }
+
+#[derive(Serialize, Debug)]
+struct EmailChangeContext {
+    link: String,
+    pseudo: String,
+    mail: String,
+    new_email: String,
+}
+
+#[derive(Serialize, Debug)]
+struct InvitationContext {
+    link: String,
+    pseudo: String,
+    mail: String,
+    band: String,
+    role: String,
+    profile: BandTemplateContext,
+}
+
+#[derive(Serialize, Debug)]
+struct MentionContext {
+    link: String,
+    pseudo: String,
+    mail: String,
+    author: String,
+    band: String,
+    org: String,
+    note: String,
+}
+
 #[derive(Debug)]
 pub enum Mailer {
-    Verify,
-    ForgotPassword,
+    Verify(String),
+    ForgotPassword(String),
+    EmailChangeConfirm(String),
+    EmailChangeNotice,
+    /// Invitation id and clear token, sent on behalf of the inviting user.
+    BandInvitation(i32, String),
+    /// Note the user was mentioned in.
+    Mention(i32),
+}
+
+fn send_template<C: Serialize>(
+    config: &Config,
+    template: String,
+    subject: &str,
+    to: &str,
+    context: &C,
+) -> Result<()> {
+    let rawcontents = fs::read_to_string(template)?;
+    let mut tt = TinyTemplate::new();
+
+    tt.add_template("mail", &rawcontents)?;
+    let mail_contents = tt.render("mail", context)?;
+    let email = MessageBuilder::new()
+        .from(Mailbox::new(
+            Some("Noreply Tourboy".to_string()),
+            config.from_addr().parse::<Address>()?,
+        ))
+        .to(Mailbox::new(None, to.parse::<Address>()?))
+        .subject(subject)
+        .multipart(MultiPart::alternative_plain_html(
+            String::from(
+                "
+                    Vous avez besoin d'un affichage HTML
+                    pour visionner ce message correctement.
+                    Veuillez contacter dorian.vuolo@gmail.com
+                    pour une intervention manuelle.
+                ",
+            ),
+            mail_contents,
+        ))?;
+
+    config.mailer()?.send(&email)?;
+
+    Ok(())
 }
 
 impl Mailer {
     pub async fn send_email(&self, user_id: i32, pool: Pool) -> Result<()> {
+        match self {
+            Self::Verify(token) | Self::ForgotPassword(token) => {
+                self.send_verif_email(user_id, token, pool).await
+            }
+            Self::EmailChangeConfirm(token) => self.send_email_change(user_id, token, pool).await,
+            Self::EmailChangeNotice => self.send_email_change(user_id, "", pool).await,
+            Self::BandInvitation(id_invitation, token) => {
+                self.send_invitation(user_id, *id_invitation, token, pool)
+                    .await
+            }
+            Self::Mention(id_note) => self.send_mention(user_id, *id_note, pool).await,
+        }
+    }
+
+    async fn send_verif_email(&self, user_id: i32, token: &str, pool: Pool) -> Result<()> {
         let config = Config::retrieve(false)?;
         let client = pool.get().await?;
         let stmt = client
             .prepare(
                 "
-            SELECT verify_chain, pseudo, email FROM cnm_user
+            SELECT pseudo, email FROM cnm_user
             WHERE id = $1
         ",
             )
@@ -39,59 +129,195 @@ impl Mailer {
             .await?
             .iter()
             .map(|row| {
-                let v: String = row.get(0);
-                let p: String = row.get(1);
-                let e: String = row.get(2);
-                (v, p, e)
+                let p: String = row.get(0);
+                let e: String = row.get(1);
+                (p, e)
             })
-            .collect::<Vec<(String, String, String)>>();
+            .collect::<Vec<(String, String)>>();
 
         if rows.is_empty() {
             Err(anyhow!("No use found"))
         } else {
-            let rawcontents = fs::read_to_string(match self {
-                Self::ForgotPassword => config.forgot_password_mail(),
-                Self::Verify => config.verif_mail(),
-            })?;
             let context = VerifContext {
                 link: format!(
                     "{}/{}/{}",
                     match self {
-                        Self::Verify => config.verif_base_url(),
-                        Self::ForgotPassword => config.forgot_password_base_url(),
+                        Self::ForgotPassword(_) => config.forgot_password_base_url(),
+                        _ => config.verif_base_url(),
                     },
                     user_id,
-                    rows[0].0,
+                    token,
                 ),
-                pseudo: rows[0].1.clone(),
+                pseudo: rows[0].0.clone(),
                 mail: config.admin_mail(),
             };
-            let mut tt = TinyTemplate::new();
-
-            tt.add_template("verifmail", &rawcontents)?;
-            let mail_contents = tt.render("verifmail", &context)?;
-            let email = MessageBuilder::new()
-                .from(Mailbox::new(
-                    Some("Noreply Tourboy".to_string()),
-                    config.from_addr().parse::<Address>()?,
-                ))
-                .to(Mailbox::new(None, rows[0].2.parse::<Address>()?))
-                .subject("Vérifiez votre email sur Tourboy")
-                .multipart(MultiPart::alternative_plain_html(
-                    String::from(
-                        "
-                            Vous avez besoin d'un affichage HTML
-                            pour visionner ce message correctement.
-                            Veuillez contacter dorian.vuolo@gmail.com
-                            pour une intervention manuelle.
-                        ",
-                    ),
-                    mail_contents,
-                ))?;
-
-            config.mailer()?.send(&email)?;
-
-            Ok(())
+
+            send_template(
+                &config,
+                match self {
+                    Self::ForgotPassword(_) => config.forgot_password_mail(),
+                    _ => config.verif_mail(),
+                },
+                "Vérifiez votre email sur Tourboy",
+                &rows[0].1,
+                &context,
+            )
+        }
+    }
+
+    async fn send_email_change(&self, user_id: i32, token: &str, pool: Pool) -> Result<()> {
+        let config = Config::retrieve(false)?;
+        let client = pool.get().await?;
+        let stmt = client
+            .prepare(
+                "
+            SELECT cu.pseudo, cu.email, ec.new_email
+            FROM email_change ec
+            JOIN cnm_user cu ON cu.id = ec.id_user
+            WHERE ec.id_user = $1 AND ec.confirmed_stamp IS NULL
+            ORDER BY ec.creation_stamp DESC
+            LIMIT 1
+        ",
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&user_id]).await?;
+
+        if rows.is_empty() {
+            Err(anyhow!("No pending email change found"))
+        } else {
+            let old_email: String = rows[0].get(1);
+            let new_email: String = rows[0].get(2);
+            let context = EmailChangeContext {
+                link: format!("{}/{}/{}", config.email_change_base_url(), user_id, token),
+                pseudo: rows[0].get(0),
+                mail: config.admin_mail(),
+                new_email: new_email.clone(),
+            };
+
+            match self {
+                Self::EmailChangeNotice => send_template(
+                    &config,
+                    config.email_change_notice_mail(),
+                    "Changement d'adresse email sur Tourboy",
+                    &old_email,
+                    &context,
+                ),
+                _ => send_template(
+                    &config,
+                    config.email_change_mail(),
+                    "Confirmez votre nouvelle adresse email sur Tourboy",
+                    &new_email,
+                    &context,
+                ),
+            }
+        }
+    }
+
+    async fn send_invitation(
+        &self,
+        user_id: i32,
+        id_invitation: i32,
+        token: &str,
+        pool: Pool,
+    ) -> Result<()> {
+        let config = Config::retrieve(false)?;
+        let client = pool.get().await?;
+        let stmt = client
+            .prepare(
+                "
+            SELECT cu.pseudo, bi.email, b.name, br.name, bi.id_band
+            FROM band_invitation bi
+            JOIN band b ON b.id = bi.id_band
+            JOIN band_role br ON br.id = bi.id_role
+            JOIN cnm_user cu ON cu.id = $1
+            WHERE bi.id = $2
+        ",
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&user_id, &id_invitation]).await?;
+
+        if rows.is_empty() {
+            Err(anyhow!("No invitation found"))
+        } else {
+            let email: String = rows[0].get(1);
+            let band: String = rows[0].get(2);
+            let role: String = rows[0].get(3);
+            let profile = BandProfile::new(pool.clone())
+                .template_context(rows[0].get(4))
+                .await?;
+            let context = InvitationContext {
+                link: format!(
+                    "{}/{}/{}",
+                    config.invitation_base_url(),
+                    id_invitation,
+                    token
+                ),
+                pseudo: rows[0].get(0),
+                mail: config.admin_mail(),
+                band: band.clone(),
+                role: match role.as_ref() {
+                    "owner" => "propriétaire".to_string(),
+                    "admin" => "administrateur".to_string(),
+                    "booker" => "booker".to_string(),
+                    "viewer" => "lecteur".to_string(),
+                    _ => role,
+                },
+                profile,
+            };
+
+            send_template(
+                &config,
+                config.invitation_mail(),
+                &format!("Invitation à rejoindre {} sur Tourboy", band),
+                &email,
+                &context,
+            )
+        }
+    }
+
+    async fn send_mention(&self, user_id: i32, id_note: i32, pool: Pool) -> Result<()> {
+        let config = Config::retrieve(false)?;
+        let client = pool.get().await?;
+        let stmt = client
+            .prepare(
+                "
+            SELECT cu.pseudo, cu.email, au.pseudo, b.name, o.name, n.note, n.id_band, n.id_activity
+            FROM note n
+            JOIN cnm_user cu ON cu.id = $1
+            JOIN cnm_user au ON au.id = n.id_user
+            JOIN band b ON b.id = n.id_band
+            JOIN activity a ON a.id = n.id_activity
+            JOIN org o ON o.id = a.id_org
+            WHERE n.id = $2
+        ",
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&user_id, &id_note]).await?;
+
+        if rows.is_empty() {
+            Err(anyhow!("No note found"))
+        } else {
+            let email: String = rows[0].get(1);
+            let author: String = rows[0].get(2);
+            let id_band: i32 = rows[0].get(6);
+            let id_activity: i32 = rows[0].get(7);
+            let context = MentionContext {
+                link: format!("{}/{}/{}", config.note_base_url(), id_band, id_activity),
+                pseudo: rows[0].get(0),
+                mail: config.admin_mail(),
+                author: author.clone(),
+                band: rows[0].get(3),
+                org: rows[0].get(4),
+                note: rows[0].get(5),
+            };
+
+            send_template(
+                &config,
+                config.mention_mail(),
+                &format!("{} vous a mentionné sur Tourboy", author),
+                &email,
+                &context,
+            )
         }
     }
 }
@@ -1,20 +1,115 @@
+#![recursion_limit = "256"]
+
+use std::time::Duration;
+
 use cnm::{
     config::Config,
     errors::handle_rejection,
-    router::{band::band_routes, note::note_routes, org::org_routes, user::user_routes},
+    models::{attachment::Attachment, band::Band, contact::Contact},
+    router::{
+        attachment::attachment_routes, band::band_routes, dashboard::dashboard_routes,
+        interaction::interaction_routes, note::note_routes, notification::notification_routes,
+        org::org_routes, privacy::privacy_routes, user::user_routes,
+    },
+    storage,
 };
 use warp::Filter;
 
+/// Periodically purges the bands whose deletion grace period is over, and
+/// the attachments whose target was deleted.
+fn spawn_band_purge(config: Config) {
+    tokio::spawn(async move {
+        let bands = config.bands().clone();
+        let storage = storage::open(config.storage());
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(bands.purge_interval_minutes() * 60));
+        loop {
+            interval.tick().await;
+            if let Some(pool) = config.pool() {
+                match Band::new(pool.clone())
+                    .purge_deleted(bands.deletion_grace_days())
+                    .await
+                {
+                    Ok((0, _)) => {}
+                    Ok((count, keys)) => {
+                        for key in keys {
+                            if let Err(e) = storage.delete(&key).await {
+                                eprintln!("Unable to remove purged file {} {}", key, e);
+                            }
+                        }
+                        eprintln!("Purged {} deleted band(s)", count);
+                    }
+                    Err(e) => eprintln!("Band purge problem {}", e),
+                }
+                match Attachment::new(pool).purge_detached().await {
+                    Ok(keys) => {
+                        for key in keys {
+                            if let Err(e) = storage.delete(&key).await {
+                                eprintln!("Unable to remove detached file {} {}", key, e);
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Attachment purge problem {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Periodically flags the contacts kept past the retention period, so that
+/// bands review or delete them.
+fn spawn_contact_retention(config: Config) {
+    tokio::spawn(async move {
+        let contacts = config.contacts().clone();
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            contacts.retention_interval_minutes() * 60,
+        ));
+        loop {
+            interval.tick().await;
+            if let Some(pool) = config.pool() {
+                match Contact::new(pool)
+                    .flag_stale(contacts.retention_days())
+                    .await
+                {
+                    Ok((0, 0)) => {}
+                    Ok((flagged, unflagged)) => eprintln!(
+                        "Flagged {} stale contact(s), unflagged {}",
+                        flagged, unflagged
+                    ),
+                    Err(e) => eprintln!("Contact retention problem {}", e),
+                }
+            }
+        }
+    });
+}
+
 #[tokio::main]
 async fn main() {
     let config = Config::retrieve(true).expect("Unable to retrieve configuration file");
+    spawn_band_purge(config.clone());
+    spawn_contact_retention(config.clone());
     let band_routes = warp::path("band").and(band_routes(config.clone()));
     let org_routes = warp::path("org").and(org_routes(config.clone()));
     let user_routes = warp::path("user").and(user_routes(config.clone()));
-    let note_routes = warp::path("note").and(note_routes(config));
+    let note_routes = warp::path("note").and(note_routes(config.clone()));
+    let dashboard_routes = warp::path("dashboard").and(dashboard_routes(config.clone()));
+    let interaction_routes = warp::path("interaction").and(interaction_routes(config.clone()));
+    let notification_routes = warp::path("notification").and(notification_routes(config.clone()));
+    let privacy_routes = warp::path("privacy").and(privacy_routes(config.clone()));
+    let attachment_routes = warp::path("attachment").and(attachment_routes(config));
     let cors = warp::cors().allow_any_origin();
     let api = warp::path("api")
-        .and(band_routes.or(org_routes).or(user_routes).or(note_routes))
+        .and(
+            band_routes
+                .or(org_routes)
+                .or(user_routes)
+                .or(note_routes)
+                .or(dashboard_routes)
+                .or(interaction_routes)
+                .or(notification_routes)
+                .or(privacy_routes)
+                .or(attachment_routes),
+        )
         .with(cors)
         .recover(handle_rejection);
     warp::serve(api).run(([127, 0, 0, 1], 3030)).await;
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+
+enum Segment<'a> {
+    Text(&'a str),
+    Mention(&'a str),
+}
+
+fn is_pseudo_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Splits a text on its `@pseudo` mentions. An `@` following a letter or a
+/// digit is part of an email, not a mention.
+fn scan(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut start = 0;
+    let mut previous: Option<char> = None;
+    for (i, c) in text.char_indices() {
+        if i < start {
+            previous = Some(c);
+            continue;
+        }
+        if c == '@' && !previous.map(char::is_alphanumeric).unwrap_or(false) {
+            let rest = &text[i + 1..];
+            let len = rest.find(|c| !is_pseudo_char(c)).unwrap_or(rest.len());
+            let pseudo = rest[..len].trim_end_matches(['.', '-']);
+            if !pseudo.is_empty() {
+                if start < i {
+                    segments.push(Segment::Text(&text[start..i]));
+                }
+                segments.push(Segment::Mention(pseudo));
+                start = i + 1 + pseudo.len();
+            }
+        }
+        previous = Some(c);
+    }
+    if start < text.len() {
+        segments.push(Segment::Text(&text[start..]));
+    }
+    segments
+}
+
+/// Markdown events with consecutive texts merged, so that mentions are not
+/// split across them, and whether each one is inside a code block.
+fn events(source: &str) -> Vec<(Event<'_>, bool)> {
+    let mut res: Vec<(Event, bool)> = vec![];
+    let mut in_code = false;
+    let parser = Parser::new_ext(
+        source,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS,
+    );
+    for event in parser {
+        match (&event, res.last_mut()) {
+            (Event::Start(Tag::CodeBlock(_)), _) => in_code = true,
+            (Event::End(Tag::CodeBlock(_)), _) => in_code = false,
+            (Event::Text(text), Some((Event::Text(previous), _))) => {
+                *previous = CowStr::from(format!("{}{}", previous, text));
+                continue;
+            }
+            _ => {}
+        }
+        res.push((event, in_code));
+    }
+    res
+}
+
+/// Pseudos mentioned in a note, lowercased, leaving out code.
+pub fn mentions(source: &str) -> Vec<String> {
+    let mut res: Vec<String> = vec![];
+    for (event, in_code) in events(source) {
+        if let (Event::Text(text), false) = (event, in_code) {
+            for segment in scan(&text) {
+                if let Segment::Mention(pseudo) = segment {
+                    let pseudo = pseudo.to_lowercase();
+                    if !res.contains(&pseudo) {
+                        res.push(pseudo);
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Marks the place of a mention in the rendered text until the HTML is
+/// sanitised, these private use characters are removed from the source.
+const MENTION_START: char = '\u{E000}';
+const MENTION_END: char = '\u{E001}';
+
+/// Renders a note to sanitised HTML. Mentions of `members`, keyed by
+/// lowercased pseudo, become `<span class="mention" data-user="id">`. They
+/// are put in once the HTML is sanitised, so that a note cannot forge them.
+pub fn render(source: &str, members: &HashMap<String, i32>) -> String {
+    let source = source.replace([MENTION_START, MENTION_END], "");
+    let mut spans: Vec<String> = vec![];
+    let mut res: Vec<Event> = vec![];
+    for (event, in_code) in events(&source) {
+        match (event, in_code) {
+            (Event::Text(text), false) => {
+                for segment in scan(&text) {
+                    res.push(Event::Text(CowStr::from(match segment {
+                        Segment::Text(t) => t.to_string(),
+                        Segment::Mention(pseudo) => match members.get(&pseudo.to_lowercase()) {
+                            Some(id) => {
+                                spans.push(format!(
+                                    "<span class=\"mention\" data-user=\"{}\">@{}</span>",
+                                    id, pseudo
+                                ));
+                                format!("{}{}{}", MENTION_START, spans.len() - 1, MENTION_END)
+                            }
+                            None => format!("@{}", pseudo),
+                        },
+                    })));
+                }
+            }
+            (event, _) => res.push(event),
+        }
+    }
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, res.into_iter());
+    let clean = Builder::default().clean(&unsafe_html).to_string();
+
+    let mut html = String::with_capacity(clean.len());
+    let mut rest = clean.as_str();
+    while let Some(start) = rest.find(MENTION_START) {
+        html.push_str(&rest[..start]);
+        rest = &rest[start + MENTION_START.len_utf8()..];
+        let end = rest.find(MENTION_END).unwrap_or(rest.len());
+        if let Some(span) = rest[..end].parse::<usize>().ok().and_then(|i| spans.get(i)) {
+            html.push_str(span);
+        }
+        rest = rest.get(end + MENTION_END.len_utf8()..).unwrap_or_default();
+    }
+    html.push_str(rest);
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_leave_out_emails_and_code() {
+        let source = "Hi @Jane and @bob.smith. Ask **@JANE** or write to jane@example.org\n\
+                      \n\
+                      `@inline` and\n\
+                      \n\
+                      ```\n\
+                      @block\n\
+                      ```\n";
+        assert_eq!(mentions(source), vec!["jane", "bob.smith"]);
+    }
+
+    #[test]
+    fn known_members_are_rendered() {
+        let members = HashMap::from([("jane".to_string(), 7)]);
+        let html = render("Thanks @Jane, @nobody", &members);
+        assert_eq!(
+            html,
+            "<p>Thanks <span class=\"mention\" data-user=\"7\">@Jane</span>, @nobody</p>\n"
+        );
+    }
+
+    #[test]
+    fn html_is_sanitised() {
+        let html = render(
+            "<script>alert(1)</script><img src=x onerror=alert(1)>\n\n\
+             [link](javascript:alert(1)) <span class=\"mention\" data-user=\"1\">@boss</span> \
+             \u{E000}0\u{E001} @jane",
+            &HashMap::from([("jane".to_string(), 7)]),
+        );
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(html.contains("<a rel=\"noopener noreferrer\">link</a>"));
+        // Only the mention of an actual member is kept.
+        assert!(!html.contains("data-user=\"1\""));
+        assert_eq!(html.matches("mention").count(), 1);
+        assert!(html.contains("<span class=\"mention\" data-user=\"7\">@jane</span>"));
+    }
+}
@@ -1,5 +1,22 @@
+pub mod api_token;
+pub mod assignment;
+pub mod attachment;
 pub mod band;
+pub mod band_file;
+pub mod band_profile;
+pub mod contact;
+pub mod dashboard;
+pub mod event;
 pub mod filter;
+pub mod identity;
+pub mod interaction;
+pub mod invitation;
+pub mod label;
 pub mod note;
+pub mod notification;
 pub mod org;
+pub mod privacy;
+pub mod role;
+pub mod saved_search;
+pub mod shared_contact;
 pub mod user;
@@ -0,0 +1,194 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+pub const API_TOKEN_PREFIX: &str = "cnm_";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "read_orgs")]
+    ReadOrgs,
+    #[serde(rename = "tag_orgs")]
+    TagOrgs,
+    #[serde(rename = "manage_contacts")]
+    ManageContacts,
+    #[serde(rename = "band_admin")]
+    BandAdmin,
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_ref() {
+            "read_orgs" => Ok(Self::ReadOrgs),
+            "tag_orgs" => Ok(Self::TagOrgs),
+            "manage_contacts" => Ok(Self::ManageContacts),
+            "band_admin" => Ok(Self::BandAdmin),
+            _ => Err("unknownScope".to_string()),
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Scope::ReadOrgs => "read_orgs",
+                Scope::TagOrgs => "tag_orgs",
+                Scope::ManageContacts => "manage_contacts",
+                Scope::BandAdmin => "band_admin",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiTokenInterface {
+    pub id: i32,
+    #[serde(rename = "idUser")]
+    pub id_user: i32,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(rename = "idBand")]
+    pub id_band: Option<i32>,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+    #[serde(rename = "expiryStamp")]
+    pub expiry_stamp: Option<NaiveDateTime>,
+    #[serde(rename = "lastUsedStamp")]
+    pub last_used_stamp: Option<NaiveDateTime>,
+}
+
+impl From<&Row> for ApiTokenInterface {
+    fn from(row: &Row) -> Self {
+        let scopes: Vec<String> = row.get(3);
+        ApiTokenInterface {
+            id: row.get(0),
+            id_user: row.get(1),
+            name: row.get(2),
+            scopes: scopes
+                .into_iter()
+                .filter_map(|s| Scope::try_from(s).ok())
+                .collect(),
+            id_band: row.get(4),
+            creation_stamp: row.get(5),
+            expiry_stamp: row.get(6),
+            last_used_stamp: row.get(7),
+        }
+    }
+}
+
+pub struct ApiToken(Pool);
+
+impl ApiToken {
+    pub fn new(pool: Pool) -> Self {
+        ApiToken(pool)
+    }
+
+    /// Creates a token and returns it in clear alongside its description.
+    /// The clear value is never stored and cannot be retrieved afterwards.
+    pub async fn create(
+        &self,
+        id_user: i32,
+        name: String,
+        scopes: Vec<Scope>,
+        id_band: Option<i32>,
+        expiry_stamp: Option<NaiveDateTime>,
+    ) -> Result<(ApiTokenInterface, String)> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT encode(gen_random_bytes(32), 'hex')")
+            .await?;
+        let random: String = client.query(&stmt, &[]).await?[0].get(0);
+        let token = format!("{}{}", API_TOKEN_PREFIX, random);
+        let scopes = scopes
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO api_token(id_user, name, token_hash, scopes, id_band, expiry_stamp)
+                VALUES ($1, $2, encode(digest($3::text, 'sha256'), 'hex'), $4, $5, $6)
+                RETURNING
+                    id, id_user, name, scopes, id_band,
+                    creation_stamp, expiry_stamp, last_used_stamp
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[&id_user, &name, &token, &scopes, &id_band, &expiry_stamp],
+            )
+            .await?;
+        Ok((ApiTokenInterface::from(&rows[0]), token))
+    }
+
+    pub async fn list(&self, id_user: i32) -> Result<Vec<ApiTokenInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    id, id_user, name, scopes, id_band,
+                    creation_stamp, expiry_stamp, last_used_stamp
+                FROM api_token
+                WHERE id_user = $1 AND revoked_stamp IS NULL
+                ORDER BY creation_stamp DESC
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user])
+            .await?
+            .iter()
+            .map(ApiTokenInterface::from)
+            .collect())
+    }
+
+    pub async fn revoke(&self, id_user: i32, id: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE api_token SET revoked_stamp = CURRENT_TIMESTAMP
+                WHERE id = $1 AND id_user = $2 AND revoked_stamp IS NULL
+                RETURNING id
+            ",
+            )
+            .await?;
+        Ok(!client.query(&stmt, &[&id, &id_user]).await?.is_empty())
+    }
+
+    /// Looks a clear token up, returning its description if it is neither
+    /// revoked nor expired.
+    pub async fn authenticate(&self, token: &str) -> Result<Option<ApiTokenInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE api_token SET last_used_stamp = CURRENT_TIMESTAMP
+                WHERE token_hash = encode(digest($1::text, 'sha256'), 'hex')
+                    AND revoked_stamp IS NULL
+                    AND (expiry_stamp IS NULL OR expiry_stamp > CURRENT_TIMESTAMP)
+                RETURNING
+                    id, id_user, name, scopes, id_band,
+                    creation_stamp, expiry_stamp, last_used_stamp
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&token])
+            .await?
+            .first()
+            .map(ApiTokenInterface::from))
+    }
+}
@@ -0,0 +1,334 @@
+use std::{collections::HashMap, fmt::Display};
+
+use anyhow::Result;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::models::filter::{gen_request_search, query_params, Filter};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Strategy {
+    #[serde(rename = "roundRobin")]
+    RoundRobin,
+    #[serde(rename = "region")]
+    Region,
+}
+
+impl From<String> for Strategy {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "region" => Self::Region,
+            _ => Self::RoundRobin,
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Strategy::RoundRobin => "roundRobin",
+                Strategy::Region => "region",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkloadInterface {
+    #[serde(rename = "idUser")]
+    pub id_user: i32,
+    pub pseudo: String,
+    pub total: i32,
+    pub todo: i32,
+    pub raise: i32,
+    pub success: i32,
+    pub failure: i32,
+    pub pending: i32,
+}
+
+impl From<&Row> for WorkloadInterface {
+    fn from(row: &Row) -> Self {
+        WorkloadInterface {
+            id_user: row.get(0),
+            pseudo: row.get(1),
+            total: row.get(2),
+            todo: row.get(3),
+            raise: row.get(4),
+            success: row.get(5),
+            failure: row.get(6),
+            pending: row.get(7),
+        }
+    }
+}
+
+/// An org waiting to be distributed, along with the postal code used to
+/// group it by region.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub id_org: i32,
+    pub postal_code: Option<String>,
+}
+
+/// French department of a postal code, overseas ones use three digits.
+fn region_of(postal_code: &Option<String>) -> String {
+    match postal_code.as_deref().map(str::trim) {
+        Some(p) if p.starts_with("97") || p.starts_with("98") => p.chars().take(3).collect(),
+        Some(p) => p.chars().take(2).collect(),
+        None => String::new(),
+    }
+}
+
+/// Splits the candidates between members, given as (id_user, current
+/// workload). Round robin starts with the least loaded members, region keeps
+/// every department with a single member, biggest departments first.
+pub fn plan(
+    strategy: Strategy,
+    mut members: Vec<(i32, i32)>,
+    candidates: Vec<Candidate>,
+) -> Vec<(i32, Vec<i32>)> {
+    members.sort_by_key(|(id, load)| (*load, *id));
+    let mut shares: Vec<(i32, Vec<i32>)> = members.iter().map(|(id, _)| (*id, vec![])).collect();
+    if shares.is_empty() {
+        return shares;
+    }
+    match strategy {
+        Strategy::RoundRobin => {
+            let count = shares.len();
+            for (i, candidate) in candidates.into_iter().enumerate() {
+                shares[i % count].1.push(candidate.id_org);
+            }
+        }
+        Strategy::Region => {
+            let mut regions: HashMap<String, Vec<i32>> = HashMap::new();
+            for candidate in candidates {
+                regions
+                    .entry(region_of(&candidate.postal_code))
+                    .or_default()
+                    .push(candidate.id_org);
+            }
+            let mut regions: Vec<(String, Vec<i32>)> = regions.into_iter().collect();
+            regions.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(&b.0)));
+            let mut loads: Vec<i32> = members.iter().map(|(_, load)| *load).collect();
+            for (_, orgs) in regions {
+                let lightest = (0..loads.len()).min_by_key(|i| loads[*i]).unwrap_or(0);
+                loads[lightest] += orgs.len() as i32;
+                shares[lightest].1.extend(orgs);
+            }
+        }
+    }
+    shares
+}
+
+pub struct Assignment(Pool);
+
+impl Assignment {
+    pub fn new(pool: Pool) -> Self {
+        Assignment(pool)
+    }
+
+    /// Assigns the orgs to a member, their band status is left untouched.
+    pub async fn assign(&self, id_band: i32, id_user: i32, orgs: Vec<i32>) -> Result<u64> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO org_assign(id_org, id_user, id_band)
+                SELECT unnest($3::int[]), $2, $1
+                ON CONFLICT (id_org, id_band) DO UPDATE SET id_user = EXCLUDED.id_user
+            ",
+            )
+            .await?;
+        Ok(client.execute(&stmt, &[&id_band, &id_user, &orgs]).await?)
+    }
+
+    pub async fn unassign(&self, id_band: i32, orgs: Vec<i32>) -> Result<u64> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "UPDATE org_assign SET id_user = NULL WHERE id_band = $1 AND id_org = ANY($2)",
+            )
+            .await?;
+        Ok(client.execute(&stmt, &[&id_band, &orgs]).await?)
+    }
+
+    /// Hands the orgs of a member over to another one, all of them when no
+    /// list is given.
+    pub async fn reassign(
+        &self,
+        id_band: i32,
+        from: i32,
+        to: i32,
+        orgs: Option<Vec<i32>>,
+    ) -> Result<u64> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE org_assign SET id_user = $3
+                WHERE id_band = $1 AND id_user = $2 AND ($4::int[] IS NULL OR id_org = ANY($4))
+            ",
+            )
+            .await?;
+        Ok(client
+            .execute(&stmt, &[&id_band, &from, &to, &orgs])
+            .await?)
+    }
+
+    /// Applies a distribution plan at once.
+    pub async fn assign_all(&self, id_band: i32, shares: &[(i32, Vec<i32>)]) -> Result<u64> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                INSERT INTO org_assign(id_org, id_user, id_band)
+                SELECT unnest($3::int[]), $2, $1
+                ON CONFLICT (id_org, id_band) DO UPDATE SET id_user = EXCLUDED.id_user
+            ",
+            )
+            .await?;
+        let mut count = 0;
+        for (id_user, orgs) in shares {
+            count += transaction
+                .execute(&stmt, &[&id_band, id_user, orgs])
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(count)
+    }
+
+    /// Orgs matching the filters, one row per org whatever its activities.
+    pub async fn candidates(
+        &self,
+        id_band: i32,
+        filters: Vec<Filter>,
+        only_unassigned: bool,
+    ) -> Result<Vec<Candidate>> {
+        let client = self.0.get().await?;
+        let (req_filter, values) = gen_request_search(&filters, 2);
+        let streq = format!(
+            "
+            SELECT DISTINCT ON (o.id) o.id, a.postal_code
+            FROM org o
+            JOIN activity a ON a.id_org = o.id
+            LEFT JOIN org_assign oa ON oa.id_org = o.id AND oa.id_band = $1
+            LEFT JOIN cnm_user cu ON cu.id = oa.id_user
+            WHERE ($2 = FALSE OR oa.id_user IS NULL) {}{}
+            ORDER BY o.id, a.id
+            ",
+            if req_filter.is_empty() { "" } else { " AND " },
+            req_filter,
+        );
+        let stmt = client.prepare_cached(&streq).await?;
+        Ok(client
+            .query(&stmt, &query_params(&[&id_band, &only_unassigned], &values))
+            .await?
+            .iter()
+            .map(|row| Candidate {
+                id_org: row.get(0),
+                postal_code: row.get(1),
+            })
+            .collect())
+    }
+
+    /// Assigned orgs of every member of the band, by status.
+    pub async fn workload(&self, id_band: i32) -> Result<Vec<WorkloadInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    cu.id,
+                    cu.pseudo,
+                    CAST(COUNT(oa.id) AS INT),
+                    CAST(COUNT(oa.id) FILTER (WHERE oa.status = 'todo') AS INT),
+                    CAST(COUNT(oa.id) FILTER (WHERE oa.status = 'raise') AS INT),
+                    CAST(COUNT(oa.id) FILTER (WHERE oa.status = 'success') AS INT),
+                    CAST(COUNT(oa.id) FILTER (WHERE oa.status = 'failure') AS INT),
+                    CAST(COUNT(oa.id) FILTER (WHERE oa.status = 'pending') AS INT)
+                FROM user_band ub
+                JOIN cnm_user cu ON cu.id = ub.id_user
+                LEFT JOIN org_assign oa ON oa.id_user = ub.id_user AND oa.id_band = ub.id_band
+                WHERE ub.id_band = $1
+                GROUP BY cu.id, cu.pseudo
+                ORDER BY cu.pseudo
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(WorkloadInterface::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(postal_codes: &[Option<&str>]) -> Vec<Candidate> {
+        postal_codes
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Candidate {
+                id_org: 10 + i as i32,
+                postal_code: p.map(str::to_string),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_robin_starts_with_least_loaded() {
+        let shares = plan(
+            Strategy::RoundRobin,
+            vec![(1, 5), (2, 0), (3, 2)],
+            candidates(&[None; 5]),
+        );
+        assert_eq!(
+            shares,
+            vec![(2, vec![10, 13]), (3, vec![11, 14]), (1, vec![12])]
+        );
+    }
+
+    #[test]
+    fn region_keeps_departments_together() {
+        let shares = plan(
+            Strategy::Region,
+            vec![(2, 3), (1, 0)],
+            candidates(&[
+                Some("69001"),
+                Some("69002"),
+                Some(" 69100"),
+                Some("75001"),
+                Some("97411"),
+                Some("97500"),
+                None,
+            ]),
+        );
+        // 69 goes to the idle member, then each department to the lightest.
+        assert_eq!(
+            shares,
+            vec![(1, vec![10, 11, 12, 16, 14]), (2, vec![13, 15])]
+        );
+    }
+
+    #[test]
+    fn nothing_is_planned_without_members() {
+        for strategy in [Strategy::RoundRobin, Strategy::Region] {
+            assert!(plan(strategy, vec![], candidates(&[Some("69001")])).is_empty());
+        }
+    }
+
+    #[test]
+    fn overseas_departments_use_three_digits() {
+        assert_eq!(region_of(&Some("97411".to_string())), "974");
+        assert_eq!(region_of(&Some(" 2A004".to_string())), "2A");
+        assert_eq!(region_of(&None), "");
+    }
+}
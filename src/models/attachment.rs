@@ -0,0 +1,278 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use tokio_postgres::Row;
+
+/// What a file is attached to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AttachmentTarget {
+    Note(i32),
+    Contact(i32),
+    Org(i32),
+}
+
+impl AttachmentTarget {
+    /// Reads a target from its kind and id, as found in routes.
+    pub fn parse(kind: &str, id: i32) -> Option<Self> {
+        match kind {
+            "note" => Some(Self::Note(id)),
+            "contact" => Some(Self::Contact(id)),
+            "org" => Some(Self::Org(id)),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        match self {
+            Self::Note(id) | Self::Contact(id) | Self::Org(id) => *id,
+        }
+    }
+
+    /// Values of the note, contact and org columns.
+    fn columns(&self) -> (Option<i32>, Option<i32>, Option<i32>) {
+        match *self {
+            Self::Note(id) => (Some(id), None, None),
+            Self::Contact(id) => (None, Some(id), None),
+            Self::Org(id) => (None, None, Some(id)),
+        }
+    }
+}
+
+impl Display for AttachmentTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                AttachmentTarget::Note(_) => "note",
+                AttachmentTarget::Contact(_) => "contact",
+                AttachmentTarget::Org(_) => "org",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    pub target: String,
+    #[serde(rename = "idTarget")]
+    pub id_target: i32,
+    pub filename: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub size: i64,
+    #[serde(rename = "uploaderId")]
+    pub uploader_id: Option<i32>,
+    #[serde(rename = "uploaderPseudo")]
+    pub uploader_pseudo: Option<String>,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl From<&Row> for AttachmentInterface {
+    fn from(row: &Row) -> Self {
+        AttachmentInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            target: row.get(2),
+            id_target: row.get(3),
+            filename: row.get(4),
+            content_type: row.get(5),
+            size: row.get(6),
+            uploader_id: row.get(7),
+            uploader_pseudo: row.get(8),
+            creation_stamp: row.get(9),
+        }
+    }
+}
+
+/// Attachments still attached to their target, with their storage key.
+const SELECT_ATTACHMENT: &str = "
+    SELECT
+        a.id, a.id_band,
+        CASE
+            WHEN a.id_note IS NOT NULL THEN 'note'
+            WHEN a.id_contact IS NOT NULL THEN 'contact'
+            ELSE 'org'
+        END,
+        COALESCE(a.id_note, a.id_contact, a.id_org),
+        a.filename, a.content_type, a.size, a.id_uploader, cu.pseudo,
+        a.creation_stamp, a.storage_key
+    FROM attachment a
+    LEFT JOIN cnm_user cu ON cu.id = a.id_uploader
+    WHERE num_nonnulls(a.id_note, a.id_contact, a.id_org) = 1
+";
+
+pub struct Attachment(Pool);
+
+impl Attachment {
+    pub fn new(pool: Pool) -> Self {
+        Attachment(pool)
+    }
+
+    /// Whether files may be attached to the target by the band: notes and
+    /// contacts must belong to the band, and notes must not be deleted. Orgs
+    /// are shared by all bands.
+    pub async fn target_exists(&self, id_band: i32, target: AttachmentTarget) -> Result<bool> {
+        let client = self.0.get().await?;
+        let rows = match target {
+            AttachmentTarget::Note(id) => {
+                let stmt = client
+                    .prepare_cached(
+                        "SELECT 1 FROM note WHERE id = $1 AND id_band = $2 AND deleted_stamp IS NULL",
+                    )
+                    .await?;
+                client.query(&stmt, &[&id, &id_band]).await?
+            }
+            AttachmentTarget::Contact(id) => {
+                let stmt = client
+                    .prepare_cached("SELECT 1 FROM contact WHERE id = $1 AND id_band = $2")
+                    .await?;
+                client.query(&stmt, &[&id, &id_band]).await?
+            }
+            AttachmentTarget::Org(id) => {
+                let stmt = client
+                    .prepare_cached("SELECT 1 FROM org WHERE id = $1")
+                    .await?;
+                client.query(&stmt, &[&id]).await?
+            }
+        };
+        Ok(!rows.is_empty())
+    }
+
+    /// Records a new attachment and returns the storage key its content has
+    /// to be written under.
+    pub async fn create(
+        &self,
+        id_band: i32,
+        id_uploader: i32,
+        target: AttachmentTarget,
+        filename: String,
+        content_type: String,
+        size: i64,
+    ) -> Result<(AttachmentInterface, String)> {
+        let (id_note, id_contact, id_org) = target.columns();
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO attachment(
+                    id_band, id_uploader, id_note, id_contact, id_org,
+                    filename, content_type, size, storage_key
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, encode(gen_random_bytes(32), 'hex'))
+                RETURNING id
+            ",
+            )
+            .await?;
+        let id: i32 = client
+            .query_one(
+                &stmt,
+                &[
+                    &id_band,
+                    &id_uploader,
+                    &id_note,
+                    &id_contact,
+                    &id_org,
+                    &filename,
+                    &content_type,
+                    &size,
+                ],
+            )
+            .await?
+            .get(0);
+        self.get(id, id_band)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Attachment {} vanished", id))
+    }
+
+    /// Attachments of the target, latest first.
+    pub async fn list(
+        &self,
+        id_band: i32,
+        target: AttachmentTarget,
+    ) -> Result<Vec<AttachmentInterface>> {
+        let (id_note, id_contact, id_org) = target.columns();
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    AND a.id_band = $1
+                    AND (a.id_note = $2 OR a.id_contact = $3 OR a.id_org = $4)
+                    ORDER BY a.creation_stamp DESC
+                    ",
+                    SELECT_ATTACHMENT
+                )
+                .as_str(),
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band, &id_note, &id_contact, &id_org])
+            .await?
+            .iter()
+            .map(AttachmentInterface::from)
+            .collect())
+    }
+
+    pub async fn get(
+        &self,
+        id: i32,
+        id_band: i32,
+    ) -> Result<Option<(AttachmentInterface, String)>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!("{} AND a.id = $1 AND a.id_band = $2", SELECT_ATTACHMENT).as_str(),
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(|row| (AttachmentInterface::from(row), row.get(10))))
+    }
+
+    /// Returns the storage key of the removed attachment.
+    pub async fn delete(&self, id: i32, id_band: i32) -> Result<Option<String>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "DELETE FROM attachment WHERE id = $1 AND id_band = $2 RETURNING storage_key",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+
+    /// Removes the attachments whose target was deleted, returns the
+    /// storage keys of their files.
+    pub async fn purge_detached(&self) -> Result<Vec<String>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                DELETE FROM attachment
+                WHERE num_nonnulls(id_note, id_contact, id_org) = 0
+                RETURNING storage_key
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[])
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+}
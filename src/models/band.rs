@@ -2,6 +2,7 @@ use anyhow::Result;
 use chrono::NaiveDateTime;
 use deadpool_postgres::Pool;
 use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
 
 use super::user::UserInterface;
 
@@ -10,7 +11,29 @@ pub struct BandInterface {
     pub id: i32,
     pub name: String,
     pub creation_stamp: NaiveDateTime,
+    pub archived_stamp: Option<NaiveDateTime>,
+    pub deleted_stamp: Option<NaiveDateTime>,
 }
+
+impl From<&Row> for BandInterface {
+    fn from(row: &Row) -> Self {
+        BandInterface {
+            id: row.get(0),
+            name: row.get(1),
+            creation_stamp: row.get(2),
+            archived_stamp: row.get(3),
+            deleted_stamp: row.get(4),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BandState {
+    Active,
+    Archived,
+    Deleted,
+}
+
 pub struct Band(Pool);
 
 impl Band {
@@ -27,22 +50,208 @@ impl Band {
         let id: i32 = rows[0].get(0);
         let stmt = client
             .prepare_cached(
-                "INSERT INTO user_band(id_user, id_band, is_admin) VALUES ($1, $2, true)",
+                "
+                INSERT INTO user_band(id_user, id_band, id_role)
+                SELECT $1, $2, id FROM band_role WHERE id_band IS NULL AND name = 'owner'
+            ",
             )
             .await?;
         client.query(&stmt, &[&id_user, &id]).await?;
         Ok(id)
     }
 
-    pub async fn remove(&self, id: i32) -> Result<()> {
+    /// Marks the band as deleted, its data is only purged once the grace
+    /// period is over.
+    pub async fn remove(&self, id: i32, id_deleter: i32) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band SET deleted_stamp = CURRENT_TIMESTAMP, id_deleter = $2
+                WHERE id = $1 AND deleted_stamp IS NULL
+            ",
+            )
+            .await?;
+        client.query(&stmt, &[&id, &id_deleter]).await?;
+        Ok(())
+    }
+
+    /// Brings a deleted band back, as long as it has not been purged and is
+    /// still within the retention window.
+    pub async fn restore(&self, id: i32, grace_days: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band SET deleted_stamp = NULL, id_deleter = NULL
+                WHERE id = $1
+                    AND deleted_stamp > CURRENT_TIMESTAMP - make_interval(days => $2)
+                RETURNING id
+            ",
+            )
+            .await?;
+        Ok(!client.query(&stmt, &[&id, &grace_days]).await?.is_empty())
+    }
+
+    pub async fn archive(&self, id: i32) -> Result<()> {
         let client = self.0.get().await?;
         let stmt = client
-            .prepare_cached("DELETE FROM band WHERE id = $1 CASCADE")
+            .prepare_cached(
+                "UPDATE band SET archived_stamp = CURRENT_TIMESTAMP WHERE id = $1 AND archived_stamp IS NULL",
+            )
             .await?;
         client.query(&stmt, &[&id]).await?;
         Ok(())
     }
 
+    pub async fn unarchive(&self, id: i32) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("UPDATE band SET archived_stamp = NULL WHERE id = $1")
+            .await?;
+        client.query(&stmt, &[&id]).await?;
+        Ok(())
+    }
+
+    pub async fn state(&self, id: i32) -> Result<Option<BandState>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "SELECT deleted_stamp IS NOT NULL, archived_stamp IS NOT NULL FROM band WHERE id = $1",
+            )
+            .await?;
+        Ok(client.query(&stmt, &[&id]).await?.first().map(|row| {
+            if row.get(0) {
+                BandState::Deleted
+            } else if row.get(1) {
+                BandState::Archived
+            } else {
+                BandState::Active
+            }
+        }))
+    }
+
+    /// Deleted bands the user owns which can still be restored.
+    pub async fn get_deleted_owned(
+        &self,
+        id_user: i32,
+        grace_days: i32,
+    ) -> Result<Vec<BandInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT b.id, b.name, b.creation_stamp, b.archived_stamp, b.deleted_stamp
+                FROM band b
+                JOIN user_band ub ON ub.id_band = b.id
+                JOIN band_role br ON br.id = ub.id_role
+                WHERE ub.id_user = $1
+                    AND br.id_band IS NULL AND br.name = 'owner'
+                    AND b.deleted_stamp > CURRENT_TIMESTAMP - make_interval(days => $2)
+                ORDER BY b.deleted_stamp DESC
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user, &grace_days])
+            .await?
+            .iter()
+            .map(BandInterface::from)
+            .collect())
+    }
+
+    /// Permanently deletes bands removed more than `grace_days` ago, along
+    /// with everything they own. Returns the number of purged bands and the
+    /// storage keys of their files and attachments.
+    pub async fn purge_deleted(&self, grace_days: i32) -> Result<(u64, Vec<String>)> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                SELECT bf.storage_key
+                FROM band_file bf
+                JOIN band b ON b.id = bf.id_band
+                WHERE b.deleted_stamp < CURRENT_TIMESTAMP - make_interval(days => $1)
+                UNION ALL
+                SELECT a.storage_key
+                FROM attachment a
+                JOIN band b ON b.id = a.id_band
+                WHERE b.deleted_stamp < CURRENT_TIMESTAMP - make_interval(days => $1)
+            ",
+            )
+            .await?;
+        let keys = transaction
+            .query(&stmt, &[&grace_days])
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        let stmt = transaction
+            .prepare_cached(
+                "
+                DELETE FROM band
+                WHERE deleted_stamp < CURRENT_TIMESTAMP - make_interval(days => $1)
+            ",
+            )
+            .await?;
+        let count = transaction.execute(&stmt, &[&grace_days]).await?;
+        transaction.commit().await?;
+        Ok((count, keys))
+    }
+
+    pub async fn get_owner(&self, id_band: i32) -> Result<Option<i32>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT ub.id_user
+                FROM user_band ub
+                JOIN band_role br ON br.id = ub.id_role
+                WHERE ub.id_band = $1 AND br.id_band IS NULL AND br.name = 'owner'
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+
+    /// Hands the owner role over to another member, the previous owner
+    /// stays in the band as an administrator.
+    pub async fn transfer_ownership(&self, id_band: i32, from: i32, to: i32) -> Result<bool> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE user_band
+                SET id_role = (SELECT id FROM band_role WHERE id_band IS NULL AND name = 'owner')
+                WHERE id_band = $1 AND id_user = $2
+                RETURNING id_user
+            ",
+            )
+            .await?;
+        if transaction.query(&stmt, &[&id_band, &to]).await?.is_empty() {
+            transaction.rollback().await?;
+            return Ok(false);
+        }
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE user_band
+                SET id_role = (SELECT id FROM band_role WHERE id_band IS NULL AND name = 'admin')
+                WHERE id_band = $1 AND id_user = $2
+            ",
+            )
+            .await?;
+        transaction.query(&stmt, &[&id_band, &from]).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
     pub async fn edit(&self, id: i32, name: String) -> Result<()> {
         let client = self.0.get().await?;
         let stmt = client
@@ -52,20 +261,41 @@ impl Band {
         Ok(())
     }
 
+    /// Administrators are the members allowed to manage the others.
     pub async fn is_admin(&self, id_user: i32, id_band: i32) -> Result<bool> {
         let client = self.0.get().await?;
         let stmt = client
-            .prepare_cached("SELECT is_admin FROM user_band WHERE id_user = $1 AND id_band = $2")
+            .prepare_cached(
+                "
+                SELECT 'manage_members' = ANY(br.permissions)
+                FROM user_band ub
+                JOIN band_role br ON br.id = ub.id_role
+                WHERE ub.id_user = $1 AND ub.id_band = $2
+            ",
+            )
             .await?;
         let rows = client.query(&stmt, &[&id_user, &id_band]).await?;
-        Ok(rows[0].get(0))
+        Ok(rows.first().map(|r| r.get(0)).unwrap_or(false))
+    }
+
+    pub async fn is_member(&self, id_user: i32, id_band: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT 1 FROM user_band WHERE id_user = $1 AND id_band = $2")
+            .await?;
+        Ok(!client.query(&stmt, &[&id_user, &id_band]).await?.is_empty())
     }
 
     pub async fn get_admin_count(&self, id_band: i32) -> Result<i32> {
         let client = self.0.get().await?;
         let stmt = client
             .prepare_cached(
-                "SELECT COUNT(id_user) FROM user_band WHERE id_band = $1 AND is_admin = true",
+                "
+                SELECT CAST(COUNT(ub.id_user) AS INT)
+                FROM user_band ub
+                JOIN band_role br ON br.id = ub.id_role
+                WHERE ub.id_band = $1 AND 'manage_members' = ANY(br.permissions)
+            ",
             )
             .await?;
         let rows = client.query(&stmt, &[&id_band]).await?;
@@ -86,10 +316,14 @@ impl Band {
                     cu.creation_stamp,
                     cu.last_login,
                     cu.verified,
-                    ub.is_admin
+                    'manage_members' = ANY(br.permissions),
+                    br.id,
+                    br.name
                 FROM cnm_user cu
                 JOIN user_band ub 
                 ON ub.id_user = cu.id
+                JOIN band_role br
+                ON br.id = ub.id_role
                 WHERE ub.id_band = $1
             ",
             )
@@ -108,6 +342,8 @@ impl Band {
                 last_login: row.get(6),
                 verified: row.get(7),
                 is_admin: Some(row.get(8)),
+                id_role: Some(row.get(9)),
+                role: Some(row.get(10)),
             })
             .collect();
 
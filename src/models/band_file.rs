@@ -0,0 +1,170 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileKind {
+    #[serde(rename = "press_kit")]
+    PressKit,
+    #[serde(rename = "rider")]
+    Rider,
+}
+
+impl From<String> for FileKind {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "rider" => Self::Rider,
+            _ => Self::PressKit,
+        }
+    }
+}
+
+impl Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                FileKind::PressKit => "press_kit",
+                FileKind::Rider => "rider",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BandFileInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    pub kind: FileKind,
+    pub filename: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub size: i64,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl From<&Row> for BandFileInterface {
+    fn from(row: &Row) -> Self {
+        let kind: String = row.get(2);
+        BandFileInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            kind: FileKind::from(kind),
+            filename: row.get(3),
+            content_type: row.get(4),
+            size: row.get(5),
+            creation_stamp: row.get(6),
+        }
+    }
+}
+
+pub struct BandFile(Pool);
+
+impl BandFile {
+    pub fn new(pool: Pool) -> Self {
+        BandFile(pool)
+    }
+
+    /// Records a new file and returns the storage key its content has to be
+    /// written under.
+    pub async fn create(
+        &self,
+        id_band: i32,
+        id_uploader: i32,
+        kind: FileKind,
+        filename: String,
+        content_type: String,
+        size: i64,
+    ) -> Result<(BandFileInterface, String)> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO band_file(id_band, id_uploader, kind, filename, content_type, size, storage_key)
+                VALUES ($1, $2, $3::text::band_file_kind, $4, $5, $6, encode(gen_random_bytes(32), 'hex'))
+                RETURNING
+                    id, id_band, CAST(kind AS VARCHAR(16)), filename,
+                    content_type, size, creation_stamp, storage_key
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[
+                    &id_band,
+                    &id_uploader,
+                    &kind.to_string(),
+                    &filename,
+                    &content_type,
+                    &size,
+                ],
+            )
+            .await?;
+        Ok((BandFileInterface::from(&rows[0]), rows[0].get(7)))
+    }
+
+    pub async fn list(&self, id_band: i32) -> Result<Vec<BandFileInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    id, id_band, CAST(kind AS VARCHAR(16)), filename,
+                    content_type, size, creation_stamp
+                FROM band_file
+                WHERE id_band = $1
+                ORDER BY kind, creation_stamp DESC
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(BandFileInterface::from)
+            .collect())
+    }
+
+    pub async fn get(&self, id: i32, id_band: i32) -> Result<Option<(BandFileInterface, String)>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    id, id_band, CAST(kind AS VARCHAR(16)), filename,
+                    content_type, size, creation_stamp, storage_key
+                FROM band_file
+                WHERE id = $1 AND id_band = $2
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(|row| (BandFileInterface::from(row), row.get(7))))
+    }
+
+    /// Returns the storage key of the removed file.
+    pub async fn delete(&self, id: i32, id_band: i32) -> Result<Option<String>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "DELETE FROM band_file WHERE id = $1 AND id_band = $2 RETURNING storage_key",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+const SHORT_BIO_MAX: usize = 280;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BandProfileInterface {
+    #[serde(default)]
+    pub genres: Vec<String>,
+    #[serde(rename = "shortBio")]
+    pub short_bio: Option<String>,
+    #[serde(rename = "longBio")]
+    pub long_bio: Option<String>,
+    pub website: Option<String>,
+    #[serde(rename = "socialLinks", default)]
+    pub social_links: Vec<String>,
+    #[serde(rename = "streamingLinks", default)]
+    pub streaming_links: Vec<String>,
+    #[serde(rename = "lineupSize")]
+    pub lineup_size: Option<i32>,
+    #[serde(rename = "homeCity")]
+    pub home_city: Option<String>,
+    #[serde(rename = "homePostalCode")]
+    pub home_postal_code: Option<String>,
+    #[serde(rename = "feeMin")]
+    pub fee_min: Option<i32>,
+    #[serde(rename = "feeMax")]
+    pub fee_max: Option<i32>,
+    #[serde(rename = "feeCurrency", default = "default_currency")]
+    pub fee_currency: String,
+    #[serde(rename = "feeNotes")]
+    pub fee_notes: Option<String>,
+    #[serde(rename = "updateStamp", skip_deserializing)]
+    pub update_stamp: Option<NaiveDateTime>,
+}
+
+fn default_currency() -> String {
+    "EUR".to_string()
+}
+
+fn is_url(s: &str) -> bool {
+    (s.starts_with("https://") || s.starts_with("http://")) && !s.contains(char::is_whitespace)
+}
+
+impl BandProfileInterface {
+    /// Returns the failed rules, empty when the profile can be saved.
+    pub fn check(&self) -> Vec<String> {
+        let mut failed = vec![];
+        if self
+            .short_bio
+            .as_ref()
+            .map(|b| b.chars().count() > SHORT_BIO_MAX)
+            .unwrap_or(false)
+        {
+            failed.push("shortBioTooLong".to_string());
+        }
+        if self.website.as_deref().map(|w| !is_url(w)).unwrap_or(false)
+            || self
+                .social_links
+                .iter()
+                .chain(self.streaming_links.iter())
+                .any(|l| !is_url(l))
+        {
+            failed.push("invalidLink".to_string());
+        }
+        if self.lineup_size.map(|s| s < 1).unwrap_or(false) {
+            failed.push("invalidLineupSize".to_string());
+        }
+        if self.fee_min.map(|f| f < 0).unwrap_or(false)
+            || self.fee_max.map(|f| f < 0).unwrap_or(false)
+            || matches!((self.fee_min, self.fee_max), (Some(min), Some(max)) if min > max)
+        {
+            failed.push("invalidFee".to_string());
+        }
+        if self.fee_currency.len() != 3
+            || !self.fee_currency.chars().all(|c| c.is_ascii_uppercase())
+        {
+            failed.push("invalidCurrency".to_string());
+        }
+        if self
+            .home_postal_code
+            .as_ref()
+            .map(|p| p.len() > 16)
+            .unwrap_or(false)
+        {
+            failed.push("invalidPostalCode".to_string());
+        }
+        failed
+    }
+}
+
+impl From<&Row> for BandProfileInterface {
+    fn from(row: &Row) -> Self {
+        BandProfileInterface {
+            genres: row.get(0),
+            short_bio: row.get(1),
+            long_bio: row.get(2),
+            website: row.get(3),
+            social_links: row.get(4),
+            streaming_links: row.get(5),
+            lineup_size: row.get(6),
+            home_city: row.get(7),
+            home_postal_code: row.get(8),
+            fee_min: row.get(9),
+            fee_max: row.get(10),
+            fee_currency: row.get(11),
+            fee_notes: row.get(12),
+            update_stamp: row.get(13),
+        }
+    }
+}
+
+/// Flattened band description handed to mail templates, every value is
+/// already formatted since templates cannot iterate over lists.
+#[derive(Debug, Clone, Serialize)]
+pub struct BandTemplateContext {
+    pub name: String,
+    pub genres: String,
+    #[serde(rename = "shortBio")]
+    pub short_bio: String,
+    #[serde(rename = "longBio")]
+    pub long_bio: String,
+    pub website: String,
+    #[serde(rename = "socialLinks")]
+    pub social_links: String,
+    #[serde(rename = "streamingLinks")]
+    pub streaming_links: String,
+    #[serde(rename = "lineupSize")]
+    pub lineup_size: String,
+    #[serde(rename = "homeCity")]
+    pub home_city: String,
+    #[serde(rename = "homePostalCode")]
+    pub home_postal_code: String,
+    pub fee: String,
+}
+
+impl BandTemplateContext {
+    pub fn new(name: String, profile: BandProfileInterface) -> Self {
+        let fee = match (profile.fee_min, profile.fee_max) {
+            (Some(min), Some(max)) if min == max => format!("{} {}", min, profile.fee_currency),
+            (Some(min), Some(max)) => format!("{} - {} {}", min, max, profile.fee_currency),
+            (Some(min), None) => format!("{} {}", min, profile.fee_currency),
+            (None, Some(max)) => format!("{} {}", max, profile.fee_currency),
+            (None, None) => String::new(),
+        };
+        BandTemplateContext {
+            name,
+            genres: profile.genres.join(", "),
+            short_bio: profile.short_bio.unwrap_or_default(),
+            long_bio: profile.long_bio.unwrap_or_default(),
+            website: profile.website.unwrap_or_default(),
+            social_links: profile.social_links.join("\n"),
+            streaming_links: profile.streaming_links.join("\n"),
+            lineup_size: profile
+                .lineup_size
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+            home_city: profile.home_city.unwrap_or_default(),
+            home_postal_code: profile.home_postal_code.unwrap_or_default(),
+            fee,
+        }
+    }
+}
+
+pub struct BandProfile(Pool);
+
+impl BandProfile {
+    pub fn new(pool: Pool) -> Self {
+        BandProfile(pool)
+    }
+
+    /// Bands without a saved profile get an empty one.
+    pub async fn get(&self, id_band: i32) -> Result<BandProfileInterface> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    genres, short_bio, long_bio, website, social_links,
+                    streaming_links, lineup_size, home_city, home_postal_code,
+                    fee_min, fee_max, fee_currency, fee_notes, update_stamp
+                FROM band_profile
+                WHERE id_band = $1
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .first()
+            .map(BandProfileInterface::from)
+            .unwrap_or_else(|| BandProfileInterface {
+                fee_currency: default_currency(),
+                ..Default::default()
+            }))
+    }
+
+    pub async fn save(
+        &self,
+        id_band: i32,
+        profile: BandProfileInterface,
+    ) -> Result<BandProfileInterface> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO band_profile(
+                    id_band, genres, short_bio, long_bio, website, social_links,
+                    streaming_links, lineup_size, home_city, home_postal_code,
+                    fee_min, fee_max, fee_currency, fee_notes
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                ON CONFLICT (id_band) DO UPDATE SET
+                    genres = EXCLUDED.genres,
+                    short_bio = EXCLUDED.short_bio,
+                    long_bio = EXCLUDED.long_bio,
+                    website = EXCLUDED.website,
+                    social_links = EXCLUDED.social_links,
+                    streaming_links = EXCLUDED.streaming_links,
+                    lineup_size = EXCLUDED.lineup_size,
+                    home_city = EXCLUDED.home_city,
+                    home_postal_code = EXCLUDED.home_postal_code,
+                    fee_min = EXCLUDED.fee_min,
+                    fee_max = EXCLUDED.fee_max,
+                    fee_currency = EXCLUDED.fee_currency,
+                    fee_notes = EXCLUDED.fee_notes,
+                    update_stamp = CURRENT_TIMESTAMP
+                RETURNING
+                    genres, short_bio, long_bio, website, social_links,
+                    streaming_links, lineup_size, home_city, home_postal_code,
+                    fee_min, fee_max, fee_currency, fee_notes, update_stamp
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[
+                    &id_band,
+                    &profile.genres,
+                    &profile.short_bio,
+                    &profile.long_bio,
+                    &profile.website,
+                    &profile.social_links,
+                    &profile.streaming_links,
+                    &profile.lineup_size,
+                    &profile.home_city,
+                    &profile.home_postal_code,
+                    &profile.fee_min,
+                    &profile.fee_max,
+                    &profile.fee_currency,
+                    &profile.fee_notes,
+                ],
+            )
+            .await?;
+        Ok(BandProfileInterface::from(&rows[0]))
+    }
+
+    pub async fn template_context(&self, id_band: i32) -> Result<BandTemplateContext> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT name FROM band WHERE id = $1")
+            .await?;
+        let name: Option<String> = client.query(&stmt, &[&id_band]).await?[0].get(0);
+        Ok(BandTemplateContext::new(
+            name.unwrap_or_default(),
+            self.get(id_band).await?,
+        ))
+    }
+}
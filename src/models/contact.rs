@@ -0,0 +1,1086 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+};
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{
+        event::{self, contact_details, EventKind},
+        org::{ContactInterface, CONTACT_COLUMNS},
+    },
+    vcard,
+};
+
+/// Country code given to national numbers, written with a leading 0.
+const DEFAULT_COUNTRY_CODE: &str = "33";
+const EMAIL_MAX: usize = 254;
+const JOB_TITLE_MAX: usize = 128;
+const DATA_SOURCE_MAX: usize = 256;
+const MIN_NAME_SIMILARITY: f64 = 0.85;
+const MIN_ORG_SIMILARITY: f64 = 0.8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContactRole {
+    #[serde(rename = "programmer")]
+    Programmer,
+    #[serde(rename = "booker")]
+    Booker,
+    #[serde(rename = "technician")]
+    Technician,
+    #[serde(rename = "manager")]
+    Manager,
+    #[serde(rename = "press")]
+    Press,
+    #[serde(rename = "other")]
+    Other,
+}
+
+impl From<String> for ContactRole {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "programmer" => Self::Programmer,
+            "booker" => Self::Booker,
+            "technician" => Self::Technician,
+            "manager" => Self::Manager,
+            "press" => Self::Press,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl Display for ContactRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ContactRole::Programmer => "programmer",
+                ContactRole::Booker => "booker",
+                ContactRole::Technician => "technician",
+                ContactRole::Manager => "manager",
+                ContactRole::Press => "press",
+                ContactRole::Other => "other",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContactChannel {
+    #[serde(rename = "email")]
+    Email,
+    #[serde(rename = "phone")]
+    Phone,
+    #[serde(rename = "social")]
+    Social,
+    #[serde(rename = "post")]
+    Post,
+}
+
+impl From<String> for ContactChannel {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "phone" => Self::Phone,
+            "social" => Self::Social,
+            "post" => Self::Post,
+            _ => Self::Email,
+        }
+    }
+}
+
+impl Display for ContactChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ContactChannel::Email => "email",
+                ContactChannel::Phone => "phone",
+                ContactChannel::Social => "social",
+                ContactChannel::Post => "post",
+            }
+        )
+    }
+}
+
+/// Ground on which the personal data of a contact is processed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LegalBasis {
+    #[serde(rename = "consent")]
+    Consent,
+    #[serde(rename = "legitimate_interest")]
+    LegitimateInterest,
+    #[serde(rename = "contract")]
+    Contract,
+    #[serde(rename = "legal_obligation")]
+    LegalObligation,
+}
+
+impl From<String> for LegalBasis {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "consent" => Self::Consent,
+            "contract" => Self::Contract,
+            "legal_obligation" => Self::LegalObligation,
+            _ => Self::LegitimateInterest,
+        }
+    }
+}
+
+impl Display for LegalBasis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                LegalBasis::Consent => "consent",
+                LegalBasis::LegitimateInterest => "legitimate_interest",
+                LegalBasis::Contract => "contract",
+                LegalBasis::LegalObligation => "legal_obligation",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineKind {
+    #[serde(rename = "work")]
+    #[default]
+    Work,
+    #[serde(rename = "home")]
+    Home,
+    #[serde(rename = "mobile")]
+    Mobile,
+    #[serde(rename = "other")]
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactEmail {
+    #[serde(default)]
+    pub kind: LineKind,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactPhone {
+    #[serde(default)]
+    pub kind: LineKind,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SocialHandle {
+    pub network: String,
+    pub handle: String,
+}
+
+/// Fields of a contact besides its name and postal address.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactDetails {
+    pub role: Option<ContactRole>,
+    #[serde(rename = "jobTitle")]
+    pub job_title: Option<String>,
+    #[serde(rename = "preferredChannel")]
+    pub preferred_channel: Option<ContactChannel>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub emails: Vec<ContactEmail>,
+    #[serde(default)]
+    pub phones: Vec<ContactPhone>,
+    #[serde(default)]
+    pub socials: Vec<SocialHandle>,
+    #[serde(rename = "legalBasis")]
+    pub legal_basis: Option<LegalBasis>,
+    /// Where the data was collected, e.g. a festival website.
+    #[serde(rename = "dataSource")]
+    pub data_source: Option<String>,
+    /// When the legal basis was recorded, or consent given.
+    #[serde(rename = "basisStamp")]
+    pub basis_stamp: Option<NaiveDateTime>,
+}
+
+pub fn is_email(s: &str) -> bool {
+    match s.split_once('@') {
+        Some((local, domain)) => {
+            s.len() <= EMAIL_MAX
+                && !local.is_empty()
+                && !domain.contains('@')
+                && !s.contains(char::is_whitespace)
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|p| !p.is_empty())
+        }
+        None => false,
+    }
+}
+
+/// E.164 form of a phone number, e.g. `+33612345678`. Numbers written
+/// with a leading 0 are national ones, numbers starting with 00 are
+/// international ones.
+pub fn normalize_phone(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let raw = raw.strip_prefix("tel:").unwrap_or(raw).replace("(0)", "");
+    let mut digits = String::new();
+    for (i, c) in raw.chars().enumerate() {
+        match c {
+            '0'..='9' => digits.push(c),
+            '+' if i == 0 => {}
+            ' ' | '.' | '-' | '(' | ')' | '/' => {}
+            _ => return None,
+        }
+    }
+    let number = if raw.starts_with('+') {
+        digits
+    } else if let Some(rest) = digits.strip_prefix("00") {
+        rest.to_string()
+    } else if digits.len() == 10 && digits.starts_with('0') {
+        format!("{}{}", DEFAULT_COUNTRY_CODE, &digits[1..])
+    } else {
+        return None;
+    };
+    if (8..=15).contains(&number.len()) && !number.starts_with('0') {
+        Some(format!("+{}", number))
+    } else {
+        None
+    }
+}
+
+/// Normalized language tag, e.g. `fr` or `fr-BE`.
+fn normalize_language(raw: &str) -> Option<String> {
+    let (lang, region) = match raw.split_once(['-', '_']) {
+        Some((lang, region)) => (lang, Some(region)),
+        None => (raw, None),
+    };
+    if !(2..=3).contains(&lang.len()) || !lang.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    match region {
+        None => Some(lang.to_ascii_lowercase()),
+        Some(r) if r.len() == 2 && r.chars().all(|c| c.is_ascii_alphabetic()) => Some(format!(
+            "{}-{}",
+            lang.to_ascii_lowercase(),
+            r.to_ascii_uppercase()
+        )),
+        Some(_) => None,
+    }
+}
+
+fn trimmed(value: &mut Option<String>) {
+    *value = value
+        .take()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+}
+
+impl ContactDetails {
+    /// Cleans up the details together with the primary email and phone of
+    /// the contact, which are kept first of their list. Returns the failed
+    /// rules, empty when the contact can be saved.
+    pub fn normalize(
+        &mut self,
+        email: &mut Option<String>,
+        phone: &mut Option<String>,
+        has_address: bool,
+    ) -> Vec<String> {
+        let mut failed = vec![];
+        trimmed(email);
+        trimmed(phone);
+        trimmed(&mut self.job_title);
+        trimmed(&mut self.language);
+        trimmed(&mut self.data_source);
+
+        if let Some(primary) = email.as_ref() {
+            let kind = match self
+                .emails
+                .iter()
+                .position(|e| e.value.trim().eq_ignore_ascii_case(primary))
+            {
+                Some(i) => self.emails.remove(i).kind,
+                None => LineKind::Work,
+            };
+            self.emails.insert(
+                0,
+                ContactEmail {
+                    kind,
+                    value: primary.clone(),
+                },
+            );
+        }
+        let mut emails: Vec<ContactEmail> = vec![];
+        for mut e in self.emails.drain(..) {
+            e.value = e.value.trim().to_string();
+            if !is_email(&e.value) {
+                failed.push("invalidEmail".to_string());
+            } else if !emails
+                .iter()
+                .any(|known| known.value.eq_ignore_ascii_case(&e.value))
+            {
+                emails.push(e);
+            }
+        }
+        self.emails = emails;
+        *email = match email.take() {
+            Some(primary) if is_email(&primary) => Some(primary),
+            _ => self.emails.first().map(|e| e.value.clone()),
+        };
+
+        let primary_phone = phone.as_deref().and_then(normalize_phone);
+        if phone.is_some() && primary_phone.is_none() {
+            failed.push("invalidPhone".to_string());
+        }
+        let mut phones: Vec<ContactPhone> = vec![];
+        if let Some(primary) = &primary_phone {
+            phones.push(ContactPhone {
+                kind: self
+                    .phones
+                    .iter()
+                    .find(|p| normalize_phone(&p.value).as_ref() == Some(primary))
+                    .map(|p| p.kind)
+                    .unwrap_or_default(),
+                value: primary.clone(),
+            });
+        }
+        for p in self.phones.drain(..) {
+            match normalize_phone(&p.value) {
+                Some(value) if phones.iter().any(|known| known.value == value) => {}
+                Some(value) => phones.push(ContactPhone {
+                    kind: p.kind,
+                    value,
+                }),
+                None => failed.push("invalidPhone".to_string()),
+            }
+        }
+        self.phones = phones;
+        *phone = self.phones.first().map(|p| p.value.clone());
+
+        let mut socials: Vec<SocialHandle> = vec![];
+        for s in self.socials.drain(..) {
+            let network = s.network.trim().to_lowercase();
+            let handle = s.handle.trim().to_string();
+            if network.is_empty() || handle.is_empty() || handle.contains(char::is_whitespace) {
+                failed.push("invalidSocialHandle".to_string());
+            } else if !socials
+                .iter()
+                .any(|known| known.network == network && known.handle == handle)
+            {
+                socials.push(SocialHandle { network, handle });
+            }
+        }
+        self.socials = socials;
+
+        if let Some(language) = self.language.take() {
+            match normalize_language(&language) {
+                Some(language) => self.language = Some(language),
+                None => failed.push("invalidLanguage".to_string()),
+            }
+        }
+        if self
+            .job_title
+            .as_ref()
+            .map(|t| t.chars().count() > JOB_TITLE_MAX)
+            .unwrap_or(false)
+        {
+            failed.push("jobTitleTooLong".to_string());
+        }
+        let reachable = match self.preferred_channel {
+            Some(ContactChannel::Email) => !self.emails.is_empty(),
+            Some(ContactChannel::Phone) => !self.phones.is_empty(),
+            Some(ContactChannel::Social) => !self.socials.is_empty(),
+            Some(ContactChannel::Post) => has_address,
+            None => true,
+        };
+        if !reachable {
+            failed.push("unreachablePreferredChannel".to_string());
+        }
+        if self
+            .data_source
+            .as_ref()
+            .map(|s| s.chars().count() > DATA_SOURCE_MAX)
+            .unwrap_or(false)
+        {
+            failed.push("dataSourceTooLong".to_string());
+        }
+        if self.legal_basis.is_none() {
+            self.basis_stamp = None;
+        }
+        failed.dedup();
+        failed
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DuplicateReason {
+    #[serde(rename = "email")]
+    Email,
+    #[serde(rename = "phone")]
+    Phone,
+    #[serde(rename = "name")]
+    Name,
+}
+
+/// A contact of the band with the org it belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct BandContact {
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    #[serde(rename = "orgName")]
+    pub org_name: String,
+    pub contact: ContactInterface,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub reasons: Vec<DuplicateReason>,
+    pub contacts: Vec<BandContact>,
+}
+
+/// Union-find over contact indexes.
+struct Groups {
+    parents: Vec<usize>,
+    reasons: Vec<BTreeSet<DuplicateReason>>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Groups {
+            parents: (0..len).collect(),
+            reasons: vec![BTreeSet::new(); len],
+        }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize, reason: DuplicateReason) {
+        let (a, b) = (self.root(a), self.root(b));
+        if a != b {
+            self.parents[b] = a;
+            let moved = std::mem::take(&mut self.reasons[b]);
+            self.reasons[a].extend(moved);
+        }
+        self.reasons[a].insert(reason);
+    }
+}
+
+fn full_name(contact: &ContactInterface) -> String {
+    vcard::normalize(&format!(
+        "{} {}",
+        contact.first_name.as_deref().unwrap_or_default(),
+        contact.name
+    ))
+}
+
+/// Groups the contacts sharing an email or a phone, or having close names
+/// in the same org or in orgs with close names. Contacts without any
+/// duplicate are left out.
+pub fn find_duplicates(contacts: Vec<BandContact>) -> Vec<DuplicateGroup> {
+    let mut groups = Groups::new(contacts.len());
+    let mut keys: HashMap<(DuplicateReason, String), usize> = HashMap::new();
+    for (i, c) in contacts.iter().enumerate() {
+        let emails = c
+            .contact
+            .details
+            .emails
+            .iter()
+            .map(|e| e.value.to_lowercase())
+            .chain(c.contact.email.iter().map(|e| e.to_lowercase()));
+        let phones = c
+            .contact
+            .details
+            .phones
+            .iter()
+            .map(|p| &p.value)
+            .chain(c.contact.phone.iter())
+            .filter_map(|p| normalize_phone(p));
+        let keyed = emails
+            .map(|e| (DuplicateReason::Email, e))
+            .chain(phones.map(|p| (DuplicateReason::Phone, p)));
+        for (reason, value) in keyed {
+            match keys.get(&(reason, value.clone())) {
+                Some(first) => groups.join(*first, i, reason),
+                None => {
+                    keys.insert((reason, value), i);
+                }
+            }
+        }
+    }
+
+    let names: Vec<String> = contacts.iter().map(|c| full_name(&c.contact)).collect();
+    let orgs: Vec<String> = contacts
+        .iter()
+        .map(|c| vcard::normalize(&c.org_name))
+        .collect();
+    let mut by_org: HashMap<i32, Vec<usize>> = HashMap::new();
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, c) in contacts.iter().enumerate() {
+        by_org.entry(c.id_org).or_default().push(i);
+        by_name.entry(names[i].as_str()).or_default().push(i);
+    }
+    let mut pairs = vec![];
+    for members in by_org.values() {
+        for (n, a) in members.iter().enumerate() {
+            for b in &members[n + 1..] {
+                if vcard::similarity(&names[*a], &names[*b]) >= MIN_NAME_SIMILARITY {
+                    pairs.push((*a, *b));
+                }
+            }
+        }
+    }
+    for members in by_name.values() {
+        for (n, a) in members.iter().enumerate() {
+            for b in &members[n + 1..] {
+                if contacts[*a].id_org != contacts[*b].id_org
+                    && vcard::similarity(&orgs[*a], &orgs[*b]) >= MIN_ORG_SIMILARITY
+                {
+                    pairs.push((*a, *b));
+                }
+            }
+        }
+    }
+    for (a, b) in pairs {
+        groups.join(a, b, DuplicateReason::Name);
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..contacts.len() {
+        let root = groups.root(i);
+        members.entry(root).or_default().push(i);
+    }
+    let mut contacts: Vec<Option<BandContact>> = contacts.into_iter().map(Some).collect();
+    let mut res: Vec<DuplicateGroup> = members
+        .into_iter()
+        .filter(|(_, m)| m.len() > 1)
+        .map(|(root, m)| DuplicateGroup {
+            reasons: groups.reasons[root].iter().copied().collect(),
+            contacts: m.iter().filter_map(|i| contacts[*i].take()).collect(),
+        })
+        .collect();
+    res.sort_by_key(|g| g.contacts.iter().map(|c| c.contact.id).min());
+    res
+}
+
+/// Single valued fields a merge may take from any of the merged contacts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MergeField {
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "firstName")]
+    FirstName,
+    #[serde(rename = "email")]
+    Email,
+    #[serde(rename = "phone")]
+    Phone,
+    #[serde(rename = "address")]
+    Address,
+    #[serde(rename = "zipCode")]
+    ZipCode,
+    #[serde(rename = "city")]
+    City,
+    #[serde(rename = "role")]
+    Role,
+    #[serde(rename = "jobTitle")]
+    JobTitle,
+    #[serde(rename = "preferredChannel")]
+    PreferredChannel,
+    #[serde(rename = "language")]
+    Language,
+    /// The legal basis with its source and date.
+    #[serde(rename = "legalBasis")]
+    LegalBasis,
+}
+
+/// Values of the merged contact: the picked fields come from the given
+/// contact, the other ones from the target, or from the first source having
+/// one when the target has none. Emails, phones and handles of every
+/// contact are kept.
+pub fn merge_values(
+    target: &ContactInterface,
+    sources: &[&ContactInterface],
+    picks: &HashMap<MergeField, &ContactInterface>,
+) -> ContactInterface {
+    fn pick<T: Clone>(
+        field: MergeField,
+        target: &ContactInterface,
+        sources: &[&ContactInterface],
+        picks: &HashMap<MergeField, &ContactInterface>,
+        get: impl Fn(&ContactInterface) -> Option<T>,
+    ) -> Option<T> {
+        match picks.get(&field) {
+            Some(from) => get(from),
+            None => get(target).or_else(|| sources.iter().find_map(|s| get(s))),
+        }
+    }
+    let mut merged = target.clone();
+    merged.name = picks
+        .get(&MergeField::Name)
+        .map(|c| c.name.clone())
+        .unwrap_or_else(|| target.name.clone());
+    merged.first_name = pick(MergeField::FirstName, target, sources, picks, |c| {
+        c.first_name.clone()
+    });
+    merged.email = pick(MergeField::Email, target, sources, picks, |c| {
+        c.email.clone()
+    });
+    merged.phone = pick(MergeField::Phone, target, sources, picks, |c| {
+        c.phone.clone()
+    });
+    merged.address = pick(MergeField::Address, target, sources, picks, |c| {
+        c.address.clone()
+    });
+    merged.zip_code = pick(MergeField::ZipCode, target, sources, picks, |c| {
+        c.zip_code.clone()
+    });
+    merged.city = pick(MergeField::City, target, sources, picks, |c| c.city.clone());
+    merged.details.role = pick(MergeField::Role, target, sources, picks, |c| c.details.role);
+    merged.details.job_title = pick(MergeField::JobTitle, target, sources, picks, |c| {
+        c.details.job_title.clone()
+    });
+    merged.details.preferred_channel =
+        pick(MergeField::PreferredChannel, target, sources, picks, |c| {
+            c.details.preferred_channel
+        });
+    merged.details.language = pick(MergeField::Language, target, sources, picks, |c| {
+        c.details.language.clone()
+    });
+    let basis = pick(MergeField::LegalBasis, target, sources, picks, |c| {
+        c.details
+            .legal_basis
+            .map(|b| (b, c.details.data_source.clone(), c.details.basis_stamp))
+    });
+    merged.details.legal_basis = basis.as_ref().map(|b| b.0);
+    merged.details.data_source = basis.as_ref().and_then(|b| b.1.clone());
+    merged.details.basis_stamp = basis.and_then(|b| b.2);
+    for source in sources {
+        merged
+            .details
+            .emails
+            .extend(source.details.emails.iter().cloned());
+        merged
+            .details
+            .phones
+            .extend(source.details.phones.iter().cloned());
+        merged
+            .details
+            .socials
+            .extend(source.details.socials.iter().cloned());
+    }
+    merged
+}
+
+pub struct Contact(Pool);
+
+impl Contact {
+    pub fn new(pool: Pool) -> Self {
+        Contact(pool)
+    }
+
+    /// Saves the merged values on the target, moves the history, the
+    /// interactions and the attachments of the sources to it, then deletes
+    /// the sources. A contact has a single shared copy: when the target has
+    /// none, the latest approved copy of the sources is moved to it.
+    pub async fn merge(
+        &self,
+        id_actor: i32,
+        id_band: i32,
+        merged: &ContactInterface,
+        sources: &[i32],
+    ) -> Result<u64> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let details = &merged.details;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE contact
+                SET
+                    name = $3,
+                    firstname = $4,
+                    email = $5,
+                    phone = $6,
+                    address = $7,
+                    zip_code = $8,
+                    city = $9,
+                    role = $10::text::contact_role,
+                    job_title = $11,
+                    preferred_channel = $12::text::contact_channel,
+                    language = $13,
+                    emails = $14::text::jsonb,
+                    phones = $15::text::jsonb,
+                    socials = $16::text::jsonb,
+                    legal_basis = $17::text::legal_basis,
+                    data_source = $18,
+                    basis_stamp = CASE WHEN $17::text IS NULL THEN NULL
+                        ELSE COALESCE($19::timestamp, CURRENT_TIMESTAMP) END,
+                    update_stamp = CURRENT_TIMESTAMP,
+                    stale_since = NULL
+                WHERE id = $1 AND id_band = $2
+                RETURNING id_org
+            ",
+            )
+            .await?;
+        let id_org: i32 = transaction
+            .query_one(
+                &stmt,
+                &[
+                    &merged.id,
+                    &id_band,
+                    &merged.name,
+                    &merged.first_name,
+                    &merged.email,
+                    &merged.phone,
+                    &merged.address,
+                    &merged.zip_code,
+                    &merged.city,
+                    &details.role.map(|r| r.to_string()),
+                    &details.job_title,
+                    &details.preferred_channel.map(|c| c.to_string()),
+                    &details.language,
+                    &serde_json::to_string(&details.emails)?,
+                    &serde_json::to_string(&details.phones)?,
+                    &serde_json::to_string(&details.socials)?,
+                    &details.legal_basis.map(|b| b.to_string()),
+                    &details.data_source,
+                    &details.basis_stamp,
+                ],
+            )
+            .await?
+            .get(0);
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE band_event
+                SET id_target = $3
+                WHERE id_band = $1
+                    AND id_target = ANY($2)
+                    AND kind IN ('contact_added', 'contact_edited', 'contact_merged')
+            ",
+            )
+            .await?;
+        transaction
+            .execute(&stmt, &[&id_band, &sources, &merged.id])
+            .await?;
+        let stmt = transaction
+            .prepare_cached(
+                "UPDATE interaction SET id_contact = $3 WHERE id_band = $1 AND id_contact = ANY($2)",
+            )
+            .await?;
+        transaction
+            .execute(&stmt, &[&id_band, &sources, &merged.id])
+            .await?;
+        let stmt = transaction
+            .prepare_cached(
+                "UPDATE attachment SET id_contact = $3 WHERE id_band = $1 AND id_contact = ANY($2)",
+            )
+            .await?;
+        transaction
+            .execute(&stmt, &[&id_band, &sources, &merged.id])
+            .await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE shared_contact SET id_contact = $3, id_org = $4
+                WHERE id = (
+                    SELECT id FROM shared_contact
+                    WHERE id_band = $1
+                        AND id_contact = ANY($2)
+                        AND status = 'approved'
+                        AND NOT EXISTS (SELECT 1 FROM shared_contact WHERE id_contact = $3)
+                    ORDER BY moderation_stamp DESC NULLS LAST, id DESC
+                    LIMIT 1
+                )
+            ",
+            )
+            .await?;
+        transaction
+            .execute(&stmt, &[&id_band, &sources, &merged.id, &id_org])
+            .await?;
+        let stmt = transaction
+            .prepare_cached("DELETE FROM contact WHERE id_band = $1 AND id = ANY($2)")
+            .await?;
+        let removed = transaction.execute(&stmt, &[&id_band, &sources]).await?;
+        let mut details = contact_details(merged);
+        details["merged"] = serde_json::json!(sources);
+        event::record(
+            &transaction,
+            id_band,
+            Some(id_actor),
+            EventKind::ContactMerged,
+            Some(id_org),
+            Some(merged.id),
+            details,
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(removed)
+    }
+
+    /// Flags the contacts which have not been edited, reviewed or contacted
+    /// for `retention_days`, and unflags the ones which have been since.
+    /// Returns the number of flagged and unflagged contacts.
+    pub async fn flag_stale(&self, retention_days: i32) -> Result<(u64, u64)> {
+        let client = self.0.get().await?;
+        let activity = "
+            WITH activity AS (
+                SELECT
+                    c.id,
+                    GREATEST(
+                        c.creation_stamp, c.update_stamp, c.basis_stamp, MAX(i.happened_stamp)
+                    ) AS last_stamp
+                FROM contact c
+                LEFT JOIN interaction i ON i.id_contact = c.id
+                GROUP BY c.id
+            )
+        ";
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    UPDATE contact SET stale_since = CURRENT_TIMESTAMP
+                    FROM activity a
+                    WHERE a.id = contact.id
+                        AND contact.stale_since IS NULL
+                        AND a.last_stamp < CURRENT_TIMESTAMP - make_interval(days => $1)
+                    ",
+                    activity
+                )
+                .as_str(),
+            )
+            .await?;
+        let flagged = client.execute(&stmt, &[&retention_days]).await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    UPDATE contact SET stale_since = NULL
+                    FROM activity a
+                    WHERE a.id = contact.id
+                        AND contact.stale_since IS NOT NULL
+                        AND a.last_stamp >= CURRENT_TIMESTAMP - make_interval(days => $1)
+                    ",
+                    activity
+                )
+                .as_str(),
+            )
+            .await?;
+        let unflagged = client.execute(&stmt, &[&retention_days]).await?;
+        Ok((flagged, unflagged))
+    }
+
+    /// Confirms the band still needs the contact, which restarts its
+    /// retention period.
+    pub async fn review(&self, id: i32, id_band: i32) -> Result<Option<ContactInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    UPDATE contact
+                    SET update_stamp = CURRENT_TIMESTAMP, stale_since = NULL
+                    WHERE id = $1 AND id_band = $2
+                    RETURNING {}
+                    ",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(ContactInterface::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(id: i32, first_name: &str, name: &str) -> ContactInterface {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "firstName": first_name,
+            "creationStamp": "2024-01-01T00:00:00",
+        }))
+        .unwrap()
+    }
+
+    fn band_contact(id_org: i32, org_name: &str, contact: ContactInterface) -> BandContact {
+        BandContact {
+            id_org,
+            org_name: org_name.to_string(),
+            contact,
+        }
+    }
+
+    fn ids(groups: &[DuplicateGroup]) -> Vec<Vec<i32>> {
+        groups
+            .iter()
+            .map(|g| g.contacts.iter().map(|c| c.contact.id).collect())
+            .collect()
+    }
+
+    #[test]
+    fn emails_and_phones_group_contacts() {
+        let mut a = contact(1, "Jane", "Doe");
+        a.email = Some("Jane@Example.org".to_string());
+        let mut b = contact(2, "J.", "Smith");
+        b.details.emails = vec![ContactEmail {
+            kind: LineKind::default(),
+            value: "jane@example.org".to_string(),
+        }];
+        let mut c = contact(3, "Paul", "Martin");
+        c.phone = Some("06 12 34 56 78".to_string());
+        let mut d = contact(4, "Lucie", "Bernard");
+        d.details.phones = vec![ContactPhone {
+            kind: LineKind::default(),
+            value: "+33612345678".to_string(),
+        }];
+        let e = contact(5, "Marc", "Petit");
+        let groups = find_duplicates(vec![
+            band_contact(1, "La Cigale", a),
+            band_contact(2, "Olympia", b),
+            band_contact(1, "La Cigale", c),
+            band_contact(3, "Bataclan", d),
+            band_contact(2, "Olympia", e),
+        ]);
+        assert_eq!(ids(&groups), vec![vec![1, 2], vec![3, 4]]);
+        assert_eq!(groups[0].reasons, vec![DuplicateReason::Email]);
+        assert_eq!(groups[1].reasons, vec![DuplicateReason::Phone]);
+    }
+
+    #[test]
+    fn close_names_group_contacts_of_close_orgs() {
+        let groups = find_duplicates(vec![
+            band_contact(1, "La Cigale", contact(1, "Jane", "Doe")),
+            band_contact(1, "La Cigale", contact(2, "Jane", "Does")),
+            band_contact(2, "La Cigalle", contact(3, "Jane", "Doé")),
+            band_contact(3, "Olympia", contact(4, "Jane", "Doe")),
+            band_contact(1, "La Cigale", contact(5, "Paul", "Martin")),
+        ]);
+        assert_eq!(ids(&groups), vec![vec![1, 2, 3]]);
+        assert_eq!(groups[0].reasons, vec![DuplicateReason::Name]);
+    }
+
+    #[test]
+    fn groups_joined_by_several_reasons_keep_them_all() {
+        let mut a = contact(1, "Jane", "Doe");
+        a.email = Some("jane@example.org".to_string());
+        let mut b = contact(2, "Janet", "Smith");
+        b.email = Some("jane@example.org".to_string());
+        b.phone = Some("0612345678".to_string());
+        let mut c = contact(3, "Paul", "Martin");
+        c.phone = Some("+33 6 12 34 56 78".to_string());
+        let groups = find_duplicates(vec![
+            band_contact(1, "La Cigale", a),
+            band_contact(2, "Olympia", b),
+            band_contact(3, "Bataclan", c),
+        ]);
+        assert_eq!(ids(&groups), vec![vec![1, 2, 3]]);
+        assert_eq!(
+            groups[0].reasons,
+            vec![DuplicateReason::Email, DuplicateReason::Phone]
+        );
+    }
+
+    #[test]
+    fn merged_values_follow_picks_then_target_then_sources() {
+        let mut target = contact(1, "", "Doe");
+        target.first_name = None;
+        target.email = Some("jane@example.org".to_string());
+        target.details.emails = vec![ContactEmail {
+            kind: LineKind::default(),
+            value: "doe@example.org".to_string(),
+        }];
+        let mut first = contact(2, "Jane", "Doe-Smith");
+        first.phone = Some("+33612345678".to_string());
+        first.email = Some("jane@first.org".to_string());
+        first.details.emails = vec![ContactEmail {
+            kind: LineKind::default(),
+            value: "smith@example.org".to_string(),
+        }];
+        let mut second = contact(3, "Janet", "Smith");
+        second.email = Some("jane@second.org".to_string());
+        second.city = Some("Paris".to_string());
+        second.phone = Some("+33700000000".to_string());
+
+        let picks = HashMap::from([(MergeField::Name, &first), (MergeField::Email, &second)]);
+        let merged = merge_values(&target, &[&first, &second], &picks);
+        assert_eq!(merged.id, 1);
+        assert_eq!(merged.name, "Doe-Smith");
+        assert_eq!(merged.first_name.as_deref(), Some("Jane"));
+        assert_eq!(merged.email.as_deref(), Some("jane@second.org"));
+        assert_eq!(merged.phone.as_deref(), Some("+33612345678"));
+        assert_eq!(merged.city.as_deref(), Some("Paris"));
+        assert_eq!(
+            merged
+                .details
+                .emails
+                .iter()
+                .map(|e| e.value.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["doe@example.org", "smith@example.org"]
+        );
+
+        let merged = merge_values(&target, &[&first, &second], &HashMap::new());
+        assert_eq!(merged.name, "Doe");
+        assert_eq!(merged.email.as_deref(), Some("jane@example.org"));
+    }
+
+    #[test]
+    fn emails_are_checked() {
+        for email in ["jane@example.org", "jane.doe+band@mail.example.co.uk"] {
+            assert!(is_email(email), "{:?} refused", email);
+        }
+        for email in [
+            "",
+            "jane",
+            "@example.org",
+            "jane@example",
+            "jane@example..org",
+            "jane@doe@example.org",
+            "jane doe@example.org",
+        ] {
+            assert!(!is_email(email), "{:?} accepted", email);
+        }
+        let long = format!("{}@example.org", "a".repeat(EMAIL_MAX));
+        assert!(!is_email(&long));
+    }
+
+    #[test]
+    fn phones_are_normalized() {
+        for (raw, expected) in [
+            ("06 12 34 56 78", "+33612345678"),
+            ("06.12.34.56.78", "+33612345678"),
+            ("+33 (0)6 12 34 56 78", "+33612345678"),
+            ("0033 6 12 34 56 78", "+33612345678"),
+            ("tel:+44-20-7946-0958", "+442079460958"),
+            (" +1 (212) 555-0100 ", "+12125550100"),
+        ] {
+            assert_eq!(normalize_phone(raw).as_deref(), Some(expected), "{:?}", raw);
+        }
+        for raw in [
+            "",
+            "612345678",
+            "06 12 34 56 7x",
+            "+33 6+12",
+            "+0612345678",
+            "12",
+        ] {
+            assert_eq!(normalize_phone(raw), None, "{:?}", raw);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use super::{note::stored_html, org::Status};
+
+/// An org assigned to the user in one of their bands.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardOrgInterface {
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "bandName")]
+    pub band_name: String,
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    pub name: String,
+    pub category: Option<String>,
+    pub city: Option<String>,
+    #[serde(rename = "zipCode")]
+    pub zip_code: Option<String>,
+    pub status: String,
+    #[serde(rename = "assignStamp")]
+    pub assign_stamp: NaiveDateTime,
+}
+
+impl From<&Row> for DashboardOrgInterface {
+    fn from(row: &Row) -> Self {
+        DashboardOrgInterface {
+            id_band: row.get(0),
+            band_name: row.get(1),
+            id_org: row.get(2),
+            name: row.get(3),
+            category: row.get(4),
+            city: row.get(5),
+            zip_code: row.get(6),
+            status: row.get(7),
+            assign_stamp: row.get(8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardNoteInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "bandName")]
+    pub band_name: String,
+    #[serde(rename = "idActivity")]
+    pub id_activity: i32,
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    #[serde(rename = "orgName")]
+    pub org_name: String,
+    pub note: String,
+    pub html: String,
+    #[serde(rename = "userId")]
+    pub user_id: i32,
+    #[serde(rename = "userPseudo")]
+    pub user_pseudo: String,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl From<&Row> for DashboardNoteInterface {
+    fn from(row: &Row) -> Self {
+        let note: String = row.get(6);
+        DashboardNoteInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            band_name: row.get(2),
+            id_activity: row.get(3),
+            id_org: row.get(4),
+            org_name: row.get(5),
+            html: stored_html(&note, row.get(10)),
+            note,
+            user_id: row.get(7),
+            user_pseudo: row.get(8),
+            creation_stamp: row.get(9),
+        }
+    }
+}
+
+/// Views spanning several bands, the caller hands over the bands the user
+/// may read.
+pub struct Dashboard(Pool);
+
+impl Dashboard {
+    pub fn new(pool: Pool) -> Self {
+        Dashboard(pool)
+    }
+
+    /// Orgs assigned to the user, optionally restricted to a status. Orgs
+    /// with several activities are described by their first one.
+    pub async fn orgs(
+        &self,
+        id_user: i32,
+        bands: &[i32],
+        status: Option<Status>,
+    ) -> Result<Vec<DashboardOrgInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    b.id, b.name, o.id, o.name, fa.category, fa.city, fa.postal_code,
+                    CAST(oa.status AS VARCHAR(16)), oa.creation_stamp
+                FROM org_assign oa
+                JOIN band b ON b.id = oa.id_band
+                JOIN org o ON o.id = oa.id_org
+                LEFT JOIN LATERAL (
+                    SELECT a.category, a.city, a.postal_code
+                    FROM activity a
+                    WHERE a.id_org = o.id
+                    ORDER BY a.id
+                    LIMIT 1
+                ) fa ON TRUE
+                WHERE oa.id_user = $1
+                    AND oa.id_band = ANY($2)
+                    AND ($3::text IS NULL OR oa.status = $3::text::org_status)
+                ORDER BY b.name, o.name
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user, &bands, &status.map(|s| s.to_string())])
+            .await?
+            .iter()
+            .map(DashboardOrgInterface::from)
+            .collect())
+    }
+
+    /// Latest notes written in the bands, newest first.
+    pub async fn recent_notes(
+        &self,
+        bands: &[i32],
+        limit: i64,
+    ) -> Result<Vec<DashboardNoteInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    n.id, b.id, b.name, a.id, o.id, o.name, n.note,
+                    cu.id, cu.pseudo, n.creation_stamp, n.html
+                FROM note n
+                JOIN band b ON b.id = n.id_band
+                JOIN activity a ON a.id = n.id_activity
+                JOIN org o ON o.id = a.id_org
+                JOIN cnm_user cu ON cu.id = n.id_user
+                WHERE n.id_band = ANY($1) AND n.deleted_stamp IS NULL
+                ORDER BY n.creation_stamp DESC
+                LIMIT $2
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&bands, &limit])
+            .await?
+            .iter()
+            .map(DashboardNoteInterface::from)
+            .collect())
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio_postgres::Row;
+
+use crate::{models::org::ContactInterface, paginator::Paginator};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    #[serde(rename = "org_tagged")]
+    OrgTagged,
+    #[serde(rename = "contact_added")]
+    ContactAdded,
+    #[serde(rename = "contact_edited")]
+    ContactEdited,
+    #[serde(rename = "contact_removed")]
+    ContactRemoved,
+    #[serde(rename = "contact_merged")]
+    ContactMerged,
+    #[serde(rename = "note_created")]
+    NoteCreated,
+    #[serde(rename = "note_edited")]
+    NoteEdited,
+    #[serde(rename = "note_deleted")]
+    NoteDeleted,
+    #[serde(rename = "note_restored")]
+    NoteRestored,
+    #[serde(rename = "note_pinned")]
+    NotePinned,
+    #[serde(rename = "note_unpinned")]
+    NoteUnpinned,
+    #[serde(rename = "member_joined")]
+    MemberJoined,
+    #[serde(rename = "member_left")]
+    MemberLeft,
+    #[serde(rename = "member_kicked")]
+    MemberKicked,
+}
+
+impl TryFrom<String> for EventKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_ref() {
+            "org_tagged" => Ok(Self::OrgTagged),
+            "contact_added" => Ok(Self::ContactAdded),
+            "contact_edited" => Ok(Self::ContactEdited),
+            "contact_removed" => Ok(Self::ContactRemoved),
+            "contact_merged" => Ok(Self::ContactMerged),
+            "note_created" => Ok(Self::NoteCreated),
+            "note_edited" => Ok(Self::NoteEdited),
+            "note_deleted" => Ok(Self::NoteDeleted),
+            "note_restored" => Ok(Self::NoteRestored),
+            "note_pinned" => Ok(Self::NotePinned),
+            "note_unpinned" => Ok(Self::NoteUnpinned),
+            "member_joined" => Ok(Self::MemberJoined),
+            "member_left" => Ok(Self::MemberLeft),
+            "member_kicked" => Ok(Self::MemberKicked),
+            _ => Err("unknownEventKind".to_string()),
+        }
+    }
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                EventKind::OrgTagged => "org_tagged",
+                EventKind::ContactAdded => "contact_added",
+                EventKind::ContactEdited => "contact_edited",
+                EventKind::ContactRemoved => "contact_removed",
+                EventKind::ContactMerged => "contact_merged",
+                EventKind::NoteCreated => "note_created",
+                EventKind::NoteEdited => "note_edited",
+                EventKind::NoteDeleted => "note_deleted",
+                EventKind::NoteRestored => "note_restored",
+                EventKind::NotePinned => "note_pinned",
+                EventKind::NoteUnpinned => "note_unpinned",
+                EventKind::MemberJoined => "member_joined",
+                EventKind::MemberLeft => "member_left",
+                EventKind::MemberKicked => "member_kicked",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    pub kind: EventKind,
+    #[serde(rename = "actorId")]
+    pub actor_id: Option<i32>,
+    #[serde(rename = "actorPseudo")]
+    pub actor_pseudo: Option<String>,
+    #[serde(rename = "idOrg")]
+    pub id_org: Option<i32>,
+    #[serde(rename = "orgName")]
+    pub org_name: Option<String>,
+    #[serde(rename = "idTarget")]
+    pub id_target: Option<i32>,
+    pub details: Value,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl TryFrom<&Row> for EventInterface {
+    type Error = String;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        let kind: String = row.get(2);
+        let details: String = row.get(8);
+        Ok(EventInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            kind: EventKind::try_from(kind)?,
+            actor_id: row.get(3),
+            actor_pseudo: row.get(4),
+            id_org: row.get(5),
+            org_name: row.get(6),
+            id_target: row.get(7),
+            details: serde_json::from_str(&details).unwrap_or(Value::Null),
+            creation_stamp: row.get(9),
+        })
+    }
+}
+
+/// What the feed shows of a contact, it may be gone when the event is read.
+pub fn contact_details(contact: &ContactInterface) -> Value {
+    json!({
+        "name": contact.name,
+        "firstName": contact.first_name,
+    })
+}
+
+/// Records an event in the transaction of the change it describes, so that
+/// the feed has an entry for every change that was committed and none for
+/// the ones rolled back. Events of jobs have no actor.
+pub async fn record(
+    transaction: &Transaction<'_>,
+    id_band: i32,
+    id_actor: Option<i32>,
+    kind: EventKind,
+    id_org: Option<i32>,
+    id_target: Option<i32>,
+    details: Value,
+) -> Result<()> {
+    let stmt = transaction
+        .prepare_cached(
+            "
+            INSERT INTO band_event(id_band, id_actor, kind, id_org, id_target, details)
+            VALUES ($1, $2, $3::text::band_event_kind, $4, $5, $6::text::jsonb)
+        ",
+        )
+        .await?;
+    transaction
+        .execute(
+            &stmt,
+            &[
+                &id_band,
+                &id_actor,
+                &kind.to_string(),
+                &id_org,
+                &id_target,
+                &details.to_string(),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Narrows a band feed, empty criteria match every event.
+#[derive(Debug, Clone, Default)]
+pub struct FeedFilter {
+    pub kinds: Vec<EventKind>,
+    pub actor: Option<i32>,
+    pub org: Option<i32>,
+    pub since: Option<NaiveDateTime>,
+}
+
+pub struct Event(Pool);
+
+impl Event {
+    pub fn new(pool: Pool) -> Self {
+        Event(pool)
+    }
+
+    /// Events of the band, newest first.
+    pub async fn feed(
+        &self,
+        id_band: i32,
+        filter: FeedFilter,
+        paginator: Option<Paginator>,
+    ) -> Result<(Vec<EventInterface>, Paginator)> {
+        let client = self.0.get().await?;
+        let pag = paginator.unwrap_or_default();
+        let kinds: Option<Vec<String>> = if filter.kinds.is_empty() {
+            None
+        } else {
+            Some(filter.kinds.iter().map(|k| k.to_string()).collect())
+        };
+        let conditions = "
+            be.id_band = $1
+            AND ($2::text[] IS NULL OR CAST(be.kind AS TEXT) = ANY($2))
+            AND ($3::int IS NULL OR be.id_actor = $3)
+            AND ($4::int IS NULL OR be.id_org = $4)
+            AND ($5::timestamp IS NULL OR be.creation_stamp >= $5)
+        ";
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    SELECT
+                        be.id, be.id_band, CAST(be.kind AS VARCHAR(16)), be.id_actor,
+                        cu.pseudo, be.id_org, o.name, be.id_target,
+                        CAST(be.details AS TEXT), be.creation_stamp
+                    FROM band_event be
+                    LEFT JOIN cnm_user cu ON cu.id = be.id_actor
+                    LEFT JOIN org o ON o.id = be.id_org
+                    WHERE {}
+                    ORDER BY be.creation_stamp DESC, be.id DESC
+                    {}
+                    ",
+                    conditions, pag,
+                )
+                .as_str(),
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[&id_band, &kinds, &filter.actor, &filter.org, &filter.since],
+            )
+            .await?
+            .iter()
+            .filter_map(|row| EventInterface::try_from(row).ok())
+            .collect();
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "SELECT CAST(COUNT(*) AS INT) FROM band_event be WHERE {}",
+                    conditions
+                )
+                .as_str(),
+            )
+            .await?;
+        let count: i32 = client
+            .query(
+                &stmt,
+                &[&id_band, &kinds, &filter.actor, &filter.org, &filter.since],
+            )
+            .await?[0]
+            .get(0);
+        Ok((
+            rows,
+            Paginator {
+                page: pag.page,
+                size: pag.size,
+                page_count: if pag.size == 0 {
+                    None
+                } else {
+                    Some(count / pag.size)
+                },
+                item_count: Some(count),
+            },
+        ))
+    }
+}
@@ -1,23 +1,14 @@
 use std::fmt::Display;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::ToSql;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FilterType {
     Numeric,
     String,
 }
 
-impl From<String> for FilterType {
-    fn from(s: String) -> Self {
-        match s.as_str() {
-            "numeric" => Self::Numeric,
-            "string" => Self::String,
-            _ => Self::String,
-        }
-    }
-}
-
 impl Display for FilterType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -31,55 +22,66 @@ impl Display for FilterType {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FilterOp {
     Like,
     Exact,
 }
 
-impl From<String> for FilterOp {
-    fn from(s: String) -> Self {
+impl TryFrom<String> for FilterOp {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
         match s.as_str() {
-            "like" => Self::Like,
-            "exact" => Self::Exact,
-            _ => Self::Exact,
+            "like" => Ok(Self::Like),
+            "exact" => Ok(Self::Exact),
+            _ => Err("unknownFilterOp".to_string()),
         }
     }
 }
 
-impl FilterOp {
-    pub fn to_req<T: Display>(
-        &self,
-        key: String,
-        alias: Option<String>,
-        value: T,
-        t: FilterType,
-        like_start: bool,
-    ) -> String {
-        let enclose = match t {
-            FilterType::Numeric => "",
-            FilterType::String => "'",
-        };
-
-        let field = if let Some(al) = alias {
-            format!("{}.{}", al, key)
-        } else {
-            key
-        };
-        let prefix = if !like_start {
-            "%".to_string()
-        } else {
-            "".to_string()
-        };
+/// Columns of the org listings which can be filtered: table alias, key,
+/// column and type of the values.
+const FILTER_COLUMNS: &[(&str, &str, &str, FilterType)] = &[
+    ("o", "id", "o.id", FilterType::Numeric),
+    ("o", "name", "o.name", FilterType::String),
+    ("o", "name_bis", "o.name_bis", FilterType::String),
+    ("o", "description", "o.description", FilterType::String),
+    ("a", "id", "a.id", FilterType::Numeric),
+    ("a", "name", "a.name", FilterType::String),
+    ("a", "name_bis", "a.name_bis", FilterType::String),
+    ("a", "description", "a.description", FilterType::String),
+    ("a", "category", "a.category", FilterType::String),
+    ("a", "city", "a.city", FilterType::String),
+    ("a", "postal_code", "a.postal_code", FilterType::String),
+    ("oa", "status", "oa.status", FilterType::String),
+    ("oa", "id_user", "oa.id_user", FilterType::Numeric),
+    ("cu", "id", "cu.id", FilterType::Numeric),
+    ("cu", "pseudo", "cu.pseudo", FilterType::String),
+];
 
-        match self {
-            FilterOp::Exact => format!("{} = {}{}{}", field, enclose, value, enclose),
-            FilterOp::Like => format!("LOWER({}) LIKE LOWER('{}{}%')", field, prefix, value),
-        }
+/// Column of a filter key, the alias may be left out when no other table
+/// has a column of the same name.
+fn filter_column(key: &str, alias: Option<&str>) -> Option<(&'static str, FilterType)> {
+    let mut found = FILTER_COLUMNS
+        .iter()
+        .filter(|(a, k, _, _)| *k == key && alias.is_none_or(|alias| alias == *a));
+    match (found.next(), found.next()) {
+        (Some((_, _, column, t)), None) => Some((column, *t)),
+        _ => None,
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// Escapes the wildcards of a LIKE pattern.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// A filter as sent by clients and stored in saved searches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterIntermediate {
     key: String,
     alias: Option<String>,
@@ -91,45 +93,198 @@ pub struct FilterIntermediate {
     like_start: bool,
 }
 
+/// A filter on a known column, its value is bound as a query parameter.
 #[derive(Debug, Clone)]
 pub struct Filter {
-    key: String,
-    alias: Option<String>,
+    column: &'static str,
     op: FilterOp,
     filter_type: FilterType,
     value: String,
     like_start: bool,
 }
 
-impl From<FilterIntermediate> for Filter {
-    fn from(inter: FilterIntermediate) -> Self {
-        Filter {
-            key: inter.key,
-            alias: inter.alias,
-            op: FilterOp::from(inter.op),
-            filter_type: FilterType::from(inter.filter_type),
-            value: inter.value,
+/// Unknown keys and operators are rejected, the type of the value is the
+/// one of the column whatever the client said.
+impl TryFrom<FilterIntermediate> for Filter {
+    type Error = String;
+
+    fn try_from(inter: FilterIntermediate) -> Result<Self, Self::Error> {
+        let (column, filter_type) = filter_column(&inter.key, inter.alias.as_deref())
+            .ok_or_else(|| "unknownFilterKey".to_string())?;
+        let op = FilterOp::try_from(inter.op)?;
+        let value = match filter_type {
+            FilterType::Numeric if op == FilterOp::Exact => inter
+                .value
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| "invalidFilterValue".to_string())?
+                .to_string(),
+            _ => inter.value,
+        };
+        Ok(Filter {
+            column,
+            op,
+            filter_type,
+            value,
             like_start: inter.like_start,
-        }
+        })
     }
 }
 
 impl Filter {
-    pub fn gen_request_append(&self) -> String {
-        self.op.to_req(
-            self.key.clone(),
-            self.alias.clone(),
-            self.value.clone(),
-            self.filter_type,
-            self.like_start,
-        )
+    /// Condition on the column, the value being bound to `$index`.
+    fn gen_condition(&self, index: usize) -> String {
+        match (self.op, self.filter_type) {
+            (FilterOp::Exact, FilterType::Numeric) => {
+                format!("{} = CAST(${}::text AS INTEGER)", self.column, index)
+            }
+            (FilterOp::Exact, FilterType::String) => {
+                format!("CAST({} AS TEXT) = ${}::text", self.column, index)
+            }
+            (FilterOp::Like, _) => format!(
+                "LOWER(CAST({} AS TEXT)) LIKE LOWER(${}::text)",
+                self.column, index
+            ),
+        }
+    }
+
+    fn param(&self) -> String {
+        match self.op {
+            FilterOp::Exact => self.value.clone(),
+            FilterOp::Like => format!(
+                "{}{}%",
+                if self.like_start { "" } else { "%" },
+                escape_like(&self.value)
+            ),
+        }
     }
 }
 
-pub fn gen_request_search(filters: Vec<Filter>) -> String {
-    filters
+/// Conditions of the filters joined together, their values being bound
+/// after the `first` parameters of the query. Returns the conditions and
+/// the values to bind.
+pub fn gen_request_search(filters: &[Filter], first: usize) -> (String, Vec<String>) {
+    (
+        filters
+            .iter()
+            .enumerate()
+            .map(|(i, f)| f.gen_condition(first + i + 1))
+            .collect::<Vec<String>>()
+            .join(" AND "),
+        filters.iter().map(Filter::param).collect(),
+    )
+}
+
+/// Parameters of a query, the fixed ones followed by the filter values.
+pub fn query_params<'a>(
+    fixed: &[&'a (dyn ToSql + Sync)],
+    values: &'a [String],
+) -> Vec<&'a (dyn ToSql + Sync)> {
+    fixed
         .iter()
-        .map(|f| f.gen_request_append())
-        .collect::<Vec<String>>()
-        .join(" AND ")
+        .copied()
+        .chain(values.iter().map(|v| v as &(dyn ToSql + Sync)))
+        .collect()
+}
+
+/// Ordering of an org listing, keys are the serialized names of the listed
+/// fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sort {
+    pub key: String,
+    #[serde(default)]
+    pub desc: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort {
+            key: "name".to_string(),
+            desc: false,
+        }
+    }
+}
+
+impl Sort {
+    fn column(&self) -> Option<&'static str> {
+        match self.key.as_str() {
+            "name" => Some("o.name"),
+            "city" => Some("a.city"),
+            "zipCode" => Some("a.postal_code"),
+            "category" => Some("a.category"),
+            "status" => Some("oa.status"),
+            "creationStamp" => Some("o.creation_stamp"),
+            "lastContacted" => Some("lc.last_contacted"),
+            _ => None,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.column().is_some()
+    }
+
+    /// Unknown keys fall back to the org name.
+    pub fn gen_request_order(&self) -> String {
+        format!(
+            "ORDER BY {} {}, o.id",
+            self.column().unwrap_or("o.name"),
+            if self.desc { "DESC NULLS LAST" } else { "ASC" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(key: &str, alias: Option<&str>, op: &str, value: &str) -> FilterIntermediate {
+        FilterIntermediate {
+            key: key.to_string(),
+            alias: alias.map(str::to_string),
+            op: op.to_string(),
+            filter_type: "string".to_string(),
+            value: value.to_string(),
+            like_start: false,
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for f in [
+            filter("pwd", Some("cu"), "exact", "x"),
+            filter("name; DROP TABLE org; --", None, "exact", "x"),
+            filter("name", Some("o) OR (1=1"), "exact", "x"),
+            // Both org and activity have a name.
+            filter("name", None, "exact", "x"),
+        ] {
+            assert_eq!(Filter::try_from(f).unwrap_err(), "unknownFilterKey");
+        }
+        assert_eq!(
+            Filter::try_from(filter("city", None, "regex", "x")).unwrap_err(),
+            "unknownFilterOp"
+        );
+        assert_eq!(
+            Filter::try_from(filter("id", Some("o"), "exact", "1 OR 1=1")).unwrap_err(),
+            "invalidFilterValue"
+        );
+    }
+
+    #[test]
+    fn values_are_bound() {
+        let mut starts = filter("name", Some("o"), "like", "o'brien_%");
+        starts.like_start = true;
+        let filters = vec![
+            Filter::try_from(filter("city", None, "exact", "' OR ''='")).unwrap(),
+            Filter::try_from(starts).unwrap(),
+            Filter::try_from(filter("id_user", None, "exact", " 12 ")).unwrap(),
+        ];
+        let (conditions, values) = gen_request_search(&filters, 2);
+        assert_eq!(
+            conditions,
+            "CAST(a.city AS TEXT) = $3::text \
+             AND LOWER(CAST(o.name AS TEXT)) LIKE LOWER($4::text) \
+             AND oa.id_user = CAST($5::text AS INTEGER)"
+        );
+        assert_eq!(values, vec!["' OR ''='", "o'brien\\_\\%%", "12"]);
+    }
 }
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+
+/// Pending authorization request, kept until the provider calls us back.
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub code_challenge: String,
+    pub id_user: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityInterface {
+    pub id: i32,
+    pub issuer: String,
+    pub email: Option<String>,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+pub struct Identity(Pool);
+
+impl Identity {
+    pub fn new(pool: Pool) -> Self {
+        Identity(pool)
+    }
+
+    /// Generates state, nonce and PKCE verifier for a new login. `id_user`
+    /// is set when an already authenticated user links an identity.
+    pub async fn start_login(&self, id_user: Option<i32>) -> Result<PendingLogin> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO oidc_login(state, nonce, code_verifier, id_user)
+                VALUES (
+                    encode(gen_random_bytes(32), 'hex'),
+                    encode(gen_random_bytes(16), 'hex'),
+                    encode(gen_random_bytes(48), 'hex'),
+                    $1
+                )
+                RETURNING
+                    state,
+                    nonce,
+                    code_verifier,
+                    encode(digest(code_verifier::text, 'sha256'), 'base64')
+            ",
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&id_user]).await?;
+        let challenge: String = rows[0].get(3);
+
+        Ok(PendingLogin {
+            state: rows[0].get(0),
+            nonce: rows[0].get(1),
+            code_verifier: rows[0].get(2),
+            // PKCE wants the unpadded base64url variant
+            code_challenge: challenge
+                .trim_end_matches('=')
+                .replace('+', "-")
+                .replace('/', "_"),
+            id_user,
+        })
+    }
+
+    /// Consumes a pending login, returns None for unknown or stale states.
+    pub async fn take_login(&self, state: String) -> Result<Option<PendingLogin>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "DELETE FROM oidc_login WHERE creation_stamp < CURRENT_TIMESTAMP - INTERVAL '10 minutes'",
+            )
+            .await?;
+        client.query(&stmt, &[]).await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                DELETE FROM oidc_login WHERE state = $1
+                RETURNING state, nonce, code_verifier, id_user
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&state])
+            .await?
+            .first()
+            .map(|row| PendingLogin {
+                state: row.get(0),
+                nonce: row.get(1),
+                code_verifier: row.get(2),
+                code_challenge: String::new(),
+                id_user: row.get(3),
+            }))
+    }
+
+    pub async fn find_user(&self, issuer: String, subject: String) -> Result<Option<i32>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT id_user FROM user_identity WHERE issuer = $1 AND subject = $2")
+            .await?;
+        Ok(client
+            .query(&stmt, &[&issuer, &subject])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+
+    pub async fn link(
+        &self,
+        id_user: i32,
+        issuer: String,
+        subject: String,
+        email: Option<String>,
+    ) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO user_identity(id_user, issuer, subject, email)
+                VALUES ($1, $2, $3, $4)
+            ",
+            )
+            .await?;
+        client
+            .query(&stmt, &[&id_user, &issuer, &subject, &email])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list(&self, id_user: i32) -> Result<Vec<IdentityInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "SELECT id, issuer, email, creation_stamp FROM user_identity WHERE id_user = $1",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user])
+            .await?
+            .iter()
+            .map(|row| IdentityInterface {
+                id: row.get(0),
+                issuer: row.get(1),
+                email: row.get(2),
+                creation_stamp: row.get(3),
+            })
+            .collect())
+    }
+
+    pub async fn unlink(&self, id_user: i32, id: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("DELETE FROM user_identity WHERE id = $1 AND id_user = $2 RETURNING id")
+            .await?;
+        Ok(!client.query(&stmt, &[&id, &id_user]).await?.is_empty())
+    }
+}
@@ -0,0 +1,499 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::paginator::Paginator;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InteractionKind {
+    #[serde(rename = "call")]
+    Call,
+    #[serde(rename = "email")]
+    Email,
+    #[serde(rename = "meeting")]
+    Meeting,
+    #[serde(rename = "message")]
+    Message,
+    #[serde(rename = "other")]
+    Other,
+}
+
+impl TryFrom<String> for InteractionKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_ref() {
+            "call" => Ok(Self::Call),
+            "email" => Ok(Self::Email),
+            "meeting" => Ok(Self::Meeting),
+            "message" => Ok(Self::Message),
+            "other" => Ok(Self::Other),
+            _ => Err("unknownInteractionKind".to_string()),
+        }
+    }
+}
+
+impl Display for InteractionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                InteractionKind::Call => "call",
+                InteractionKind::Email => "email",
+                InteractionKind::Meeting => "meeting",
+                InteractionKind::Message => "message",
+                InteractionKind::Other => "other",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    #[serde(rename = "inbound")]
+    Inbound,
+    #[serde(rename = "outbound")]
+    Outbound,
+}
+
+impl From<String> for Direction {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "inbound" => Self::Inbound,
+            _ => Self::Outbound,
+        }
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Direction::Inbound => "inbound",
+                Direction::Outbound => "outbound",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    #[serde(rename = "reached")]
+    Reached,
+    #[serde(rename = "no_answer")]
+    NoAnswer,
+    #[serde(rename = "left_message")]
+    LeftMessage,
+    #[serde(rename = "follow_up")]
+    FollowUp,
+    #[serde(rename = "booked")]
+    Booked,
+    #[serde(rename = "declined")]
+    Declined,
+}
+
+impl From<String> for Outcome {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "no_answer" => Self::NoAnswer,
+            "left_message" => Self::LeftMessage,
+            "follow_up" => Self::FollowUp,
+            "booked" => Self::Booked,
+            "declined" => Self::Declined,
+            _ => Self::Reached,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Outcome::Reached => "reached",
+                Outcome::NoAnswer => "no_answer",
+                Outcome::LeftMessage => "left_message",
+                Outcome::FollowUp => "follow_up",
+                Outcome::Booked => "booked",
+                Outcome::Declined => "declined",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    #[serde(rename = "orgName")]
+    pub org_name: String,
+    #[serde(rename = "idContact")]
+    pub id_contact: Option<i32>,
+    #[serde(rename = "contactName")]
+    pub contact_name: Option<String>,
+    #[serde(rename = "userId")]
+    pub user_id: Option<i32>,
+    #[serde(rename = "userPseudo")]
+    pub user_pseudo: Option<String>,
+    pub kind: InteractionKind,
+    pub direction: Direction,
+    pub outcome: Option<Outcome>,
+    pub summary: Option<String>,
+    #[serde(rename = "happenedStamp")]
+    pub happened_stamp: NaiveDateTime,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl TryFrom<&Row> for InteractionInterface {
+    type Error = String;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        let kind: String = row.get(8);
+        let direction: String = row.get(9);
+        let outcome: Option<String> = row.get(10);
+        Ok(InteractionInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            id_org: row.get(2),
+            org_name: row.get(3),
+            id_contact: row.get(4),
+            contact_name: row.get(5),
+            user_id: row.get(6),
+            user_pseudo: row.get(7),
+            kind: InteractionKind::try_from(kind)?,
+            direction: Direction::from(direction),
+            outcome: outcome.map(Outcome::from),
+            summary: row.get(11),
+            happened_stamp: row.get(12),
+            creation_stamp: row.get(13),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InteractionRequest {
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    #[serde(rename = "idContact")]
+    pub id_contact: Option<i32>,
+    pub kind: InteractionKind,
+    pub direction: Direction,
+    pub outcome: Option<Outcome>,
+    pub summary: Option<String>,
+    /// Now when missing.
+    #[serde(rename = "happenedStamp")]
+    pub happened_stamp: Option<NaiveDateTime>,
+}
+
+/// Narrows an interaction listing, empty criteria match every interaction.
+#[derive(Debug, Clone, Default)]
+pub struct InteractionFilter {
+    pub org: Option<i32>,
+    pub contact: Option<i32>,
+    pub user: Option<i32>,
+    pub kinds: Vec<InteractionKind>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+/// Last contacted criteria of an org listing, on the orgs which have been
+/// contacted in a range of dates or never been.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactedFilter {
+    pub after: Option<NaiveDateTime>,
+    pub before: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub never: bool,
+}
+
+impl ContactedFilter {
+    pub fn gen_request(&self) -> Option<String> {
+        if self.never {
+            return Some("lc.last_contacted IS NULL".to_string());
+        }
+        let conditions: Vec<String> = [
+            self.after
+                .map(|a| format!("lc.last_contacted >= '{}'", a.format("%Y-%m-%d %H:%M:%S"))),
+            self.before
+                .map(|b| format!("lc.last_contacted < '{}'", b.format("%Y-%m-%d %H:%M:%S"))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if conditions.is_empty() {
+            None
+        } else {
+            Some(conditions.join(" AND "))
+        }
+    }
+}
+
+/// Join giving `lc.last_contacted`, the date of the latest interaction of
+/// the band with each org.
+pub fn gen_last_contact_join(id_band: i32) -> String {
+    format!(
+        "
+        LEFT JOIN (
+            SELECT id_org, MAX(happened_stamp) AS last_contacted
+            FROM interaction
+            WHERE id_band = {}
+            GROUP BY id_org
+        ) lc ON lc.id_org = o.id
+        ",
+        id_band
+    )
+}
+
+pub struct Interaction(Pool);
+
+const SELECT_INTERACTION: &str = "
+    SELECT
+        i.id, i.id_band, i.id_org, o.name, i.id_contact,
+        CASE WHEN c.id IS NULL THEN NULL
+            ELSE TRIM(COALESCE(c.firstname, '') || ' ' || c.name)
+        END,
+        i.id_user, cu.pseudo,
+        CAST(i.kind AS VARCHAR(16)), CAST(i.direction AS VARCHAR(16)),
+        CAST(i.outcome AS VARCHAR(16)), i.summary, i.happened_stamp, i.creation_stamp
+    FROM interaction i
+    JOIN org o ON o.id = i.id_org
+    LEFT JOIN contact c ON c.id = i.id_contact
+    LEFT JOIN cnm_user cu ON cu.id = i.id_user
+";
+
+impl Interaction {
+    pub fn new(pool: Pool) -> Self {
+        Interaction(pool)
+    }
+
+    pub async fn get(&self, id: i32, id_band: i32) -> Result<Option<InteractionInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!("{} WHERE i.id = $1 AND i.id_band = $2", SELECT_INTERACTION).as_str(),
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .and_then(|row| InteractionInterface::try_from(row).ok()))
+    }
+
+    pub async fn create(
+        &self,
+        id_band: i32,
+        id_user: i32,
+        interaction: InteractionRequest,
+    ) -> Result<InteractionInterface> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO interaction(
+                    id_band, id_org, id_contact, id_user, kind, direction,
+                    outcome, summary, happened_stamp)
+                VALUES (
+                    $1, $2, $3, $4, $5::text::interaction_kind,
+                    $6::text::interaction_direction, $7::text::interaction_outcome, $8,
+                    COALESCE($9::timestamp, CURRENT_TIMESTAMP))
+                RETURNING id
+            ",
+            )
+            .await?;
+        let id: i32 = client
+            .query_one(
+                &stmt,
+                &[
+                    &id_band,
+                    &interaction.id_org,
+                    &interaction.id_contact,
+                    &id_user,
+                    &interaction.kind.to_string(),
+                    &interaction.direction.to_string(),
+                    &interaction.outcome.map(|o| o.to_string()),
+                    &interaction.summary,
+                    &interaction.happened_stamp,
+                ],
+            )
+            .await?
+            .get(0);
+        self.get(id, id_band)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("interaction {} vanished", id))
+    }
+
+    /// Only the author may rewrite an interaction.
+    pub async fn update(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_user: i32,
+        interaction: InteractionRequest,
+    ) -> Result<Option<InteractionInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE interaction
+                SET
+                    id_org = $4,
+                    id_contact = $5,
+                    kind = $6::text::interaction_kind,
+                    direction = $7::text::interaction_direction,
+                    outcome = $8::text::interaction_outcome,
+                    summary = $9,
+                    happened_stamp = COALESCE($10, happened_stamp)
+                WHERE id = $1 AND id_band = $2 AND id_user = $3
+            ",
+            )
+            .await?;
+        let updated = client
+            .execute(
+                &stmt,
+                &[
+                    &id,
+                    &id_band,
+                    &id_user,
+                    &interaction.id_org,
+                    &interaction.id_contact,
+                    &interaction.kind.to_string(),
+                    &interaction.direction.to_string(),
+                    &interaction.outcome.map(|o| o.to_string()),
+                    &interaction.summary,
+                    &interaction.happened_stamp,
+                ],
+            )
+            .await?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        self.get(id, id_band).await
+    }
+
+    /// Interactions with any of the contacts or logged by the user, in
+    /// every band.
+    pub async fn for_subject(
+        &self,
+        contacts: &[i32],
+        id_user: Option<i32>,
+    ) -> Result<Vec<InteractionInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    WHERE i.id_contact = ANY($1) OR i.id_user = $2
+                    ORDER BY i.happened_stamp DESC, i.id DESC
+                    ",
+                    SELECT_INTERACTION
+                )
+                .as_str(),
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&contacts, &id_user])
+            .await?
+            .iter()
+            .filter_map(|row| InteractionInterface::try_from(row).ok())
+            .collect())
+    }
+
+    pub async fn delete(&self, id: i32, id_band: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("DELETE FROM interaction WHERE id = $1 AND id_band = $2")
+            .await?;
+        Ok(client.execute(&stmt, &[&id, &id_band]).await? > 0)
+    }
+
+    /// Interactions of the band, latest first.
+    pub async fn list(
+        &self,
+        id_band: i32,
+        filter: InteractionFilter,
+        paginator: Option<Paginator>,
+    ) -> Result<(Vec<InteractionInterface>, Paginator)> {
+        let client = self.0.get().await?;
+        let pag = paginator.unwrap_or_default();
+        let kinds: Option<Vec<String>> = if filter.kinds.is_empty() {
+            None
+        } else {
+            Some(filter.kinds.iter().map(|k| k.to_string()).collect())
+        };
+        let conditions = "
+            i.id_band = $1
+            AND ($2::int IS NULL OR i.id_org = $2)
+            AND ($3::int IS NULL OR i.id_contact = $3)
+            AND ($4::int IS NULL OR i.id_user = $4)
+            AND ($5::text[] IS NULL OR CAST(i.kind AS TEXT) = ANY($5))
+            AND ($6::timestamp IS NULL OR i.happened_stamp >= $6)
+            AND ($7::timestamp IS NULL OR i.happened_stamp < $7)
+        ";
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    WHERE {}
+                    ORDER BY i.happened_stamp DESC, i.id DESC
+                    {}
+                    ",
+                    SELECT_INTERACTION, conditions, pag,
+                )
+                .as_str(),
+            )
+            .await?;
+        let params: [&(dyn tokio_postgres::types::ToSql + Sync); 7] = [
+            &id_band,
+            &filter.org,
+            &filter.contact,
+            &filter.user,
+            &kinds,
+            &filter.since,
+            &filter.until,
+        ];
+        let rows = client
+            .query(&stmt, &params)
+            .await?
+            .iter()
+            .filter_map(|row| InteractionInterface::try_from(row).ok())
+            .collect();
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "SELECT CAST(COUNT(*) AS INT) FROM interaction i WHERE {}",
+                    conditions
+                )
+                .as_str(),
+            )
+            .await?;
+        let count: i32 = client.query(&stmt, &params).await?[0].get(0);
+        Ok((
+            rows,
+            Paginator {
+                page: pag.page,
+                size: pag.size,
+                page_count: if pag.size == 0 {
+                    None
+                } else {
+                    Some(count / pag.size)
+                },
+                item_count: Some(count),
+            },
+        ))
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvitationStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "accepted")]
+    Accepted,
+    #[serde(rename = "declined")]
+    Declined,
+    #[serde(rename = "revoked")]
+    Revoked,
+}
+
+impl From<String> for InvitationStatus {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "accepted" => Self::Accepted,
+            "declined" => Self::Declined,
+            "revoked" => Self::Revoked,
+            _ => Self::Pending,
+        }
+    }
+}
+
+impl Display for InvitationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                InvitationStatus::Pending => "pending",
+                InvitationStatus::Accepted => "accepted",
+                InvitationStatus::Declined => "declined",
+                InvitationStatus::Revoked => "revoked",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "bandName")]
+    pub band_name: Option<String>,
+    pub email: String,
+    #[serde(rename = "idRole")]
+    pub id_role: i32,
+    pub role: String,
+    pub status: InvitationStatus,
+    #[serde(rename = "inviterPseudo")]
+    pub inviter_pseudo: String,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+    #[serde(rename = "expiryStamp")]
+    pub expiry_stamp: NaiveDateTime,
+}
+
+impl From<&Row> for InvitationInterface {
+    fn from(row: &Row) -> Self {
+        let status: String = row.get(6);
+        InvitationInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            band_name: row.get(2),
+            email: row.get(3),
+            id_role: row.get(4),
+            role: row.get(5),
+            status: InvitationStatus::from(status),
+            inviter_pseudo: row.get(7),
+            creation_stamp: row.get(8),
+            expiry_stamp: row.get(9),
+        }
+    }
+}
+
+const INVITATION_SELECT: &str = "
+    SELECT
+        bi.id,
+        bi.id_band,
+        b.name,
+        bi.email,
+        bi.id_role,
+        br.name,
+        CAST(bi.status AS VARCHAR(16)),
+        cu.pseudo,
+        bi.creation_stamp,
+        bi.expiry_stamp
+    FROM band_invitation bi
+    JOIN band b ON b.id = bi.id_band
+    JOIN cnm_user cu ON cu.id = bi.id_inviter
+    JOIN band_role br ON br.id = bi.id_role
+";
+
+pub struct Invitation(Pool);
+
+impl Invitation {
+    pub fn new(pool: Pool) -> Self {
+        Invitation(pool)
+    }
+
+    /// Creates an invitation, revoking any pending one for the same email
+    /// and band. Returns the new invitation id and its clear token.
+    pub async fn create(
+        &self,
+        id_band: i32,
+        id_inviter: i32,
+        email: String,
+        id_role: i32,
+        ttl_minutes: i32,
+    ) -> Result<(i32, String)> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band_invitation SET status = 'revoked', answer_stamp = CURRENT_TIMESTAMP
+                WHERE id_band = $1 AND LOWER(email) = LOWER($2) AND status = 'pending'
+            ",
+            )
+            .await?;
+        client.query(&stmt, &[&id_band, &email]).await?;
+        let stmt = client
+            .prepare_cached("SELECT encode(gen_random_bytes(32), 'hex')")
+            .await?;
+        let token: String = client.query(&stmt, &[]).await?[0].get(0);
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO band_invitation(id_band, id_inviter, email, id_role, token_hash, expiry_stamp)
+                VALUES (
+                    $1, $2, $3, $4,
+                    encode(digest($5::text, 'sha256'), 'hex'),
+                    CURRENT_TIMESTAMP + make_interval(mins => $6)
+                )
+                RETURNING id
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[
+                    &id_band,
+                    &id_inviter,
+                    &email,
+                    &id_role,
+                    &token,
+                    &ttl_minutes,
+                ],
+            )
+            .await?;
+        Ok((rows[0].get(0), token))
+    }
+
+    pub async fn get(&self, id: i32) -> Result<Option<InvitationInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(&format!("{} WHERE bi.id = $1", INVITATION_SELECT))
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id])
+            .await?
+            .first()
+            .map(InvitationInterface::from))
+    }
+
+    pub async fn list_pending(&self, id_band: i32) -> Result<Vec<InvitationInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(&format!(
+                "{} WHERE bi.id_band = $1 AND bi.status = 'pending' ORDER BY bi.creation_stamp DESC",
+                INVITATION_SELECT
+            ))
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(InvitationInterface::from)
+            .collect())
+    }
+
+    /// Issues a fresh token and expiry for a pending invitation, the
+    /// previous link stops working.
+    pub async fn renew(&self, id: i32, id_band: i32, ttl_minutes: i32) -> Result<Option<String>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT encode(gen_random_bytes(32), 'hex')")
+            .await?;
+        let token: String = client.query(&stmt, &[]).await?[0].get(0);
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band_invitation
+                SET
+                    token_hash = encode(digest($3::text, 'sha256'), 'hex'),
+                    expiry_stamp = CURRENT_TIMESTAMP + make_interval(mins => $4)
+                WHERE id = $1 AND id_band = $2 AND status = 'pending'
+                RETURNING id
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(&stmt, &[&id, &id_band, &token, &ttl_minutes])
+            .await?;
+        Ok(if rows.is_empty() { None } else { Some(token) })
+    }
+
+    pub async fn revoke(&self, id: i32, id_band: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band_invitation SET status = 'revoked', answer_stamp = CURRENT_TIMESTAMP
+                WHERE id = $1 AND id_band = $2 AND status = 'pending'
+                RETURNING id
+            ",
+            )
+            .await?;
+        Ok(!client.query(&stmt, &[&id, &id_band]).await?.is_empty())
+    }
+
+    /// Invitations sent to an email, whatever their status.
+    pub async fn for_email(&self, email: &str) -> Result<Vec<InvitationInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(&format!(
+                "
+                {}
+                WHERE LOWER(bi.email) = LOWER($1)
+                ORDER BY bi.creation_stamp DESC
+                ",
+                INVITATION_SELECT
+            ))
+            .await?;
+        Ok(client
+            .query(&stmt, &[&email])
+            .await?
+            .iter()
+            .map(InvitationInterface::from)
+            .collect())
+    }
+
+    /// Returns the invitation matching a clear token, as long as it is still
+    /// pending and has not expired.
+    pub async fn find_pending(
+        &self,
+        id: i32,
+        token: String,
+    ) -> Result<Option<InvitationInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(&format!(
+                "
+                {}
+                WHERE bi.id = $1
+                    AND bi.token_hash = encode(digest($2::text, 'sha256'), 'hex')
+                    AND bi.status = 'pending'
+                    AND bi.expiry_stamp > CURRENT_TIMESTAMP
+                ",
+                INVITATION_SELECT
+            ))
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &token])
+            .await?
+            .first()
+            .map(InvitationInterface::from))
+    }
+
+    pub async fn answer(&self, id: i32, status: InvitationStatus) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band_invitation
+                SET status = $2::text::invitation_status, answer_stamp = CURRENT_TIMESTAMP
+                WHERE id = $1 AND status = 'pending'
+            ",
+            )
+            .await?;
+        client.query(&stmt, &[&id, &status.to_string()]).await?;
+        Ok(())
+    }
+}
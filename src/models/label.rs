@@ -0,0 +1,207 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    pub name: String,
+    pub color: String,
+    #[serde(rename = "orgCount")]
+    pub org_count: i32,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl From<&Row> for LabelInterface {
+    fn from(row: &Row) -> Self {
+        LabelInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            name: row.get(2),
+            color: row.get(3),
+            org_count: row.get(4),
+            creation_stamp: row.get(5),
+        }
+    }
+}
+
+/// Colours are stored as `#rrggbb`.
+pub fn is_color(s: &str) -> bool {
+    s.len() == 7 && s.starts_with('#') && s[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// SQL condition keeping the orgs carrying every given label of the band,
+/// to be appended to a query joining `org o`.
+pub fn gen_label_filter(id_band: i32, labels: &[i32]) -> Option<String> {
+    if labels.is_empty() {
+        return None;
+    }
+    let mut labels = labels.to_vec();
+    labels.sort_unstable();
+    labels.dedup();
+    Some(format!(
+        "
+        o.id IN (
+            SELECT ol.id_org
+            FROM org_label ol
+            JOIN band_label bl ON bl.id = ol.id_label
+            WHERE bl.id_band = {} AND ol.id_label IN ({})
+            GROUP BY ol.id_org
+            HAVING COUNT(DISTINCT ol.id_label) = {}
+        )",
+        id_band,
+        labels
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>()
+            .join(", "),
+        labels.len(),
+    ))
+}
+
+/// Parses the comma separated label ids given along org listings.
+pub fn parse_labels(labels: Option<String>) -> Vec<i32> {
+    labels
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|l| l.trim().parse().ok())
+        .collect()
+}
+
+pub struct Label(Pool);
+
+impl Label {
+    pub fn new(pool: Pool) -> Self {
+        Label(pool)
+    }
+
+    pub async fn list(&self, id_band: i32) -> Result<Vec<LabelInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    bl.id, bl.id_band, bl.name, bl.color,
+                    CAST(COUNT(ol.id_org) AS INT), bl.creation_stamp
+                FROM band_label bl
+                LEFT JOIN org_label ol ON ol.id_label = bl.id
+                WHERE bl.id_band = $1
+                GROUP BY bl.id
+                ORDER BY bl.name
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(LabelInterface::from)
+            .collect())
+    }
+
+    pub async fn create(
+        &self,
+        id_band: i32,
+        name: String,
+        color: String,
+    ) -> Result<LabelInterface> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO band_label(id_band, name, color)
+                VALUES ($1, $2, $3)
+                RETURNING id, id_band, name, color, 0, creation_stamp
+            ",
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&id_band, &name, &color]).await?;
+        Ok(LabelInterface::from(&rows[0]))
+    }
+
+    pub async fn update(
+        &self,
+        id: i32,
+        id_band: i32,
+        name: String,
+        color: String,
+    ) -> Result<Option<LabelInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band_label SET name = $3, color = $4
+                WHERE id = $1 AND id_band = $2
+                RETURNING
+                    id, id_band, name, color,
+                    (SELECT CAST(COUNT(*) AS INT) FROM org_label WHERE id_label = $1),
+                    creation_stamp
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band, &name, &color])
+            .await?
+            .first()
+            .map(LabelInterface::from))
+    }
+
+    pub async fn delete(&self, id: i32, id_band: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("DELETE FROM band_label WHERE id = $1 AND id_band = $2 RETURNING id")
+            .await?;
+        Ok(!client.query(&stmt, &[&id, &id_band]).await?.is_empty())
+    }
+
+    /// Puts the label on the orgs, the ones already carrying it are skipped.
+    pub async fn attach(&self, id: i32, orgs: Vec<i32>) -> Result<u64> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO org_label(id_label, id_org)
+                SELECT $1, unnest($2::int[])
+                ON CONFLICT DO NOTHING
+            ",
+            )
+            .await?;
+        Ok(client.execute(&stmt, &[&id, &orgs]).await?)
+    }
+
+    pub async fn detach(&self, id: i32, orgs: Vec<i32>) -> Result<u64> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("DELETE FROM org_label WHERE id_label = $1 AND id_org = ANY($2)")
+            .await?;
+        Ok(client.execute(&stmt, &[&id, &orgs]).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_labels_are_counted_once() {
+        let filter = gen_label_filter(3, &[7, 5, 7]).unwrap();
+        assert!(filter.contains("ol.id_label IN (5, 7)"));
+        assert!(filter.contains("HAVING COUNT(DISTINCT ol.id_label) = 2"));
+        assert_eq!(gen_label_filter(3, &[]), None);
+    }
+
+    #[test]
+    fn label_ids_are_parsed_leniently() {
+        assert_eq!(
+            parse_labels(Some(" 4, 12,,x,-1 ".to_string())),
+            vec![4, 12, -1]
+        );
+        assert!(parse_labels(Some(String::new())).is_empty());
+        assert!(parse_labels(None).is_empty());
+    }
+}
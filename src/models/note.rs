@@ -1,17 +1,233 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use chrono::NaiveDateTime;
-use deadpool_postgres::Pool;
+use deadpool_postgres::{Pool, Transaction};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio_postgres::Row;
+
+use super::{
+    event::{self, EventKind},
+    user::UserInterface,
+};
+use crate::{markdown, paginator::Paginator};
+
+/// Longest reaction accepted, in characters.
+const MAX_REACTION_LENGTH: usize = 16;
 
-use super::user::UserInterface;
+/// Members who reacted to a note with the same emoji.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteReaction {
+    pub emoji: String,
+    pub users: Vec<i32>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NoteInterface {
     pub id: i32,
+    /// Markdown source.
     pub note: String,
+    /// Sanitised rendering of the source.
+    pub html: String,
+    /// Band members mentioned in the note.
+    pub mentions: Vec<i32>,
     pub user: Option<UserInterface>,
     #[serde(rename = "creationStamp")]
     pub creation_stamp: NaiveDateTime,
+    /// Date of the latest edit, none when the note was never edited.
+    #[serde(rename = "editedStamp")]
+    pub edited_stamp: Option<NaiveDateTime>,
+    #[serde(rename = "revisionCount")]
+    pub revision_count: i64,
+    #[serde(rename = "deletedStamp")]
+    pub deleted_stamp: Option<NaiveDateTime>,
+    #[serde(rename = "deletedBy")]
+    pub deleted_by: Option<i32>,
+    /// Note answered by this one, none for the start of a thread.
+    #[serde(rename = "idParent")]
+    pub id_parent: Option<i32>,
+    #[serde(rename = "pinnedStamp")]
+    pub pinned_stamp: Option<NaiveDateTime>,
+    #[serde(rename = "pinnedBy")]
+    pub pinned_by: Option<i32>,
+    pub reactions: Vec<NoteReaction>,
+    /// Answers to the note, oldest first. Only filled when reading threads.
+    #[serde(default)]
+    pub replies: Vec<NoteInterface>,
+}
+
+/// Reads the columns of `gen_note_select`.
+impl From<&Row> for NoteInterface {
+    fn from(r: &Row) -> Self {
+        NoteInterface {
+            id: r.get(0),
+            note: r.get(1),
+            html: stored_html(r.get(1), r.get(11)),
+            mentions: r.get(12),
+            creation_stamp: r.get(2),
+            user: Some(UserInterface {
+                id: r.get(3),
+                pseudo: r.get(4),
+                name: r.get(5),
+                firstname: r.get(6),
+                email: r.get(7),
+                creation_stamp: r.get(8),
+                last_login: r.get(9),
+                verified: r.get(10),
+                is_admin: None,
+                id_role: None,
+                role: None,
+            }),
+            edited_stamp: r.get(13),
+            revision_count: r.get(14),
+            deleted_stamp: r.get(15),
+            deleted_by: r.get(16),
+            id_parent: r.get(17),
+            pinned_stamp: r.get(18),
+            pinned_by: r.get(19),
+            reactions: serde_json::from_str(r.get(20)).unwrap_or_default(),
+            replies: vec![],
+        }
+    }
+}
+
+/// A version of a note, the first one being the note as created.
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteRevisionInterface {
+    pub id: i32,
+    pub note: String,
+    pub html: String,
+    #[serde(rename = "userId")]
+    pub user_id: Option<i32>,
+    #[serde(rename = "userPseudo")]
+    pub user_pseudo: Option<String>,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+/// Notes of the band matching `condition`, with their author.
+fn gen_note_select(condition: &str) -> String {
+    format!(
+        "
+        SELECT 
+            n.id, n.note, n.creation_stamp,
+            cu.id, cu.pseudo, cu.name, cu.firstname,
+            cu.email, cu.creation_stamp, cu.last_login,
+            cu.verified, n.html,
+            ARRAY(SELECT id_user FROM note_mention WHERE id_note = n.id ORDER BY id_user),
+            n.edited_stamp,
+            (SELECT COUNT(*) FROM note_revision WHERE id_note = n.id),
+            n.deleted_stamp, n.id_deleter,
+            n.id_parent, n.pinned_stamp, n.id_pinner,
+            CAST(COALESCE((
+                SELECT json_agg(json_build_object('emoji', r.emoji, 'users', r.users) ORDER BY r.first)
+                FROM (
+                    SELECT emoji, array_agg(id_user ORDER BY creation_stamp) AS users,
+                        MIN(creation_stamp) AS first
+                    FROM note_reaction
+                    WHERE id_note = n.id
+                    GROUP BY emoji
+                ) r
+            ), '[]') AS TEXT)
+        FROM note n
+        JOIN cnm_user cu ON cu.id = n.id_user
+        WHERE {}
+        ",
+        condition
+    )
+}
+
+/// Hangs the replies under the note `id_parent`, recursively.
+fn nest(id_parent: i32, replies: &mut HashMap<i32, Vec<NoteInterface>>) -> Vec<NoteInterface> {
+    let mut children = replies.remove(&id_parent).unwrap_or_default();
+    for child in children.iter_mut() {
+        child.replies = nest(child.id, replies);
+    }
+    children
+}
+
+/// A reaction is a short emoji or word, without spaces.
+pub fn valid_reaction(emoji: &str) -> bool {
+    !emoji.is_empty()
+        && emoji.chars().count() <= MAX_REACTION_LENGTH
+        && !emoji.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Notes written before Markdown support have no stored rendering.
+pub fn stored_html(note: &str, html: Option<String>) -> String {
+    html.unwrap_or_else(|| markdown::render(note, &HashMap::new()))
+}
+
+/// Records a change of a note in the band feed, with the org of its
+/// activity.
+async fn record_event(
+    transaction: &Transaction<'_>,
+    id: i32,
+    id_band: i32,
+    id_actor: i32,
+    kind: EventKind,
+    details: Value,
+) -> Result<()> {
+    let stmt = transaction
+        .prepare_cached(
+            "SELECT a.id_org FROM note n JOIN activity a ON a.id = n.id_activity WHERE n.id = $1",
+        )
+        .await?;
+    let id_org = transaction
+        .query(&stmt, &[&id])
+        .await?
+        .first()
+        .map(|row| row.get(0));
+    event::record(
+        transaction,
+        id_band,
+        Some(id_actor),
+        kind,
+        id_org,
+        Some(id),
+        details,
+    )
+    .await
+}
+
+/// Replaces the members mentioned in the note.
+async fn save_mentions(
+    transaction: &Transaction<'_>,
+    id_note: i32,
+    mentions: &[i32],
+) -> Result<()> {
+    let stmt = transaction
+        .prepare_cached("DELETE FROM note_mention WHERE id_note = $1")
+        .await?;
+    transaction.execute(&stmt, &[&id_note]).await?;
+    let stmt = transaction
+        .prepare_cached("INSERT INTO note_mention(id_note, id_user) SELECT $1, UNNEST($2::int[])")
+        .await?;
+    transaction.execute(&stmt, &[&id_note, &mentions]).await?;
+    Ok(())
+}
+
+/// Keeps a revision of the note, returns the number of revisions.
+async fn add_revision(
+    transaction: &Transaction<'_>,
+    id_note: i32,
+    id_user: i32,
+    note: &str,
+    html: &str,
+) -> Result<i64> {
+    let stmt = transaction
+        .prepare_cached(
+            "INSERT INTO note_revision(id_note, id_user, note, html) VALUES ($1, $2, $3, $4)",
+        )
+        .await?;
+    transaction
+        .execute(&stmt, &[&id_note, &id_user, &note, &html])
+        .await?;
+    let stmt = transaction
+        .prepare_cached("SELECT COUNT(*) FROM note_revision WHERE id_note = $1")
+        .await?;
+    Ok(transaction.query_one(&stmt, &[&id_note]).await?.get(0))
 }
 
 pub struct Note(Pool);
@@ -21,26 +237,89 @@ impl Note {
         Note(pool)
     }
 
+    /// Renders the note, resolving its mentions among the band members.
+    /// Returns the HTML and the ids of the mentioned members.
+    async fn render(&self, id_band: i32, note: &str) -> Result<(String, Vec<i32>)> {
+        let pseudos = markdown::mentions(note);
+        let members: HashMap<String, i32> = if pseudos.is_empty() {
+            HashMap::new()
+        } else {
+            let client = self.0.get().await?;
+            let stmt = client
+                .prepare_cached(
+                    "
+                    SELECT LOWER(cu.pseudo), cu.id
+                    FROM user_band ub
+                    JOIN cnm_user cu ON cu.id = ub.id_user
+                    WHERE ub.id_band = $1 AND LOWER(cu.pseudo) = ANY($2)
+                ",
+                )
+                .await?;
+            client
+                .query(&stmt, &[&id_band, &pseudos])
+                .await?
+                .iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect()
+        };
+        let html = markdown::render(note, &members);
+        let mut mentions: Vec<i32> = members.into_values().collect();
+        mentions.sort_unstable();
+        mentions.dedup();
+        Ok((html, mentions))
+    }
+
+    async fn get(&self, id: i32, id_band: i32) -> Result<Option<NoteInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(gen_note_select("n.id = $1 AND n.id_band = $2").as_str())
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(NoteInterface::from))
+    }
+
     pub async fn create(
         &self,
         id_user: i32,
         id_band: i32,
         id_activity: i32,
+        id_parent: Option<i32>,
         note: String,
     ) -> Result<NoteInterface> {
-        let client = self.0.get().await?;
-        let stmt = client
+        let (html, mentions) = self.render(id_band, &note).await?;
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
             .prepare_cached(
                 "
-                INSERT INTO note(id_user, id_band, id_activity, note) 
-                VALUES($1, $2, $3, $4)
+                INSERT INTO note(id_user, id_band, id_activity, note, html, id_parent) 
+                VALUES($1, $2, $3, $4, $5, $6)
                 RETURNING id, note, creation_stamp
             ",
             )
             .await?;
-        let res = client
-            .query(&stmt, &[&id_user, &id_band, &id_activity, &note])
+        let res = transaction
+            .query(
+                &stmt,
+                &[&id_user, &id_band, &id_activity, &note, &html, &id_parent],
+            )
             .await?;
+        record_event(
+            &transaction,
+            res[0].get(0),
+            id_band,
+            id_user,
+            EventKind::NoteCreated,
+            json!({ "idActivity": id_activity, "idParent": id_parent }),
+        )
+        .await?;
+        save_mentions(&transaction, res[0].get(0), &mentions).await?;
+        let revision_count =
+            add_revision(&transaction, res[0].get(0), id_user, &note, &html).await?;
+        transaction.commit().await?;
         let stmt2 = client
             .prepare_cached(
                 "
@@ -67,97 +346,447 @@ impl Note {
                 last_login: row.get(6),
                 verified: row.get(7),
                 is_admin: None,
+                id_role: None,
+                role: None,
             })
             .collect::<Vec<UserInterface>>();
         Ok(NoteInterface {
             id: res[0].get(0),
             note: res[0].get(1),
+            html,
+            mentions,
             user: Some(res2[0].clone()),
             creation_stamp: res[0].get(2),
+            edited_stamp: None,
+            revision_count,
+            deleted_stamp: None,
+            deleted_by: None,
+            id_parent,
+            pinned_stamp: None,
+            pinned_by: None,
+            reactions: vec![],
+            replies: vec![],
         })
     }
 
-    pub async fn edit(&self, id: i32, id_user: i32, note: String) -> Result<NoteInterface> {
+    /// Authors edit their notes as long as they are not deleted, the
+    /// previous text stays in the revisions.
+    pub async fn edit(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_user: i32,
+        note: String,
+    ) -> Result<Option<NoteInterface>> {
+        let (html, mentions) = self.render(id_band, &note).await?;
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE note SET note = $1, html = $4, edited_stamp = CURRENT_TIMESTAMP
+                WHERE id = $2 AND id_band = $5 AND id_user = $3 AND deleted_stamp IS NULL
+                RETURNING id
+            ",
+            )
+            .await?;
+        if transaction
+            .query(&stmt, &[&note, &id, &id_user, &html, &id_band])
+            .await?
+            .is_empty()
+        {
+            return Ok(None);
+        }
+        record_event(
+            &transaction,
+            id,
+            id_band,
+            id_user,
+            EventKind::NoteEdited,
+            json!({}),
+        )
+        .await?;
+        save_mentions(&transaction, id, &mentions).await?;
+        add_revision(&transaction, id, id_user, &note, &html).await?;
+        transaction.commit().await?;
+        drop(client);
+        self.get(id, id_band).await
+    }
+
+    /// Activity of a note of the band which can be answered, that is which
+    /// is not deleted.
+    pub async fn get_thread_activity(&self, id: i32, id_band: i32) -> Result<Option<i32>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "SELECT id_activity FROM note WHERE id = $1 AND id_band = $2 AND deleted_stamp IS NULL",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+
+    pub async fn get_band_id(&self, id: i32) -> Result<Option<i32>> {
         let client = self.0.get().await?;
         let stmt = client
+            .prepare_cached("SELECT id_band FROM note WHERE id = $1")
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+
+    /// Hides the note, which can be restored.
+    pub async fn delete(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_deleter: i32,
+    ) -> Result<Option<NoteInterface>> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
             .prepare_cached(
                 "
-                UPDATE note SET note = $1 WHERE id = $2 AND id_user = $3
-                RETURNING id, note, creation_stamp
+                UPDATE note SET deleted_stamp = CURRENT_TIMESTAMP, id_deleter = $3
+                WHERE id = $1 AND id_band = $2 AND deleted_stamp IS NULL
+                RETURNING id
             ",
             )
             .await?;
-        let res = client.query(&stmt, &[&note, &id, &id_user]).await?;
+        if transaction
+            .query(&stmt, &[&id, &id_band, &id_deleter])
+            .await?
+            .is_empty()
+        {
+            return Ok(None);
+        }
+        record_event(
+            &transaction,
+            id,
+            id_band,
+            id_deleter,
+            EventKind::NoteDeleted,
+            json!({}),
+        )
+        .await?;
+        transaction.commit().await?;
+        drop(client);
+        self.get(id, id_band).await
+    }
 
-        Ok(NoteInterface {
-            id: res[0].get(0),
-            note: res[0].get(1),
-            user: None,
-            creation_stamp: res[0].get(2),
-        })
+    pub async fn restore(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_actor: i32,
+    ) -> Result<Option<NoteInterface>> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE note SET deleted_stamp = NULL, id_deleter = NULL
+                WHERE id = $1 AND id_band = $2 AND deleted_stamp IS NOT NULL
+                RETURNING id
+            ",
+            )
+            .await?;
+        if transaction.query(&stmt, &[&id, &id_band]).await?.is_empty() {
+            return Ok(None);
+        }
+        record_event(
+            &transaction,
+            id,
+            id_band,
+            id_actor,
+            EventKind::NoteRestored,
+            json!({}),
+        )
+        .await?;
+        transaction.commit().await?;
+        drop(client);
+        self.get(id, id_band).await
+    }
+
+    /// Pins a note starting a thread at the top of its activity.
+    pub async fn pin(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_pinner: i32,
+    ) -> Result<Option<NoteInterface>> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE note SET pinned_stamp = CURRENT_TIMESTAMP, id_pinner = $3
+                WHERE id = $1 AND id_band = $2 AND id_parent IS NULL AND deleted_stamp IS NULL
+                RETURNING id
+            ",
+            )
+            .await?;
+        if transaction
+            .query(&stmt, &[&id, &id_band, &id_pinner])
+            .await?
+            .is_empty()
+        {
+            return Ok(None);
+        }
+        record_event(
+            &transaction,
+            id,
+            id_band,
+            id_pinner,
+            EventKind::NotePinned,
+            json!({}),
+        )
+        .await?;
+        transaction.commit().await?;
+        drop(client);
+        self.get(id, id_band).await
+    }
+
+    pub async fn unpin(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_actor: i32,
+    ) -> Result<Option<NoteInterface>> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE note SET pinned_stamp = NULL, id_pinner = NULL
+                WHERE id = $1 AND id_band = $2 AND pinned_stamp IS NOT NULL
+                RETURNING id
+            ",
+            )
+            .await?;
+        if transaction.query(&stmt, &[&id, &id_band]).await?.is_empty() {
+            return Ok(None);
+        }
+        record_event(
+            &transaction,
+            id,
+            id_band,
+            id_actor,
+            EventKind::NoteUnpinned,
+            json!({}),
+        )
+        .await?;
+        transaction.commit().await?;
+        drop(client);
+        self.get(id, id_band).await
     }
 
-    pub async fn delete(&self, id: i32) -> Result<Option<NoteInterface>> {
+    /// Adds the reaction of the user to a note which is not deleted,
+    /// reacting twice with the same emoji changes nothing.
+    pub async fn react(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_user: i32,
+        emoji: &str,
+    ) -> Result<Option<NoteInterface>> {
         let client = self.0.get().await?;
         let stmt = client
             .prepare_cached(
                 "
-                DELETE FROM note WHERE id = $1
-                RETURNING id, note, creation_stamp
+                INSERT INTO note_reaction(id_note, id_user, emoji)
+                SELECT id, $3, $4 FROM note
+                WHERE id = $1 AND id_band = $2 AND deleted_stamp IS NULL
+                ON CONFLICT (id_note, id_user, emoji) DO NOTHING
             ",
             )
             .await?;
-        let res = client.query(&stmt, &[&id]).await?;
+        client
+            .execute(&stmt, &[&id, &id_band, &id_user, &emoji])
+            .await?;
+        Ok(self
+            .get(id, id_band)
+            .await?
+            .filter(|n| n.deleted_stamp.is_none()))
+    }
 
-        if res.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(NoteInterface {
-                id: res[0].get(0),
-                note: res[0].get(1),
-                user: None,
-                creation_stamp: res[0].get(2),
-            }))
-        }
+    pub async fn unreact(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_user: i32,
+        emoji: &str,
+    ) -> Result<Option<NoteInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                DELETE FROM note_reaction
+                WHERE id_note = $1 AND id_user = $2 AND emoji = $3
+            ",
+            )
+            .await?;
+        client.execute(&stmt, &[&id, &id_user, &emoji]).await?;
+        self.get(id, id_band).await
     }
 
-    pub async fn read_all(&self, id_activity: i32, id_band: i32) -> Result<Vec<NoteInterface>> {
+    /// Revisions of a note of the band, oldest first.
+    pub async fn revisions(&self, id: i32, id_band: i32) -> Result<Vec<NoteRevisionInterface>> {
         let client = self.0.get().await?;
         let stmt = client
             .prepare_cached(
                 "
-                SELECT 
-                    n.id, n.note, n.creation_stamp,
-                    cu.id, cu.pseudo, cu.name, cu.firstname,
-                    cu.email, cu.creation_stamp, cu.last_login,
-                    cu.verified
-                FROM note n
-                JOIN cnm_user cu ON cu.id = n.id_user
-                WHERE n.id_activity = $1 AND n.id_band = $2
-                ORDER BY n.creation_stamp DESC
+                SELECT nr.id, nr.note, nr.html, nr.id_user, cu.pseudo, nr.creation_stamp
+                FROM note_revision nr
+                JOIN note n ON n.id = nr.id_note
+                LEFT JOIN cnm_user cu ON cu.id = nr.id_user
+                WHERE nr.id_note = $1 AND n.id_band = $2
+                ORDER BY nr.creation_stamp, nr.id
             ",
             )
             .await?;
         Ok(client
-            .query(&stmt, &[&id_activity, &id_band])
+            .query(&stmt, &[&id, &id_band])
             .await?
             .iter()
-            .map(|r| NoteInterface {
-                id: r.get(0),
-                note: r.get(1),
-                creation_stamp: r.get(2),
-                user: Some(UserInterface {
-                    id: r.get(3),
-                    pseudo: r.get(4),
-                    name: r.get(5),
-                    firstname: r.get(6),
-                    email: r.get(7),
-                    creation_stamp: r.get(8),
-                    last_login: r.get(9),
-                    verified: r.get(10),
-                    is_admin: None,
-                }),
+            .map(|row| {
+                let note: String = row.get(1);
+                NoteRevisionInterface {
+                    id: row.get(0),
+                    html: stored_html(&note, row.get(2)),
+                    note,
+                    user_id: row.get(3),
+                    user_pseudo: row.get(4),
+                    creation_stamp: row.get(5),
+                }
             })
             .collect())
     }
+
+    /// Threads of an activity, pinned ones first then the latest ones.
+    /// Pagination applies to the threads, each coming with all its
+    /// replies. Replies to a deleted note are hidden along with it.
+    pub async fn read_all(
+        &self,
+        id_activity: i32,
+        id_band: i32,
+        paginator: Option<Paginator>,
+    ) -> Result<(Vec<NoteInterface>, Paginator)> {
+        let client = self.0.get().await?;
+        let pag = paginator.unwrap_or_default();
+        let conditions = "
+            n.id_activity = $1 AND n.id_band = $2
+            AND n.deleted_stamp IS NULL AND n.id_parent IS NULL
+        ";
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    ORDER BY n.pinned_stamp DESC NULLS LAST, n.creation_stamp DESC, n.id DESC
+                    {}
+                    ",
+                    gen_note_select(conditions),
+                    pag
+                )
+                .as_str(),
+            )
+            .await?;
+        let mut threads: Vec<NoteInterface> = client
+            .query(&stmt, &[&id_activity, &id_band])
+            .await?
+            .iter()
+            .map(NoteInterface::from)
+            .collect();
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "SELECT CAST(COUNT(*) AS INT) FROM note n WHERE {}",
+                    conditions
+                )
+                .as_str(),
+            )
+            .await?;
+        let count: i32 = client.query(&stmt, &[&id_activity, &id_band]).await?[0].get(0);
+
+        let roots: Vec<i32> = threads.iter().map(|n| n.id).collect();
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    {}
+                    ORDER BY n.creation_stamp, n.id
+                    ",
+                    gen_note_select(
+                        "
+                        n.id IN (
+                            WITH RECURSIVE thread AS (
+                                SELECT id FROM note
+                                WHERE id_parent = ANY($1) AND deleted_stamp IS NULL
+                                UNION
+                                SELECT r.id FROM note r
+                                JOIN thread t ON r.id_parent = t.id
+                                WHERE r.deleted_stamp IS NULL
+                            )
+                            SELECT id FROM thread
+                        )
+                        "
+                    )
+                )
+                .as_str(),
+            )
+            .await?;
+        let mut replies: HashMap<i32, Vec<NoteInterface>> = HashMap::new();
+        for row in client.query(&stmt, &[&roots]).await? {
+            let reply = NoteInterface::from(&row);
+            if let Some(id_parent) = reply.id_parent {
+                replies.entry(id_parent).or_default().push(reply);
+            }
+        }
+        for thread in threads.iter_mut() {
+            thread.replies = nest(thread.id, &mut replies);
+        }
+
+        Ok((
+            threads,
+            Paginator {
+                page: pag.page,
+                size: pag.size,
+                page_count: if pag.size == 0 {
+                    None
+                } else {
+                    Some(count / pag.size)
+                },
+                item_count: Some(count),
+            },
+        ))
+    }
+
+    /// Deleted notes of an activity, which may be restored.
+    pub async fn read_deleted(&self, id_activity: i32, id_band: i32) -> Result<Vec<NoteInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "{} ORDER BY n.creation_stamp DESC",
+                    gen_note_select(
+                        "n.id_activity = $1 AND n.id_band = $2 AND n.deleted_stamp IS NOT NULL"
+                    )
+                )
+                .as_str(),
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_activity, &id_band])
+            .await?
+            .iter()
+            .map(NoteInterface::from)
+            .collect())
+    }
 }
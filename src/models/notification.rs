@@ -0,0 +1,191 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NotificationKind {
+    #[serde(rename = "mention")]
+    Mention,
+}
+
+impl From<String> for NotificationKind {
+    fn from(_: String) -> Self {
+        Self::Mention
+    }
+}
+
+impl Display for NotificationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                NotificationKind::Mention => "mention",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "bandName")]
+    pub band_name: String,
+    pub kind: NotificationKind,
+    #[serde(rename = "idNote")]
+    pub id_note: Option<i32>,
+    #[serde(rename = "idActivity")]
+    pub id_activity: Option<i32>,
+    #[serde(rename = "actorId")]
+    pub actor_id: Option<i32>,
+    #[serde(rename = "actorPseudo")]
+    pub actor_pseudo: Option<String>,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+    #[serde(rename = "readStamp")]
+    pub read_stamp: Option<NaiveDateTime>,
+}
+
+impl From<&Row> for NotificationInterface {
+    fn from(row: &Row) -> Self {
+        let kind: String = row.get(3);
+        NotificationInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            band_name: row.get(2),
+            kind: NotificationKind::from(kind),
+            id_note: row.get(4),
+            id_activity: row.get(5),
+            actor_id: row.get(6),
+            actor_pseudo: row.get(7),
+            creation_stamp: row.get(8),
+            read_stamp: row.get(9),
+        }
+    }
+}
+
+pub struct Notification(Pool);
+
+impl Notification {
+    pub fn new(pool: Pool) -> Self {
+        Notification(pool)
+    }
+
+    /// Notifies the users mentioned in a note, except its author and the
+    /// users already notified of it. Returns the newly notified users.
+    pub async fn notify_mentions(
+        &self,
+        id_band: i32,
+        id_note: i32,
+        id_actor: i32,
+        users: &[i32],
+    ) -> Result<Vec<i32>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO notification(id_user, id_band, kind, id_note, id_actor)
+                SELECT u, $1, 'mention', $2, $3
+                FROM UNNEST($4::int[]) u
+                WHERE u <> $3
+                ON CONFLICT (id_user, kind, id_note) DO NOTHING
+                RETURNING id_user
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band, &id_note, &id_actor, &users])
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    /// Notifications of the user, latest first.
+    pub async fn list(
+        &self,
+        id_user: i32,
+        unread_only: bool,
+    ) -> Result<Vec<NotificationInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    n.id, n.id_band, b.name, CAST(n.kind AS VARCHAR(16)), n.id_note,
+                    nt.id_activity, n.id_actor, cu.pseudo, n.creation_stamp, n.read_stamp
+                FROM notification n
+                JOIN band b ON b.id = n.id_band
+                LEFT JOIN note nt ON nt.id = n.id_note
+                LEFT JOIN cnm_user cu ON cu.id = n.id_actor
+                WHERE n.id_user = $1 AND (NOT $2 OR n.read_stamp IS NULL)
+                ORDER BY n.creation_stamp DESC
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user, &unread_only])
+            .await?
+            .iter()
+            .map(NotificationInterface::from)
+            .collect())
+    }
+
+    /// Marks one notification of the user as read, or all of them when no
+    /// id is given. Returns the number of notifications marked.
+    pub async fn mark_read(&self, id_user: i32, id: Option<i32>) -> Result<u64> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE notification SET read_stamp = CURRENT_TIMESTAMP
+                WHERE id_user = $1 AND ($2::int IS NULL OR id = $2) AND read_stamp IS NULL
+            ",
+            )
+            .await?;
+        Ok(client.execute(&stmt, &[&id_user, &id]).await?)
+    }
+
+    /// Whether the user wants mentions by email too.
+    pub async fn mention_emails(&self, id_user: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT mention_emails FROM cnm_user WHERE id = $1")
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user])
+            .await?
+            .first()
+            .map(|row| row.get(0))
+            .unwrap_or(false))
+    }
+
+    pub async fn set_mention_emails(&self, id_user: i32, enabled: bool) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("UPDATE cnm_user SET mention_emails = $2 WHERE id = $1")
+            .await?;
+        client.execute(&stmt, &[&id_user, &enabled]).await?;
+        Ok(())
+    }
+
+    /// Among the users, those who want mentions by email.
+    pub async fn email_recipients(&self, users: &[i32]) -> Result<Vec<i32>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT id FROM cnm_user WHERE id = ANY($1) AND mention_emails")
+            .await?;
+        Ok(client
+            .query(&stmt, &[&users])
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+}
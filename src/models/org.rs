@@ -2,12 +2,18 @@ use std::fmt::Display;
 
 use anyhow::Result;
 use chrono::NaiveDateTime;
-use deadpool_postgres::Pool;
+use deadpool_postgres::{Pool, Transaction};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::Row;
 
 use crate::{
     models::{
-        filter::{gen_request_search, Filter},
+        contact::{ContactChannel, ContactDetails, ContactRole, LegalBasis},
+        event::{self, contact_details, EventKind},
+        filter::{gen_request_search, query_params, Filter, Sort},
+        interaction::{gen_last_contact_join, ContactedFilter},
+        label::gen_label_filter,
         user::UserInterface,
     },
     paginator::Paginator,
@@ -98,6 +104,9 @@ pub struct OrgRawInterface {
     pub user_pseudo: Option<String>,
     #[serde(rename = "creationStamp")]
     pub creation_stamp: NaiveDateTime,
+    pub labels: Vec<i32>,
+    #[serde(rename = "lastContacted")]
+    pub last_contacted: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -112,10 +121,91 @@ pub struct ContactInterface {
     #[serde(rename = "zipCode")]
     pub zip_code: Option<String>,
     pub city: Option<String>,
+    #[serde(flatten)]
+    pub details: ContactDetails,
     #[serde(rename = "creationStamp")]
     pub creation_stamp: NaiveDateTime,
+    /// Set by the retention job once the contact has not been edited,
+    /// reviewed or contacted for the retention period.
+    #[serde(rename = "staleSince", default)]
+    pub stale_since: Option<NaiveDateTime>,
 }
 
+impl ContactInterface {
+    /// See [`ContactDetails::normalize`].
+    pub fn normalize(&mut self) -> Vec<String> {
+        let has_address = self.address.is_some() || self.city.is_some();
+        self.details
+            .normalize(&mut self.email, &mut self.phone, has_address)
+    }
+}
+
+/// Reads the contact columns in the order of `CONTACT_COLUMNS`.
+impl From<&Row> for ContactInterface {
+    fn from(row: &Row) -> Self {
+        let role: Option<String> = row.get(9);
+        let preferred_channel: Option<String> = row.get(11);
+        let emails: String = row.get(13);
+        let phones: String = row.get(14);
+        let socials: String = row.get(15);
+        let legal_basis: Option<String> = row.get(16);
+        ContactInterface {
+            id: row.get(0),
+            name: row.get(1),
+            first_name: row.get(2),
+            email: row.get(3),
+            phone: row.get(4),
+            address: row.get(5),
+            zip_code: row.get(6),
+            city: row.get(7),
+            creation_stamp: row.get(8),
+            details: ContactDetails {
+                role: role.map(ContactRole::from),
+                job_title: row.get(10),
+                preferred_channel: preferred_channel.map(ContactChannel::from),
+                language: row.get(12),
+                emails: serde_json::from_str(&emails).unwrap_or_default(),
+                phones: serde_json::from_str(&phones).unwrap_or_default(),
+                socials: serde_json::from_str(&socials).unwrap_or_default(),
+                legal_basis: legal_basis.map(LegalBasis::from),
+                data_source: row.get(17),
+                basis_stamp: row.get(18),
+            },
+            stale_since: row.get(19),
+        }
+    }
+}
+
+/// Band and org of a contact read with `CONTACT_COLUMNS, id_band, id_org`.
+fn contact_with_owner(row: &Row) -> (i32, i32, ContactInterface) {
+    (row.get(20), row.get(21), ContactInterface::from(row))
+}
+
+/// Columns of the contact table read by `ContactInterface::from`, the
+/// shared contact table has the same ones.
+pub const CONTACT_COLUMNS: &str = "
+    id,
+    name,
+    firstname,
+    email,
+    phone,
+    address,
+    zip_code,
+    city,
+    creation_stamp,
+    CAST(role AS VARCHAR(16)),
+    job_title,
+    CAST(preferred_channel AS VARCHAR(16)),
+    language,
+    CAST(emails AS TEXT),
+    CAST(phones AS TEXT),
+    CAST(socials AS TEXT),
+    CAST(legal_basis AS VARCHAR(24)),
+    data_source,
+    basis_stamp,
+    stale_since
+";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContactShort {
     pub name: String,
@@ -127,7 +217,136 @@ pub struct ContactShort {
     #[serde(rename = "zipCode")]
     pub zip_code: Option<String>,
     pub city: Option<String>,
+    #[serde(flatten)]
+    pub details: ContactDetails,
+}
+
+impl ContactShort {
+    /// See [`ContactDetails::normalize`].
+    pub fn normalize(&mut self) -> Vec<String> {
+        let has_address = self.address.is_some() || self.city.is_some();
+        self.details
+            .normalize(&mut self.email, &mut self.phone, has_address)
+    }
 }
+/// What the orgs of a listing must match.
+#[derive(Debug, Clone, Default)]
+pub struct OrgCriteria {
+    pub filters: Vec<Filter>,
+    pub labels: Vec<i32>,
+    pub contacted: Option<ContactedFilter>,
+}
+
+/// Column filters, label filter and last contacted filter of an org listing,
+/// joined together. The latter needs the `gen_last_contact_join` join. The
+/// filter values are bound after the `first` parameters of the query and
+/// returned along with the conditions.
+fn gen_org_conditions(id_band: i32, criteria: OrgCriteria, first: usize) -> (String, Vec<String>) {
+    let (search, values) = gen_request_search(&criteria.filters, first);
+    (
+        [
+            Some(search).filter(|f| !f.is_empty()),
+            gen_label_filter(id_band, &criteria.labels),
+            criteria.contacted.as_ref().and_then(|c| c.gen_request()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join(" AND "),
+        values,
+    )
+}
+
+/// Inserts a contact of the org, saved as is, and records it in the band
+/// feed.
+async fn insert_contact(
+    transaction: &Transaction<'_>,
+    id_actor: i32,
+    id_org: i32,
+    id_band: i32,
+    contact: &ContactShort,
+) -> Result<ContactInterface> {
+    let stmt = transaction
+        .prepare_cached(
+            format!(
+                "
+                INSERT INTO contact(
+                    id_org,
+                    name,
+                    firstname,
+                    email,
+                    phone,
+                    address,
+                    zip_code,
+                    city,
+                    id_band,
+                    role,
+                    job_title,
+                    preferred_channel,
+                    language,
+                    emails,
+                    phones,
+                    socials,
+                    legal_basis,
+                    data_source,
+                    basis_stamp)
+                VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, $8, $9,
+                    $10::text::contact_role, $11, $12::text::contact_channel, $13,
+                    $14::text::jsonb, $15::text::jsonb, $16::text::jsonb,
+                    $17::text::legal_basis, $18,
+                    CASE WHEN $17::text IS NULL THEN NULL
+                        ELSE COALESCE($19::timestamp, CURRENT_TIMESTAMP) END)
+                RETURNING {}
+                ",
+                CONTACT_COLUMNS
+            )
+            .as_str(),
+        )
+        .await?;
+    let details = &contact.details;
+    let rows: Vec<ContactInterface> = transaction
+        .query(
+            &stmt,
+            &[
+                &id_org,
+                &contact.name,
+                &contact.first_name,
+                &contact.email,
+                &contact.phone,
+                &contact.address,
+                &contact.zip_code,
+                &contact.city,
+                &id_band,
+                &details.role.map(|r| r.to_string()),
+                &details.job_title,
+                &details.preferred_channel.map(|c| c.to_string()),
+                &details.language,
+                &serde_json::to_string(&details.emails)?,
+                &serde_json::to_string(&details.phones)?,
+                &serde_json::to_string(&details.socials)?,
+                &details.legal_basis.map(|b| b.to_string()),
+                &details.data_source,
+                &details.basis_stamp,
+            ],
+        )
+        .await?
+        .iter()
+        .map(ContactInterface::from)
+        .collect();
+    event::record(
+        transaction,
+        id_band,
+        Some(id_actor),
+        EventKind::ContactAdded,
+        Some(id_org),
+        Some(rows[0].id),
+        contact_details(&rows[0]),
+    )
+    .await?;
+    Ok(rows[0].clone())
+}
+
 pub struct Org(Pool);
 
 impl Org {
@@ -138,17 +357,18 @@ impl Org {
     pub async fn all_orgs(
         &self,
         id_band: i32,
-        filters: Vec<Filter>,
+        criteria: OrgCriteria,
+        sort: Option<Sort>,
         paginator: Option<Paginator>,
     ) -> Result<(Vec<OrgRawInterface>, Paginator)> {
         let client = self.0.get().await?;
-        let req_end = if !filters.is_empty() { " WHERE " } else { "" };
-        let req_filter = gen_request_search(filters);
-        let pag = if let Some(p) = paginator {
-            p
+        let (req_filter, values) = gen_org_conditions(id_band, criteria, 1);
+        let req_end = if !req_filter.is_empty() {
+            " WHERE "
         } else {
-            Paginator::default()
+            ""
         };
+        let pag = paginator.unwrap_or_default();
         let streq = format!(
             "
             SELECT
@@ -160,27 +380,39 @@ impl Org {
                 a.postal_code,
                 a.category,
                 CAST(oa.status AS VARCHAR(16)) as status,
-                CASE WHEN oa.id_band={} THEN cu.id
+                CASE WHEN oa.id_band = $1 THEN cu.id
                     ELSE NULL
                 END,
-                CASE WHEN oa.id_band={} THEN cu.pseudo
+                CASE WHEN oa.id_band = $1 THEN cu.pseudo
                     ELSE NULL
                 END,
                 o.creation_stamp,
-                o.id
+                o.id,
+                ARRAY(
+                    SELECT ol.id_label
+                    FROM org_label ol
+                    JOIN band_label bl ON bl.id = ol.id_label
+                    WHERE ol.id_org = o.id AND bl.id_band = $1
+                ),
+                lc.last_contacted
             FROM org o
             JOIN activity a ON a.id_org = o.id
             LEFT JOIN org_assign oa ON oa.id_org = o.id
             LEFT JOIN cnm_user cu ON cu.id = oa.id_user
+            {}
             {}{}
-            ORDER BY o.name ASC
+            {}
             {}
             ",
-            id_band, id_band, req_end, req_filter, pag,
+            gen_last_contact_join(id_band),
+            req_end,
+            req_filter,
+            sort.unwrap_or_default().gen_request_order(),
+            pag,
         );
         let stmt = client.prepare_cached(&streq).await?;
         let rows = client
-            .query(&stmt, &[])
+            .query(&stmt, &query_params(&[&id_band], &values))
             .await?
             .iter()
             .map(|row| {
@@ -198,6 +430,8 @@ impl Org {
                     user_pseudo: row.get(9),
                     creation_stamp: row.get(10),
                     id_org: row.get(11),
+                    labels: row.get(12),
+                    last_contacted: row.get(13),
                 }
             })
             .collect();
@@ -208,14 +442,18 @@ impl Org {
                 JOIN activity a ON a.id_org = o.id
                 LEFT JOIN org_assign oa ON oa.id_org = o.id
                 LEFT JOIN cnm_user cu ON cu.id = oa.id_user
+                {}
                 WHERE (oa.id_band IS NULL OR oa.id_band = $1)
                 {} {}
             ",
+            gen_last_contact_join(id_band),
             if !req_filter.is_empty() { "AND" } else { "" },
             req_filter
         );
         let stmt = client.prepare_cached(rq.as_str()).await?;
-        let result = client.query(&stmt, &[&id_band]).await?;
+        let result = client
+            .query(&stmt, &query_params(&[&id_band], &values))
+            .await?;
         let count: i32 = result[0].get(0);
         Ok((
             rows,
@@ -236,48 +474,59 @@ impl Org {
         &self,
         id_user: i32,
         id_band: i32,
-        filters: Vec<Filter>,
+        criteria: OrgCriteria,
+        sort: Option<Sort>,
         paginator: Option<Paginator>,
     ) -> Result<(Vec<OrgRawInterface>, Paginator)> {
         let client = self.0.get().await?;
-        let req_end = if !filters.is_empty() { " AND " } else { "" };
-        let req_filter = gen_request_search(filters);
-        let pag = if let Some(p) = paginator {
-            p
-        } else {
-            Paginator::default()
-        };
+        let (req_filter, values) = gen_org_conditions(id_band, criteria, 2);
+        let req_end = if !req_filter.is_empty() { " AND " } else { "" };
+        let pag = paginator.unwrap_or_default();
 
         let stmt = client
             .prepare_cached(
                 format!(
                     "
                     SELECT
-                        o.id,
+                        a.id,
                         o.name,
                         o.description,
                         a.description,
                         a.city,
                         a.postal_code,
                         a.category,
-                        oa.status,
+                        CAST(oa.status AS VARCHAR(16)),
                         cu.id,
                         cu.pseudo,
                         o.creation_stamp,
-                        o.id
+                        o.id,
+                        ARRAY(
+                            SELECT ol.id_label
+                            FROM org_label ol
+                            JOIN band_label bl ON bl.id = ol.id_label
+                            WHERE ol.id_org = o.id AND bl.id_band = $2
+                        ),
+                        lc.last_contacted
                     FROM org o
                     JOIN activity a ON a.id_org = o.id
                     JOIN org_assign oa ON oa.id_org = o.id
-                    JOIN cnm_user cu ON cu.id = oa.id_user,
-                    WHERE oa.id_user = $1 AND oa.id_band = $2 {}{}{}
+                    JOIN cnm_user cu ON cu.id = oa.id_user
+                    {}
+                    WHERE oa.id_user = $1 AND oa.id_band = $2 {}{}
+                    {}
+                    {}
                     ",
-                    req_end, req_filter, pag,
+                    gen_last_contact_join(id_band),
+                    req_end,
+                    req_filter,
+                    sort.unwrap_or_default().gen_request_order(),
+                    pag,
                 )
                 .as_str(),
             )
             .await?;
         let rows = client
-            .query(&stmt, &[&id_user, &id_band])
+            .query(&stmt, &query_params(&[&id_user, &id_band], &values))
             .await?
             .iter()
             .map(|row| {
@@ -296,21 +545,33 @@ impl Org {
                     user_pseudo: row.get(9),
                     creation_stamp: row.get(10),
                     id_org: row.get(11),
+                    labels: row.get(12),
+                    last_contacted: row.get(13),
                 }
             })
             .collect();
         let stmt = client
             .prepare_cached(
-                "
-                SELECT CAST(COUNT(o.id) AS INT)
-                FROM org o
-                JOIN activity a ON a.id_org = o.id
-                LEFT JOIN org_assign oa ON oa.id_org = o.id
-                    WHERE oa.id_user = $1 AND oa.id_band = $2
-        ",
+                format!(
+                    "
+                    SELECT CAST(COUNT(o.id) AS INT)
+                    FROM org o
+                    JOIN activity a ON a.id_org = o.id
+                    LEFT JOIN org_assign oa ON oa.id_org = o.id
+                    LEFT JOIN cnm_user cu ON cu.id = oa.id_user
+                    {}
+                        WHERE oa.id_user = $1 AND oa.id_band = $2 {}{}
+                    ",
+                    gen_last_contact_join(id_band),
+                    req_end,
+                    req_filter,
+                )
+                .as_str(),
             )
             .await?;
-        let result = client.query(&stmt, &[&id_user, &id_band]).await?;
+        let result = client
+            .query(&stmt, &query_params(&[&id_user, &id_band], &values))
+            .await?;
         let count: i32 = result[0].get(0);
         Ok((
             rows,
@@ -327,38 +588,75 @@ impl Org {
         ))
     }
 
+    /// Number of orgs matching the filters which appeared after `since`, all
+    /// of them when there is no such date.
+    pub async fn count_new(
+        &self,
+        id_band: i32,
+        criteria: OrgCriteria,
+        since: Option<NaiveDateTime>,
+    ) -> Result<i32> {
+        let client = self.0.get().await?;
+        let (req_filter, values) = gen_org_conditions(id_band, criteria, 2);
+        let rq = format!(
+            "
+                SELECT CAST(COUNT(DISTINCT o.id) AS INT)
+                FROM org o
+                JOIN activity a ON a.id_org = o.id
+                LEFT JOIN org_assign oa ON oa.id_org = o.id AND oa.id_band = $1
+                LEFT JOIN cnm_user cu ON cu.id = oa.id_user
+                {}
+                WHERE ($2::timestamp IS NULL OR GREATEST(o.creation_stamp, a.creation_stamp) > $2)
+                {} {}
+            ",
+            gen_last_contact_join(id_band),
+            if !req_filter.is_empty() { "AND" } else { "" },
+            req_filter
+        );
+        let stmt = client.prepare_cached(rq.as_str()).await?;
+        let result = client
+            .query(&stmt, &query_params(&[&id_band, &since], &values))
+            .await?;
+        Ok(result[0].get(0))
+    }
+
+    /// Sets the status of the orgs. Orgs not yet in the band are assigned to
+    /// the user tagging them, the assignment of the others is left as is.
     pub async fn tag_orgs(
         &self,
+        id_actor: i32,
         id_user: i32,
         id_band: i32,
         orgs: Vec<i32>,
         status: Status,
     ) -> Result<()> {
-        let client = self.0.get().await?;
-        let stmt1 = client
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
             .prepare_cached(
                 "
-                DELETE FROM org_assign WHERE id_org = $1 AND id_band = $2
+                INSERT INTO org_assign(id_org, id_user, id_band, status)
+                SELECT unnest($3::int[]), $1, $2, $4::text::org_status
+                ON CONFLICT (id_org, id_band) DO UPDATE SET status = EXCLUDED.status
             ",
             )
             .await?;
-        let stmt2 = client
-            .prepare_cached(
-                format!(
-                    "
-                        INSERT INTO org_assign(id_org, id_user, id_band, status) 
-                        VALUES ($1, $2, $3, '{}')
-                    ",
-                    status,
-                )
-                .as_str(),
-            )
+        transaction
+            .query(&stmt, &[&id_user, &id_band, &orgs, &status.to_string()])
             .await?;
         for id_org in orgs {
-            client.query(&stmt1, &[&id_org, &id_band]).await?;
-            client.query(&stmt2, &[&id_org, &id_user, &id_band]).await?;
+            event::record(
+                &transaction,
+                id_band,
+                Some(id_actor),
+                EventKind::OrgTagged,
+                Some(id_org),
+                Some(id_user),
+                json!({ "status": status.to_string() }),
+            )
+            .await?;
         }
-
+        transaction.commit().await?;
         Ok(())
     }
 
@@ -403,6 +701,8 @@ impl Org {
                 last_login: row.get(7),
                 verified: row.get(8),
                 is_admin: None,
+                id_role: None,
+                role: None,
             })
             .collect::<Vec<UserInterface>>())
     }
@@ -438,6 +738,8 @@ impl Org {
                     last_login: row.get(6),
                     verified: row.get(7),
                     is_admin: None,
+                    id_role: None,
+                    role: None,
                 })
                 .collect::<Vec<UserInterface>>();
             res.append(&mut loc);
@@ -450,79 +752,101 @@ impl Org {
         let client = self.0.get().await?;
         let stmt = client
             .prepare_cached(
-                "
-                SELECT 
-                    id, 
-                    name, 
-                    firstname, 
-                    email, 
-                    phone, 
-                    address, 
-                    zip_code, 
-                    city, 
-                    creation_stamp
-                FROM contact
-                WHERE id_org = $1 AND id_band = $2
-            ",
+                format!(
+                    "SELECT {} FROM contact WHERE id_org = $1 AND id_band = $2",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
             )
             .await?;
         Ok(client
             .query(&stmt, &[&id_org, &id_band])
             .await?
             .iter()
-            .map(|row| ContactInterface {
-                id: row.get(0),
-                name: row.get(1),
-                first_name: row.get(2),
-                email: row.get(3),
-                phone: row.get(4),
-                address: row.get(5),
-                zip_code: row.get(6),
-                city: row.get(7),
-                creation_stamp: row.get(8),
-            })
+            .map(ContactInterface::from)
             .collect())
     }
 
+    /// Saves the contact as is, callers normalize it first.
     pub async fn add_contact(
         &self,
+        id_actor: i32,
         id_org: i32,
         id_band: i32,
         contact: ContactShort,
     ) -> Result<ContactInterface> {
-        let client = self.0.get().await?;
-        let stmt = client
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let contact = insert_contact(&transaction, id_actor, id_org, id_band, &contact).await?;
+        transaction.commit().await?;
+        Ok(contact)
+    }
+
+    /// Adds contacts, each given along with its org, all of them or none.
+    /// Callers normalize them first.
+    pub async fn add_contacts(
+        &self,
+        id_actor: i32,
+        id_band: i32,
+        contacts: &[(i32, ContactShort)],
+    ) -> Result<Vec<ContactInterface>> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let mut added = Vec::with_capacity(contacts.len());
+        for (id_org, contact) in contacts {
+            added.push(insert_contact(&transaction, id_actor, *id_org, id_band, contact).await?);
+        }
+        transaction.commit().await?;
+        Ok(added)
+    }
+
+    /// Saves the contact as is, callers normalize it first.
+    pub async fn update_contact(
+        &self,
+        id_actor: i32,
+        contact: ContactInterface,
+    ) -> Result<ContactInterface> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
             .prepare_cached(
-                "
-                INSERT INTO contact(
-                    id_org,
-                    name,
-                    firstname,
-                    email,
-                    phone,
-                    address,
-                    zip_code,
-                    city,
-                    id_band)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) 
-                RETURNING 
-                    id,
-                    name,
-                    firstname,
-                    email,
-                    phone,
-                    address,
-                    zip_code,
-                    city,
-                    creation_stamp
-            ",
+                format!(
+                    "
+                    UPDATE contact
+                    SET
+                        name = $1,
+                        firstname = $2,
+                        email = $3,
+                        phone = $4,
+                        address = $5,
+                        zip_code = $6,
+                        city = $7,
+                        role = $9::text::contact_role,
+                        job_title = $10,
+                        preferred_channel = $11::text::contact_channel,
+                        language = $12,
+                        emails = $13::text::jsonb,
+                        phones = $14::text::jsonb,
+                        socials = $15::text::jsonb,
+                        legal_basis = $16::text::legal_basis,
+                        data_source = $17,
+                        basis_stamp = CASE WHEN $16::text IS NULL THEN NULL
+                            ELSE COALESCE($18::timestamp, CURRENT_TIMESTAMP) END,
+                        update_stamp = CURRENT_TIMESTAMP,
+                        stale_since = NULL
+                    WHERE id = $8
+                    RETURNING {}, id_band, id_org
+                    ",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
             )
             .await?;
-        let rows: Vec<ContactInterface> = client
+        let details = &contact.details;
+        let rows = transaction
             .query(
                 &stmt,
                 &[
-                    &id_org,
                     &contact.name,
                     &contact.first_name,
                     &contact.email,
@@ -530,132 +854,121 @@ impl Org {
                     &contact.address,
                     &contact.zip_code,
                     &contact.city,
-                    &id_band,
+                    &contact.id,
+                    &details.role.map(|r| r.to_string()),
+                    &details.job_title,
+                    &details.preferred_channel.map(|c| c.to_string()),
+                    &details.language,
+                    &serde_json::to_string(&details.emails)?,
+                    &serde_json::to_string(&details.phones)?,
+                    &serde_json::to_string(&details.socials)?,
+                    &details.legal_basis.map(|b| b.to_string()),
+                    &details.data_source,
+                    &details.basis_stamp,
                 ],
             )
-            .await?
-            .iter()
-            .map(|row| ContactInterface {
-                id: row.get(0),
-                name: row.get(1),
-                first_name: row.get(2),
-                email: row.get(3),
-                phone: row.get(4),
-                address: row.get(5),
-                zip_code: row.get(6),
-                city: row.get(7),
-                creation_stamp: row.get(8),
-            })
-            .collect();
-        Ok(rows[0].clone())
+            .await?;
+        let (id_band, id_org, res) = contact_with_owner(&rows[0]);
+        event::record(
+            &transaction,
+            id_band,
+            Some(id_actor),
+            EventKind::ContactEdited,
+            Some(id_org),
+            Some(res.id),
+            contact_details(&res),
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(res)
     }
 
-    pub async fn update_contact(&self, contact: ContactInterface) -> Result<ContactInterface> {
-        let client = self.0.get().await?;
-        let stmt = client
+    pub async fn remove_contact(&self, id_actor: i32, id_contact: i32) -> Result<ContactInterface> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
             .prepare_cached(
-                "
-            UPDATE contact
-            SET 
-                name = $1, 
-                firstname = $2, 
-                email = $3, 
-                phone = $4, 
-                address = $5, 
-                zip_code = $6, 
-                city = $7
-            WHERE id = $8
-            RETURNING 
-                id,
-                name,
-                firstname,
-                email,
-                phone,
-                address,
-                zip_code,
-                city,
-                creation_stamp
-        ",
+                format!(
+                    "DELETE FROM contact WHERE id = $1 RETURNING {}, id_band, id_org",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
             )
             .await?;
-        let res: Vec<ContactInterface> = client
-            .query(
-                &stmt,
-                &[
-                    &contact.name,
-                    &contact.first_name,
-                    &contact.email,
-                    &contact.phone,
-                    &contact.address,
-                    &contact.zip_code,
-                    &contact.city,
-                    &contact.id,
-                ],
-            )
-            .await?
-            .iter()
-            .map(|row| ContactInterface {
-                id: row.get(0),
-                name: row.get(1),
-                first_name: row.get(2),
-                email: row.get(3),
-                phone: row.get(4),
-                address: row.get(5),
-                zip_code: row.get(6),
-                city: row.get(7),
-                creation_stamp: row.get(8),
-            })
-            .collect();
+        let rows = transaction.query(&stmt, &[&id_contact]).await?;
+        let (id_band, id_org, res) = contact_with_owner(&rows[0]);
+        event::record(
+            &transaction,
+            id_band,
+            Some(id_actor),
+            EventKind::ContactRemoved,
+            Some(id_org),
+            Some(res.id),
+            contact_details(&res),
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(res)
+    }
 
-        Ok(res[0].clone())
+    pub async fn get_contact_band_id(&self, id_contact: i32) -> Result<i32> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT id_band FROM contact WHERE id = $1")
+            .await?;
+        let res = client.query(&stmt, &[&id_contact]).await?;
+        Ok(res[0].get(0))
     }
 
-    pub async fn remove_contact(&self, id_contact: i32) -> Result<ContactInterface> {
+    pub async fn get_org_name(&self, id_org: i32) -> Result<Option<String>> {
         let client = self.0.get().await?;
         let stmt = client
-            .prepare_cached(
-                "
-                DELETE FROM contact 
-                WHERE id = $1
-                RETURNING 
-                    id,
-                    name,
-                    firstname,
-                    email,
-                    phone,
-                    address,
-                    zip_code,
-                    city,
-                    creation_stamp
-            ",
-            )
+            .prepare_cached("SELECT name FROM org WHERE id = $1")
             .await?;
-        let res: Vec<ContactInterface> = client
-            .query(&stmt, &[&id_contact])
+        Ok(client
+            .query(&stmt, &[&id_org])
             .await?
-            .iter()
-            .map(|row| ContactInterface {
-                id: row.get(0),
-                name: row.get(1),
-                first_name: row.get(2),
-                email: row.get(3),
-                phone: row.get(4),
-                address: row.get(5),
-                zip_code: row.get(6),
-                city: row.get(7),
-                creation_stamp: row.get(8),
-            })
-            .collect();
+            .first()
+            .map(|row| row.get(0)))
+    }
 
-        Ok(res[0].clone())
+    /// Every org as (id, name), used to match imported data by name.
+    pub async fn get_org_names(&self) -> Result<Vec<(i32, String)>> {
+        let client = self.0.get().await?;
+        let stmt = client.prepare_cached("SELECT id, name FROM org").await?;
+        Ok(client
+            .query(&stmt, &[])
+            .await?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
     }
 
-    pub async fn get_contact_band_id(&self, id_contact: i32) -> Result<i32> {
+    /// Contacts of the band across all orgs, with their org id and name.
+    pub async fn get_band_contacts(
+        &self,
+        id_band: i32,
+    ) -> Result<Vec<(i32, String, ContactInterface)>> {
         let client = self.0.get().await?;
         let stmt = client
-            .prepare_cached("SELECT id_band FROM contact WHERE id = $1")
+            .prepare_cached(
+                format!(
+                    "
+                    SELECT c.*, o.id, o.name
+                    FROM (SELECT {}, id_org FROM contact WHERE id_band = $1) c
+                    JOIN org o ON o.id = c.id_org
+                    ORDER BY o.name, c.name
+                    ",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
+            )
             .await?;
-        let res = client.query(&stmt, &[&id_contact]).await?;
-        Ok(res[0].get(0))
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(|row| (row.get(21), row.get(22), ContactInterface::from(row)))
+            .collect())
     }
 }
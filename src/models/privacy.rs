@@ -0,0 +1,440 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use serde_json::json;
+
+use super::{
+    band::BandInterface,
+    event::{self, EventKind},
+    identity::{Identity, IdentityInterface},
+    interaction::{Interaction, InteractionInterface},
+    invitation::{Invitation, InvitationInterface},
+    org::{ContactInterface, CONTACT_COLUMNS},
+    shared_contact::{SharedContact, SharedContactInterface},
+    user::{User, UserInterface},
+};
+
+/// Text put in place of the erased email in free text.
+const ERASED: &str = "[erased]";
+
+/// Contacts holding the email `$1`, as primary email or in their list.
+const EMAIL_MATCH: &str = "
+    (LOWER(email) = LOWER($1) OR EXISTS (
+        SELECT 1 FROM jsonb_array_elements(emails) e WHERE LOWER(e->>'value') = LOWER($1)
+    ))
+";
+
+/// Bands of the user `$1` which would be left without an owner or an
+/// administrator if the user left them.
+const SOLE_MANAGER_BANDS: &str = "
+    SELECT ub.id_band
+    FROM user_band ub
+    JOIN band_role br ON br.id = ub.id_role
+    JOIN band b ON b.id = ub.id_band
+    WHERE ub.id_user = $1 AND b.deleted_stamp IS NULL AND (
+        (br.id_band IS NULL AND br.name = 'owner')
+        OR ('manage_members' = ANY(br.permissions) AND NOT EXISTS (
+            SELECT 1
+            FROM user_band other
+            JOIN band_role obr ON obr.id = other.id_role
+            WHERE other.id_band = ub.id_band
+                AND other.id_user <> ub.id_user
+                AND 'manage_members' = ANY(obr.permissions)
+        ))
+    )
+    ORDER BY ub.id_band
+";
+
+/// A band contact holding the email, with its band and org.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubjectContact {
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "bandName")]
+    pub band_name: String,
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    #[serde(rename = "orgName")]
+    pub org_name: String,
+    pub contact: ContactInterface,
+}
+
+/// A note written by the account or quoting the email.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubjectNote {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    pub note: String,
+    pub authored: bool,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+/// Everything stored about an email address.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubjectExport {
+    pub email: String,
+    pub account: Option<UserInterface>,
+    pub bands: Vec<BandInterface>,
+    pub identities: Vec<IdentityInterface>,
+    pub invitations: Vec<InvitationInterface>,
+    pub contacts: Vec<SubjectContact>,
+    #[serde(rename = "sharedContacts")]
+    pub shared_contacts: Vec<SharedContactInterface>,
+    pub interactions: Vec<InteractionInterface>,
+    pub notes: Vec<SubjectNote>,
+}
+
+/// What an erasure removed or anonymised.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ErasureReport {
+    pub account: bool,
+    pub contacts: u64,
+    #[serde(rename = "sharedContacts")]
+    pub shared_contacts: u64,
+    pub invitations: u64,
+    pub interactions: u64,
+    pub notes: u64,
+    pub events: u64,
+    /// Bands the account owns or is the last administrator of, nothing is
+    /// erased until they are handed over.
+    #[serde(rename = "blockingBands")]
+    pub blocking_bands: Vec<i32>,
+}
+
+/// Case insensitive regular expression matching the email as is.
+fn email_pattern(email: &str) -> String {
+    email
+        .chars()
+        .map(|c| {
+            if "\\.^$|?*+()[]{}-".contains(c) {
+                format!("\\{}", c)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+pub struct Privacy(Pool);
+
+impl Privacy {
+    pub fn new(pool: Pool) -> Self {
+        Privacy(pool)
+    }
+
+    async fn account_id(&self, email: &str) -> Result<Option<i32>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT id FROM cnm_user WHERE LOWER(email) = LOWER($1)")
+            .await?;
+        Ok(client
+            .query(&stmt, &[&email])
+            .await?
+            .first()
+            .map(|row| row.get(0)))
+    }
+
+    pub async fn export(&self, email: &str) -> Result<SubjectExport> {
+        let id_user = self.account_id(email).await?;
+        let (account, bands, identities) = match id_user {
+            Some(id) => {
+                let user = User::new(self.0.clone());
+                (
+                    Some(user.read(id).await?),
+                    user.get_bands(id, true).await?,
+                    Identity::new(self.0.clone()).list(id).await?,
+                )
+            }
+            None => (None, vec![], vec![]),
+        };
+
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    SELECT c.*, b.id, b.name, o.id, o.name
+                    FROM (
+                        SELECT {}, id_band, id_org FROM contact WHERE {}
+                    ) c
+                    JOIN band b ON b.id = c.id_band
+                    JOIN org o ON o.id = c.id_org
+                    ORDER BY b.name, o.name
+                    ",
+                    CONTACT_COLUMNS, EMAIL_MATCH
+                )
+                .as_str(),
+            )
+            .await?;
+        let contacts: Vec<SubjectContact> = client
+            .query(&stmt, &[&email])
+            .await?
+            .iter()
+            .map(|row| SubjectContact {
+                id_band: row.get(22),
+                band_name: row.get(23),
+                id_org: row.get(24),
+                org_name: row.get(25),
+                contact: ContactInterface::from(row),
+            })
+            .collect();
+        let ids: Vec<i32> = contacts.iter().map(|c| c.contact.id).collect();
+
+        let stmt = client
+            .prepare_cached(format!("SELECT id FROM shared_contact WHERE {}", EMAIL_MATCH).as_str())
+            .await?;
+        let shared = SharedContact::new(self.0.clone());
+        let mut shared_contacts = vec![];
+        for row in client.query(&stmt, &[&email]).await? {
+            if let Some(s) = shared.get(row.get(0)).await? {
+                shared_contacts.push(s);
+            }
+        }
+
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT id, id_band, note, id_user = $2, creation_stamp
+                FROM note
+                WHERE id_user = $2 OR POSITION(LOWER($1) IN LOWER(note)) > 0
+                ORDER BY creation_stamp DESC
+            ",
+            )
+            .await?;
+        let notes = client
+            .query(&stmt, &[&email, &id_user])
+            .await?
+            .iter()
+            .map(|row| SubjectNote {
+                id: row.get(0),
+                id_band: row.get(1),
+                note: row.get(2),
+                authored: row.get::<_, Option<bool>>(3).unwrap_or(false),
+                creation_stamp: row.get(4),
+            })
+            .collect();
+
+        Ok(SubjectExport {
+            email: email.to_string(),
+            account,
+            bands,
+            identities,
+            invitations: Invitation::new(self.0.clone()).for_email(email).await?,
+            contacts,
+            shared_contacts,
+            interactions: Interaction::new(self.0.clone())
+                .for_subject(&ids, id_user)
+                .await?,
+            notes,
+        })
+    }
+
+    /// Deletes the contacts and invitations holding the email, removes it
+    /// from notes and their revisions, interaction summaries and contact
+    /// history, and anonymises the account registered with it. The account
+    /// keeps its notes and interactions but loses its bands, sessions and
+    /// identities, and its assigned orgs are released.
+    pub async fn erase(&self, email: &str) -> Result<ErasureReport> {
+        let id_user = self.account_id(email).await?;
+        let pattern = email_pattern(email);
+        let mut report = ErasureReport::default();
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+
+        if let Some(id) = id_user {
+            let stmt = transaction.prepare_cached(SOLE_MANAGER_BANDS).await?;
+            report.blocking_bands = transaction
+                .query(&stmt, &[&id])
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            if !report.blocking_bands.is_empty() {
+                return Ok(report);
+            }
+        }
+
+        let stmt = transaction
+            .prepare_cached(
+                format!(
+                    "SELECT id, id_band, id_org FROM contact WHERE {}",
+                    EMAIL_MATCH
+                )
+                .as_str(),
+            )
+            .await?;
+        let contacts: Vec<(i32, i32, i32)> = transaction
+            .query(&stmt, &[&email])
+            .await?
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+        let ids: Vec<i32> = contacts.iter().map(|(id, _, _)| *id).collect();
+
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE band_event
+                SET details = jsonb_build_object('erased', true)
+                WHERE (
+                    id_target = ANY($1)
+                    AND kind IN ('contact_added', 'contact_edited', 'contact_removed', 'contact_merged')
+                ) OR POSITION(LOWER($2) IN LOWER(CAST(details AS TEXT))) > 0
+            ",
+            )
+            .await?;
+        report.events = transaction.execute(&stmt, &[&ids, &email]).await?;
+
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE interaction
+                SET
+                    id_contact = CASE WHEN id_contact = ANY($1) THEN NULL ELSE id_contact END,
+                    summary = regexp_replace(summary, $2, $3, 'gi')
+                WHERE id_contact = ANY($1) OR summary ~* $2
+            ",
+            )
+            .await?;
+        report.interactions = transaction
+            .execute(&stmt, &[&ids, &pattern, &ERASED])
+            .await?;
+
+        let stmt = transaction
+            .prepare_cached(
+                format!(
+                    "DELETE FROM shared_contact WHERE id_contact = ANY($2) OR {}",
+                    EMAIL_MATCH
+                )
+                .as_str(),
+            )
+            .await?;
+        report.shared_contacts = transaction.execute(&stmt, &[&email, &ids]).await?;
+
+        let stmt = transaction
+            .prepare_cached("DELETE FROM contact WHERE id = ANY($1)")
+            .await?;
+        report.contacts = transaction.execute(&stmt, &[&ids]).await?;
+        for (id, id_band, id_org) in contacts {
+            event::record(
+                &transaction,
+                id_band,
+                None,
+                EventKind::ContactRemoved,
+                Some(id_org),
+                Some(id),
+                json!({ "erased": true }),
+            )
+            .await?;
+        }
+
+        let stmt = transaction
+            .prepare_cached("DELETE FROM band_invitation WHERE LOWER(email) = LOWER($1)")
+            .await?;
+        report.invitations = transaction.execute(&stmt, &[&email]).await?;
+
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE note
+                SET
+                    note = regexp_replace(note, $1, $2, 'gi'),
+                    html = regexp_replace(html, $1, $2, 'gi')
+                WHERE note ~* $1
+            ",
+            )
+            .await?;
+        report.notes = transaction.execute(&stmt, &[&pattern, &ERASED]).await?;
+        let stmt = transaction
+            .prepare_cached(
+                "
+                UPDATE note_revision
+                SET
+                    note = regexp_replace(note, $1, $2, 'gi'),
+                    html = regexp_replace(html, $1, $2, 'gi')
+                WHERE note ~* $1
+            ",
+            )
+            .await?;
+        transaction.execute(&stmt, &[&pattern, &ERASED]).await?;
+
+        let stmt = transaction
+            .prepare_cached("DELETE FROM email_change WHERE LOWER(new_email) = LOWER($1)")
+            .await?;
+        transaction.execute(&stmt, &[&email]).await?;
+
+        if let Some(id) = id_user {
+            let stmt = transaction
+                .prepare_cached("SELECT id_band FROM user_band WHERE id_user = $1")
+                .await?;
+            for row in transaction.query(&stmt, &[&id]).await? {
+                event::record(
+                    &transaction,
+                    row.get(0),
+                    None,
+                    EventKind::MemberLeft,
+                    None,
+                    Some(id),
+                    json!({ "erased": true }),
+                )
+                .await?;
+            }
+            let stmt = transaction
+                .prepare_cached("UPDATE org_assign SET id_user = NULL WHERE id_user = $1")
+                .await?;
+            transaction.execute(&stmt, &[&id]).await?;
+            for table in [
+                "user_token",
+                "api_token",
+                "user_identity",
+                "oidc_login",
+                "email_change",
+                "notification",
+                "user_band",
+            ] {
+                let stmt = transaction
+                    .prepare_cached(format!("DELETE FROM {} WHERE id_user = $1", table).as_str())
+                    .await?;
+                transaction.execute(&stmt, &[&id]).await?;
+            }
+            let stmt = transaction
+                .prepare_cached(
+                    "
+                    UPDATE cnm_user
+                    SET
+                        pseudo = 'erased-' || id,
+                        name = '',
+                        firstname = '',
+                        email = 'erased-' || id || '@invalid',
+                        pwd = crypt(encode(gen_random_bytes(32), 'hex'), gen_salt('bf')),
+                        verified = false,
+                        last_login = NULL,
+                        platform_admin = false
+                    WHERE id = $1
+                ",
+                )
+                .await?;
+            report.account = transaction.execute(&stmt, &[&id]).await? > 0;
+        }
+
+        transaction.commit().await?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn email_pattern_escapes_metacharacters() {
+        assert_eq!(email_pattern("jane@example.org"), "jane@example\\.org");
+        assert_eq!(
+            email_pattern("a+b(c)|d*e?[f]{g}^$-h\\i@x.org"),
+            "a\\+b\\(c\\)\\|d\\*e\\?\\[f\\]\\{g\\}\\^\\$\\-h\\\\i@x\\.org"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use super::api_token::Scope;
+
+pub const OWNER_ROLE: &str = "owner";
+pub const ADMIN_ROLE: &str = "admin";
+pub const BOOKER_ROLE: &str = "booker";
+pub const VIEWER_ROLE: &str = "viewer";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Permission {
+    #[serde(rename = "read_orgs")]
+    ReadOrgs,
+    #[serde(rename = "tag_orgs")]
+    TagOrgs,
+    #[serde(rename = "assign_orgs")]
+    AssignOrgs,
+    #[serde(rename = "manage_contacts")]
+    ManageContacts,
+    #[serde(rename = "write_notes")]
+    WriteNotes,
+    #[serde(rename = "delete_notes")]
+    DeleteNotes,
+    #[serde(rename = "manage_members")]
+    ManageMembers,
+    #[serde(rename = "manage_roles")]
+    ManageRoles,
+    #[serde(rename = "manage_band")]
+    ManageBand,
+    #[serde(rename = "delete_band")]
+    DeleteBand,
+}
+
+impl Permission {
+    /// API token scope a request must carry to exercise this permission.
+    pub fn scope(&self) -> Scope {
+        match self {
+            Permission::ReadOrgs => Scope::ReadOrgs,
+            Permission::TagOrgs | Permission::AssignOrgs | Permission::WriteNotes => Scope::TagOrgs,
+            Permission::ManageContacts => Scope::ManageContacts,
+            Permission::DeleteNotes
+            | Permission::ManageMembers
+            | Permission::ManageRoles
+            | Permission::ManageBand
+            | Permission::DeleteBand => Scope::BandAdmin,
+        }
+    }
+}
+
+impl Permission {
+    pub fn allowed_when_archived(&self) -> bool {
+        matches!(self, Permission::ReadOrgs | Permission::DeleteBand)
+    }
+}
+
+impl TryFrom<String> for Permission {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_ref() {
+            "read_orgs" => Ok(Self::ReadOrgs),
+            "tag_orgs" => Ok(Self::TagOrgs),
+            "assign_orgs" => Ok(Self::AssignOrgs),
+            "manage_contacts" => Ok(Self::ManageContacts),
+            "write_notes" => Ok(Self::WriteNotes),
+            "delete_notes" => Ok(Self::DeleteNotes),
+            "manage_members" => Ok(Self::ManageMembers),
+            "manage_roles" => Ok(Self::ManageRoles),
+            "manage_band" => Ok(Self::ManageBand),
+            "delete_band" => Ok(Self::DeleteBand),
+            _ => Err("unknownPermission".to_string()),
+        }
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Permission::ReadOrgs => "read_orgs",
+                Permission::TagOrgs => "tag_orgs",
+                Permission::AssignOrgs => "assign_orgs",
+                Permission::ManageContacts => "manage_contacts",
+                Permission::WriteNotes => "write_notes",
+                Permission::DeleteNotes => "delete_notes",
+                Permission::ManageMembers => "manage_members",
+                Permission::ManageRoles => "manage_roles",
+                Permission::ManageBand => "manage_band",
+                Permission::DeleteBand => "delete_band",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: Option<i32>,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    pub builtin: bool,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+}
+
+impl RoleInterface {
+    pub fn is_owner(&self) -> bool {
+        self.builtin && self.name == OWNER_ROLE
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    /// A member can only hand out, or act upon, roles that grant nothing
+    /// more than their own.
+    pub fn covers(&self, permissions: &[Permission]) -> bool {
+        permissions.iter().all(|p| self.has(*p))
+    }
+}
+
+impl From<&Row> for RoleInterface {
+    fn from(row: &Row) -> Self {
+        let permissions: Vec<String> = row.get(3);
+        let id_band: Option<i32> = row.get(1);
+        RoleInterface {
+            id: row.get(0),
+            id_band,
+            name: row.get(2),
+            permissions: permissions
+                .into_iter()
+                .filter_map(|p| Permission::try_from(p).ok())
+                .collect(),
+            builtin: id_band.is_none(),
+            creation_stamp: row.get(4),
+        }
+    }
+}
+
+fn permission_strings(permissions: &[Permission]) -> Vec<String> {
+    permissions.iter().map(|p| p.to_string()).collect()
+}
+
+pub struct Role(Pool);
+
+impl Role {
+    pub fn new(pool: Pool) -> Self {
+        Role(pool)
+    }
+
+    /// Built-in roles followed by the ones defined by the band.
+    pub async fn list(&self, id_band: i32) -> Result<Vec<RoleInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT id, id_band, name, permissions, creation_stamp
+                FROM band_role
+                WHERE id_band IS NULL OR id_band = $1
+                ORDER BY id_band NULLS FIRST, id
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(RoleInterface::from)
+            .collect())
+    }
+
+    /// Returns a role usable in the band, either built-in or its own.
+    pub async fn get(&self, id: i32, id_band: i32) -> Result<Option<RoleInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT id, id_band, name, permissions, creation_stamp
+                FROM band_role
+                WHERE id = $1 AND (id_band IS NULL OR id_band = $2)
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band])
+            .await?
+            .first()
+            .map(RoleInterface::from))
+    }
+
+    pub async fn builtin(&self, name: &str) -> Result<i32> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT id FROM band_role WHERE id_band IS NULL AND name = $1")
+            .await?;
+        Ok(client.query(&stmt, &[&name]).await?[0].get(0))
+    }
+
+    /// Role of a member, None when the user is not part of the band.
+    pub async fn of_member(&self, id_user: i32, id_band: i32) -> Result<Option<RoleInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT br.id, br.id_band, br.name, br.permissions, br.creation_stamp
+                FROM user_band ub
+                JOIN band_role br ON br.id = ub.id_role
+                JOIN band b ON b.id = ub.id_band
+                WHERE ub.id_user = $1 AND ub.id_band = $2 AND b.deleted_stamp IS NULL
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_user, &id_band])
+            .await?
+            .first()
+            .map(RoleInterface::from))
+    }
+
+    pub async fn create(
+        &self,
+        id_band: i32,
+        name: String,
+        permissions: Vec<Permission>,
+    ) -> Result<RoleInterface> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO band_role(id_band, name, permissions)
+                VALUES ($1, $2, $3)
+                RETURNING id, id_band, name, permissions, creation_stamp
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(&stmt, &[&id_band, &name, &permission_strings(&permissions)])
+            .await?;
+        Ok(RoleInterface::from(&rows[0]))
+    }
+
+    /// Only custom roles can be changed, built-in ones are shared by every
+    /// band.
+    pub async fn update(
+        &self,
+        id: i32,
+        id_band: i32,
+        name: String,
+        permissions: Vec<Permission>,
+    ) -> Result<Option<RoleInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE band_role SET name = $3, permissions = $4
+                WHERE id = $1 AND id_band = $2
+                RETURNING id, id_band, name, permissions, creation_stamp
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(
+                &stmt,
+                &[&id, &id_band, &name, &permission_strings(&permissions)],
+            )
+            .await?
+            .first()
+            .map(RoleInterface::from))
+    }
+
+    /// Removes a custom role, members holding it fall back to `fallback`.
+    pub async fn delete(&self, id: i32, id_band: i32, fallback: i32) -> Result<bool> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached("UPDATE user_band SET id_role = $3 WHERE id_role = $1 AND id_band = $2")
+            .await?;
+        transaction
+            .query(&stmt, &[&id, &id_band, &fallback])
+            .await?;
+        let stmt = transaction
+            .prepare_cached(
+                "UPDATE band_invitation SET id_role = $3 WHERE id_role = $1 AND id_band = $2",
+            )
+            .await?;
+        transaction
+            .query(&stmt, &[&id, &id_band, &fallback])
+            .await?;
+        let stmt = transaction
+            .prepare_cached("DELETE FROM band_role WHERE id = $1 AND id_band = $2 RETURNING id")
+            .await?;
+        let deleted = !transaction.query(&stmt, &[&id, &id_band]).await?.is_empty();
+        transaction.commit().await?;
+        Ok(deleted)
+    }
+
+    pub async fn assign(&self, id_user: i32, id_band: i32, id_role: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "UPDATE user_band SET id_role = $3 WHERE id_user = $1 AND id_band = $2 RETURNING id_user",
+            )
+            .await?;
+        Ok(!client
+            .query(&stmt, &[&id_user, &id_band, &id_role])
+            .await?
+            .is_empty())
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::models::filter::{Filter, FilterIntermediate, Sort};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearchInterface {
+    pub id: i32,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "idOwner")]
+    pub id_owner: i32,
+    #[serde(rename = "ownerPseudo")]
+    pub owner_pseudo: String,
+    pub name: String,
+    pub filters: Vec<FilterIntermediate>,
+    pub labels: Vec<i32>,
+    pub sort: Sort,
+    pub shared: bool,
+    #[serde(rename = "lastOpened")]
+    pub last_opened: Option<NaiveDateTime>,
+    #[serde(rename = "creationStamp")]
+    pub creation_stamp: NaiveDateTime,
+    #[serde(rename = "updateStamp")]
+    pub update_stamp: NaiveDateTime,
+}
+
+impl SavedSearchInterface {
+    /// Filters are checked when saved, a search stored before that may
+    /// still hold an unknown one.
+    pub fn filters(&self) -> Result<Vec<Filter>, String> {
+        self.filters.iter().cloned().map(Filter::try_from).collect()
+    }
+}
+
+impl From<&Row> for SavedSearchInterface {
+    fn from(row: &Row) -> Self {
+        let filters: String = row.get(5);
+        SavedSearchInterface {
+            id: row.get(0),
+            id_band: row.get(1),
+            id_owner: row.get(2),
+            owner_pseudo: row.get(3),
+            name: row.get(4),
+            filters: serde_json::from_str(&filters).unwrap_or_default(),
+            labels: row.get(6),
+            sort: Sort {
+                key: row.get(7),
+                desc: row.get(8),
+            },
+            shared: row.get(9),
+            last_opened: row.get(10),
+            creation_stamp: row.get(11),
+            update_stamp: row.get(12),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedSearchRequest {
+    pub name: String,
+    #[serde(default)]
+    pub filters: Vec<FilterIntermediate>,
+    #[serde(default)]
+    pub labels: Vec<i32>,
+    #[serde(default)]
+    pub sort: Sort,
+    #[serde(default)]
+    pub shared: bool,
+}
+
+pub struct SavedSearch(Pool);
+
+impl SavedSearch {
+    pub fn new(pool: Pool) -> Self {
+        SavedSearch(pool)
+    }
+
+    /// Searches of the user along with the ones shared with the band.
+    pub async fn list(&self, id_band: i32, id_user: i32) -> Result<Vec<SavedSearchInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    ss.id, ss.id_band, ss.id_owner, cu.pseudo, ss.name, ss.filters,
+                    ss.labels, ss.sort_key, ss.sort_desc, ss.shared, ssv.opened_stamp,
+                    ss.creation_stamp, ss.update_stamp
+                FROM saved_search ss
+                JOIN cnm_user cu ON cu.id = ss.id_owner
+                LEFT JOIN saved_search_view ssv ON ssv.id_search = ss.id AND ssv.id_user = $2
+                WHERE ss.id_band = $1 AND (ss.id_owner = $2 OR ss.shared)
+                ORDER BY ss.name
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band, &id_user])
+            .await?
+            .iter()
+            .map(SavedSearchInterface::from)
+            .collect())
+    }
+
+    /// Returns the search when the user is allowed to see it.
+    pub async fn get(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_user: i32,
+    ) -> Result<Option<SavedSearchInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                SELECT
+                    ss.id, ss.id_band, ss.id_owner, cu.pseudo, ss.name, ss.filters,
+                    ss.labels, ss.sort_key, ss.sort_desc, ss.shared, ssv.opened_stamp,
+                    ss.creation_stamp, ss.update_stamp
+                FROM saved_search ss
+                JOIN cnm_user cu ON cu.id = ss.id_owner
+                LEFT JOIN saved_search_view ssv ON ssv.id_search = ss.id AND ssv.id_user = $3
+                WHERE ss.id = $1 AND ss.id_band = $2 AND (ss.id_owner = $3 OR ss.shared)
+            ",
+            )
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id, &id_band, &id_user])
+            .await?
+            .first()
+            .map(SavedSearchInterface::from))
+    }
+
+    pub async fn create(
+        &self,
+        id_band: i32,
+        id_owner: i32,
+        search: SavedSearchRequest,
+    ) -> Result<i32> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO saved_search(id_band, id_owner, name, filters, labels, sort_key, sort_desc, shared)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING id
+            ",
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[
+                    &id_band,
+                    &id_owner,
+                    &search.name,
+                    &serde_json::to_string(&search.filters)?,
+                    &search.labels,
+                    &search.sort.key,
+                    &search.sort.desc,
+                    &search.shared,
+                ],
+            )
+            .await?;
+        Ok(rows[0].get(0))
+    }
+
+    /// Only the owner can change a search, shared or not.
+    pub async fn update(
+        &self,
+        id: i32,
+        id_band: i32,
+        id_owner: i32,
+        search: SavedSearchRequest,
+    ) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE saved_search SET
+                    name = $4, filters = $5, labels = $6, sort_key = $7, sort_desc = $8,
+                    shared = $9, update_stamp = CURRENT_TIMESTAMP
+                WHERE id = $1 AND id_band = $2 AND id_owner = $3
+                RETURNING id
+            ",
+            )
+            .await?;
+        Ok(!client
+            .query(
+                &stmt,
+                &[
+                    &id,
+                    &id_band,
+                    &id_owner,
+                    &search.name,
+                    &serde_json::to_string(&search.filters)?,
+                    &search.labels,
+                    &search.sort.key,
+                    &search.sort.desc,
+                    &search.shared,
+                ],
+            )
+            .await?
+            .is_empty())
+    }
+
+    pub async fn delete(&self, id: i32, id_band: i32, id_owner: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "DELETE FROM saved_search WHERE id = $1 AND id_band = $2 AND id_owner = $3 RETURNING id",
+            )
+            .await?;
+        Ok(!client
+            .query(&stmt, &[&id, &id_band, &id_owner])
+            .await?
+            .is_empty())
+    }
+
+    /// Remembers when the user last opened the search.
+    pub async fn mark_opened(&self, id: i32, id_user: i32) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO saved_search_view(id_search, id_user) VALUES ($1, $2)
+                ON CONFLICT (id_search, id_user) DO UPDATE SET opened_stamp = CURRENT_TIMESTAMP
+            ",
+            )
+            .await?;
+        client.query(&stmt, &[&id, &id_user]).await?;
+        Ok(())
+    }
+}
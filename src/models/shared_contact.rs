@@ -0,0 +1,352 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use super::org::{ContactInterface, CONTACT_COLUMNS};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SharedStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "approved")]
+    Approved,
+    #[serde(rename = "rejected")]
+    Rejected,
+}
+
+impl From<String> for SharedStatus {
+    fn from(s: String) -> Self {
+        match s.as_ref() {
+            "approved" => Self::Approved,
+            "rejected" => Self::Rejected,
+            _ => Self::Pending,
+        }
+    }
+}
+
+impl Display for SharedStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                SharedStatus::Pending => "pending",
+                SharedStatus::Approved => "approved",
+                SharedStatus::Rejected => "rejected",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContactSource {
+    #[serde(rename = "band")]
+    Band,
+    #[serde(rename = "shared")]
+    Shared,
+}
+
+/// A contact of an org as seen by a band. Shared contacts carry the id of
+/// the shared copy, not of a band contact.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContactListItem {
+    #[serde(flatten)]
+    pub contact: ContactInterface,
+    pub source: ContactSource,
+    #[serde(rename = "idShared")]
+    pub id_shared: Option<i32>,
+    /// Moderation status of the copy contributed from a band contact.
+    pub sharing: Option<SharedStatus>,
+}
+
+/// A shared contact as seen by its contributors and the moderators.
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedContactInterface {
+    pub id: i32,
+    #[serde(rename = "idOrg")]
+    pub id_org: i32,
+    #[serde(rename = "orgName")]
+    pub org_name: String,
+    #[serde(rename = "idBand")]
+    pub id_band: i32,
+    #[serde(rename = "bandName")]
+    pub band_name: String,
+    pub status: SharedStatus,
+    #[serde(rename = "contributorPseudo")]
+    pub contributor_pseudo: Option<String>,
+    #[serde(rename = "moderationReason")]
+    pub moderation_reason: Option<String>,
+    #[serde(rename = "moderationStamp")]
+    pub moderation_stamp: Option<NaiveDateTime>,
+    pub contact: ContactInterface,
+}
+
+impl From<&Row> for SharedContactInterface {
+    fn from(row: &Row) -> Self {
+        let status: String = row.get(22);
+        SharedContactInterface {
+            id: row.get(0),
+            id_org: row.get(20),
+            org_name: row.get(26),
+            id_band: row.get(21),
+            band_name: row.get(27),
+            status: SharedStatus::from(status),
+            contributor_pseudo: row.get(28),
+            moderation_reason: row.get(24),
+            moderation_stamp: row.get(25),
+            contact: ContactInterface::from(row),
+        }
+    }
+}
+
+/// Shared contacts matching `condition`, written against the shared contact
+/// table, read by `SharedContactInterface::from`.
+fn gen_shared_select(condition: &str) -> String {
+    format!(
+        "
+        SELECT sc.*, o.name, b.name, cu.pseudo
+        FROM (
+            SELECT
+                {}, id_org, id_band, CAST(status AS VARCHAR(16)),
+                id_contributor, moderation_reason, moderation_stamp
+            FROM shared_contact
+            WHERE {}
+        ) sc
+        JOIN org o ON o.id = sc.id_org
+        JOIN band b ON b.id = sc.id_band
+        LEFT JOIN cnm_user cu ON cu.id = sc.id_contributor
+        ORDER BY o.name, sc.name
+        ",
+        CONTACT_COLUMNS, condition
+    )
+}
+
+pub struct SharedContact(Pool);
+
+impl SharedContact {
+    pub fn new(pool: Pool) -> Self {
+        SharedContact(pool)
+    }
+
+    pub async fn get(&self, id: i32) -> Result<Option<SharedContactInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(gen_shared_select("id = $1").as_str())
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id])
+            .await?
+            .first()
+            .map(SharedContactInterface::from))
+    }
+
+    /// Contributes a copy of a band contact, or refreshes the copy already
+    /// contributed. Either way it waits for moderation. None when the contact
+    /// is not one of the band.
+    pub async fn share(
+        &self,
+        id_contact: i32,
+        id_band: i32,
+        id_user: i32,
+    ) -> Result<Option<SharedContactInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO shared_contact(
+                    id_org, id_contact, id_band, id_contributor, name, firstname, email,
+                    phone, address, zip_code, city, role, job_title, preferred_channel,
+                    language, emails, phones, socials, legal_basis, data_source, basis_stamp)
+                SELECT
+                    id_org, id, id_band, $3, name, firstname, email,
+                    phone, address, zip_code, city, role, job_title, preferred_channel,
+                    language, emails, phones, socials, legal_basis, data_source, basis_stamp
+                FROM contact
+                WHERE id = $1 AND id_band = $2
+                ON CONFLICT (id_contact) DO UPDATE SET
+                    id_contributor = EXCLUDED.id_contributor,
+                    status = 'pending',
+                    name = EXCLUDED.name,
+                    firstname = EXCLUDED.firstname,
+                    email = EXCLUDED.email,
+                    phone = EXCLUDED.phone,
+                    address = EXCLUDED.address,
+                    zip_code = EXCLUDED.zip_code,
+                    city = EXCLUDED.city,
+                    role = EXCLUDED.role,
+                    job_title = EXCLUDED.job_title,
+                    preferred_channel = EXCLUDED.preferred_channel,
+                    language = EXCLUDED.language,
+                    emails = EXCLUDED.emails,
+                    phones = EXCLUDED.phones,
+                    socials = EXCLUDED.socials,
+                    legal_basis = EXCLUDED.legal_basis,
+                    data_source = EXCLUDED.data_source,
+                    basis_stamp = EXCLUDED.basis_stamp,
+                    id_moderator = NULL,
+                    moderation_reason = NULL,
+                    moderation_stamp = NULL
+                RETURNING id
+            ",
+            )
+            .await?;
+        match client
+            .query(&stmt, &[&id_contact, &id_band, &id_user])
+            .await?
+            .first()
+        {
+            Some(row) => self.get(row.get(0)).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the copy contributed from a band contact.
+    pub async fn withdraw(&self, id_contact: i32, id_band: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("DELETE FROM shared_contact WHERE id_contact = $1 AND id_band = $2")
+            .await?;
+        Ok(client.execute(&stmt, &[&id_contact, &id_band]).await? > 0)
+    }
+
+    /// Contacts of the org for a band: its own contacts, with the status of
+    /// their contribution, then the approved contacts shared by other bands
+    /// which do not share an email with them.
+    pub async fn org_contacts(&self, id_org: i32, id_band: i32) -> Result<Vec<ContactListItem>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    SELECT c.*, CAST(sc.status AS VARCHAR(16))
+                    FROM (SELECT {} FROM contact WHERE id_org = $1 AND id_band = $2) c
+                    LEFT JOIN shared_contact sc ON sc.id_contact = c.id
+                    ORDER BY c.name
+                    ",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
+            )
+            .await?;
+        let mut res: Vec<ContactListItem> = client
+            .query(&stmt, &[&id_org, &id_band])
+            .await?
+            .iter()
+            .map(|row| {
+                let sharing: Option<String> = row.get(20);
+                ContactListItem {
+                    contact: ContactInterface::from(row),
+                    source: ContactSource::Band,
+                    id_shared: None,
+                    sharing: sharing.map(SharedStatus::from),
+                }
+            })
+            .collect();
+        let stmt = client
+            .prepare_cached(
+                format!(
+                    "
+                    SELECT {}
+                    FROM shared_contact
+                    WHERE id_org = $1 AND id_band <> $2 AND status = 'approved'
+                    ORDER BY name
+                    ",
+                    CONTACT_COLUMNS
+                )
+                .as_str(),
+            )
+            .await?;
+        let shared: Vec<ContactInterface> = client
+            .query(&stmt, &[&id_org, &id_band])
+            .await?
+            .iter()
+            .map(ContactInterface::from)
+            .collect();
+        for contact in shared {
+            let known = res.iter().any(|item| {
+                item.contact.details.emails.iter().any(|a| {
+                    contact
+                        .details
+                        .emails
+                        .iter()
+                        .any(|b| a.value.eq_ignore_ascii_case(&b.value))
+                })
+            });
+            if !known {
+                res.push(ContactListItem {
+                    id_shared: Some(contact.id),
+                    contact,
+                    source: ContactSource::Shared,
+                    sharing: None,
+                });
+            }
+        }
+        Ok(res)
+    }
+
+    /// Contributions of a band, whatever their status.
+    pub async fn band_contributions(&self, id_band: i32) -> Result<Vec<SharedContactInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(gen_shared_select("id_band = $1").as_str())
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id_band])
+            .await?
+            .iter()
+            .map(SharedContactInterface::from)
+            .collect())
+    }
+
+    pub async fn by_status(&self, status: SharedStatus) -> Result<Vec<SharedContactInterface>> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(gen_shared_select("status = $1::text::shared_contact_status").as_str())
+            .await?;
+        Ok(client
+            .query(&stmt, &[&status.to_string()])
+            .await?
+            .iter()
+            .map(SharedContactInterface::from)
+            .collect())
+    }
+
+    pub async fn moderate(
+        &self,
+        id: i32,
+        id_moderator: i32,
+        status: SharedStatus,
+        reason: Option<String>,
+    ) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE shared_contact
+                SET
+                    status = $3::text::shared_contact_status,
+                    id_moderator = $2,
+                    moderation_reason = $4,
+                    moderation_stamp = CURRENT_TIMESTAMP
+                WHERE id = $1
+            ",
+            )
+            .await?;
+        Ok(client
+            .execute(&stmt, &[&id, &id_moderator, &status.to_string(), &reason])
+            .await?
+            > 0)
+    }
+
+    pub async fn delete(&self, id: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("DELETE FROM shared_contact WHERE id = $1")
+            .await?;
+        Ok(client.execute(&stmt, &[&id]).await? > 0)
+    }
+}
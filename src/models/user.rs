@@ -1,9 +1,15 @@
+use std::fmt::Display;
+
 use anyhow::{anyhow, Result};
 use chrono::NaiveDateTime;
-use deadpool_postgres::Pool;
+use deadpool_postgres::{Pool, Transaction};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::models::band::BandInterface;
+use crate::models::{
+    band::BandInterface,
+    event::{self, EventKind},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserInterface {
@@ -19,6 +25,9 @@ pub struct UserInterface {
     pub verified: bool,
     #[serde(rename = "isAdmin")]
     pub is_admin: Option<bool>,
+    #[serde(rename = "idRole")]
+    pub id_role: Option<i32>,
+    pub role: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -27,6 +36,74 @@ pub struct VerifyResponse {
     pub verified: bool,
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum TokenPurpose {
+    Verify,
+    Reset,
+    EmailChange,
+}
+
+impl Display for TokenPurpose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TokenPurpose::Verify => "verify",
+                TokenPurpose::Reset => "reset",
+                TokenPurpose::EmailChange => "email_change",
+            }
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EmailChangeResponse {
+    pub id: Option<i32>,
+    pub confirmed: bool,
+}
+
+/// Tokens of a user for a purpose matching a clear value which have not
+/// expired nor been used yet.
+const USABLE_TOKEN: &str = "
+    id_user = $1
+    AND purpose = $2::text::token_purpose
+    AND token_hash = encode(digest($3::text, 'sha256'), 'hex')
+    AND used_stamp IS NULL
+    AND expiry_stamp > CURRENT_TIMESTAMP
+";
+
+/// Marks a token as used, returns false if it does not exist, has
+/// expired or has already been used.
+async fn consume_token(
+    transaction: &Transaction<'_>,
+    id_user: i32,
+    purpose: TokenPurpose,
+    token: &str,
+) -> Result<bool> {
+    let stmt = transaction
+        .prepare_cached(&format!(
+            "UPDATE user_token SET used_stamp = CURRENT_TIMESTAMP WHERE {} RETURNING id",
+            USABLE_TOKEN
+        ))
+        .await?;
+    let rows = transaction
+        .query(&stmt, &[&id_user, &purpose.to_string(), &token])
+        .await?;
+    Ok(!rows.is_empty())
+}
+
+/// Marks every unused token of the user as used.
+async fn revoke_tokens(transaction: &Transaction<'_>, id_user: i32) -> Result<()> {
+    let stmt = transaction
+        .prepare_cached(
+            "UPDATE user_token SET used_stamp = CURRENT_TIMESTAMP WHERE id_user = $1 AND used_stamp IS NULL",
+        )
+        .await?;
+    transaction.query(&stmt, &[&id_user]).await?;
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct User(Pool);
 
@@ -68,6 +145,8 @@ impl User {
                 last_login: r.get(6),
                 verified: r.get(7),
                 is_admin: None,
+                id_role: None,
+                role: None,
             })
             .collect::<Vec<UserInterface>>();
 
@@ -100,6 +179,39 @@ impl User {
         Ok(id)
     }
 
+    /// Creates an already verified account for a user signing in through an
+    /// identity provider. The password is random and never disclosed, the
+    /// forgotten password flow can be used to set one.
+    pub async fn create_external(
+        &self,
+        pseudo: String,
+        email: String,
+        name: String,
+        firstname: String,
+    ) -> Result<i32> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "INSERT INTO cnm_user(pseudo, name, firstname, email, pwd, verified)
+            VALUES ($1, $2, $3, $4, crypt(encode(gen_random_bytes(32), 'hex'), gen_salt('bf')), true)
+            RETURNING id",
+            )
+            .await?;
+        let rows = client
+            .query(&stmt, &[&pseudo, &name, &firstname, &email])
+            .await?;
+        Ok(rows[0].get(0))
+    }
+
+    pub async fn touch_login(&self, id_user: i32) -> Result<()> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("UPDATE cnm_user SET last_login = CURRENT_TIMESTAMP WHERE id = $1")
+            .await?;
+        client.query(&stmt, &[&id_user]).await?;
+        Ok(())
+    }
+
     pub async fn authenticate(&self, email: String, pwd: String) -> Result<bool> {
         let client = self.0.get().await?;
         let stmt = client
@@ -136,24 +248,97 @@ impl User {
         Ok(matched)
     }
 
+    /// Creates a new token for `purpose`, invalidating the previous ones
+    /// issued for the same purpose. Only its hash is stored, the clear value
+    /// is returned so that it can be mailed to the user.
+    pub async fn issue_token(
+        &self,
+        id_user: i32,
+        purpose: TokenPurpose,
+        ttl_minutes: i32,
+    ) -> Result<String> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "
+                UPDATE user_token SET used_stamp = CURRENT_TIMESTAMP
+                WHERE id_user = $1 AND purpose = $2::text::token_purpose AND used_stamp IS NULL
+            ",
+            )
+            .await?;
+        client
+            .query(&stmt, &[&id_user, &purpose.to_string()])
+            .await?;
+        let stmt = client
+            .prepare_cached("SELECT encode(gen_random_bytes(32), 'hex')")
+            .await?;
+        let token: String = client.query(&stmt, &[]).await?[0].get(0);
+        let stmt = client
+            .prepare_cached(
+                "
+                INSERT INTO user_token(id_user, purpose, token_hash, expiry_stamp)
+                VALUES (
+                    $1,
+                    $2::text::token_purpose,
+                    encode(digest($3::text, 'sha256'), 'hex'),
+                    CURRENT_TIMESTAMP + make_interval(mins => $4)
+                )
+            ",
+            )
+            .await?;
+        client
+            .query(
+                &stmt,
+                &[&id_user, &purpose.to_string(), &token, &ttl_minutes],
+            )
+            .await?;
+        Ok(token)
+    }
+
+    /// Whether a token can still be used, without using it.
+    pub async fn check_token(
+        &self,
+        id_user: i32,
+        purpose: TokenPurpose,
+        token: String,
+    ) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(&format!("SELECT id FROM user_token WHERE {}", USABLE_TOKEN))
+            .await?;
+        let rows = client
+            .query(&stmt, &[&id_user, &purpose.to_string(), &token])
+            .await?;
+        Ok(!rows.is_empty())
+    }
+
+    /// The token is used in the same transaction as the password change.
     pub async fn forgot_password(
         &self,
         id: i32,
         pwd: String,
-        chain: String,
+        token: String,
     ) -> Result<VerifyResponse> {
-        let client = self.0.get().await?;
-        let stmt = client
-            .prepare(
+        let mut client = self.0.get().await?;
+        let tx = client.transaction().await?;
+        if !consume_token(&tx, id, TokenPurpose::Reset, &token).await? {
+            return Ok(VerifyResponse {
+                id: None,
+                verified: false,
+            });
+        }
+
+        let stmt = tx
+            .prepare_cached(
                 "
-                UPDATE cnm_user SET verified = true, pwd = crypt($3, gen_salt('bf'))
-                WHERE id = $1 AND verify_chain = $2
+                UPDATE cnm_user SET pwd = crypt($2, gen_salt('bf'))
+                WHERE id = $1
                 RETURNING id, verified
             ",
             )
             .await?;
-        let rows = client
-            .query(&stmt, &[&id, &chain, &pwd])
+        let rows = tx
+            .query(&stmt, &[&id, &pwd])
             .await?
             .iter()
             .map(|row| VerifyResponse {
@@ -161,6 +346,8 @@ impl User {
                 verified: row.get(1),
             })
             .collect::<Vec<VerifyResponse>>();
+        revoke_tokens(&tx, id).await?;
+        tx.commit().await?;
 
         if rows.is_empty() {
             Ok(VerifyResponse {
@@ -177,28 +364,111 @@ impl User {
             "pseudo".to_string(),
             "name".to_string(),
             "firstname".to_string(),
-            "email".to_string(),
             "pwd".to_string(),
         ]
         .contains(&field)
         {
-            let client = self.0.get().await?;
+            let mut client = self.0.get().await?;
+            let tx = client.transaction().await?;
             let stmt = if field == "pwd" {
-                client
-                    .prepare_cached("UPDATE cnm_user SET pwd=crypt($1, gen_salt('bf')) WHERE id=$2")
+                tx.prepare_cached("UPDATE cnm_user SET pwd=crypt($1, gen_salt('bf')) WHERE id=$2")
                     .await?
             } else {
-                client
-                    .prepare_cached(&format!("UPDATE cnm_user SET {}=$1 WHERE id=$2", field))
+                tx.prepare_cached(&format!("UPDATE cnm_user SET {}=$1 WHERE id=$2", field))
                     .await?
             };
-            client.query(&stmt, &[&value, &id]).await?;
+            tx.query(&stmt, &[&value, &id]).await?;
+            if field == "pwd" {
+                revoke_tokens(&tx, id).await?;
+            }
+            tx.commit().await?;
             Ok(())
         } else {
             Err(anyhow!(format!("Le champ {} n'existe pas", field)))
         }
     }
 
+    pub async fn request_email_change(
+        &self,
+        id_user: i32,
+        new_email: String,
+        ttl_minutes: i32,
+    ) -> Result<String> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached(
+                "DELETE FROM email_change WHERE id_user = $1 AND confirmed_stamp IS NULL",
+            )
+            .await?;
+        client.query(&stmt, &[&id_user]).await?;
+        let stmt = client
+            .prepare_cached("INSERT INTO email_change(id_user, new_email) VALUES ($1, $2)")
+            .await?;
+        client.query(&stmt, &[&id_user, &new_email]).await?;
+        self.issue_token(id_user, TokenPurpose::EmailChange, ttl_minutes)
+            .await
+    }
+
+    /// The token is only spent when the address is changed, so it can be
+    /// used again once a taken address is freed.
+    pub async fn confirm_email_change(
+        &self,
+        id_user: i32,
+        token: String,
+    ) -> Result<EmailChangeResponse> {
+        let mut client = self.0.get().await?;
+        let tx = client.transaction().await?;
+        if !consume_token(&tx, id_user, TokenPurpose::EmailChange, &token).await? {
+            return Ok(EmailChangeResponse {
+                id: None,
+                confirmed: false,
+            });
+        }
+
+        let stmt = tx
+            .prepare_cached(
+                "
+                UPDATE email_change SET confirmed_stamp = CURRENT_TIMESTAMP
+                WHERE id_user = $1 AND confirmed_stamp IS NULL
+                RETURNING new_email
+            ",
+            )
+            .await?;
+        let rows = tx.query(&stmt, &[&id_user]).await?;
+
+        if rows.is_empty() {
+            return Ok(EmailChangeResponse {
+                id: None,
+                confirmed: false,
+            });
+        }
+
+        let new_email: String = rows[0].get(0);
+        let stmt = tx
+            .prepare_cached(
+                "
+                UPDATE cnm_user SET email = $1
+                WHERE id = $2 AND NOT EXISTS (SELECT 1 FROM cnm_user WHERE email = $1)
+                RETURNING id
+            ",
+            )
+            .await?;
+        let rows = tx.query(&stmt, &[&new_email, &id_user]).await?;
+
+        if rows.is_empty() {
+            Err(anyhow!(format!(
+                "L'adresse {} est déjà utilisée",
+                new_email
+            )))
+        } else {
+            tx.commit().await?;
+            Ok(EmailChangeResponse {
+                id: Some(id_user),
+                confirmed: true,
+            })
+        }
+    }
+
     pub async fn delete(&self, id: i32) -> Result<()> {
         let client = self.0.get().await?;
         let stmt = client
@@ -225,14 +495,32 @@ impl User {
                 name: row.get(2),
                 firstname: row.get(3),
                 email: row.get(4),
-                creation_stamp: row.get(6),
-                last_login: row.get(7),
-                verified: row.get(8),
+                creation_stamp: row.get(5),
+                last_login: row.get(6),
+                verified: row.get(7),
                 is_admin: None,
+                id_role: None,
+                role: None,
             })
             .collect();
 
-        Ok(rows[0].clone())
+        rows.first()
+            .cloned()
+            .ok_or_else(|| anyhow!("Utilisateur {} introuvable", id))
+    }
+
+    /// Platform admins moderate what bands share with each other.
+    pub async fn is_platform_admin(&self, id: i32) -> Result<bool> {
+        let client = self.0.get().await?;
+        let stmt = client
+            .prepare_cached("SELECT platform_admin FROM cnm_user WHERE id = $1")
+            .await?;
+        Ok(client
+            .query(&stmt, &[&id])
+            .await?
+            .first()
+            .map(|row| row.get(0))
+            .unwrap_or(false))
     }
 
     pub async fn get_id_from_email(&self, email: String) -> Result<Option<i32>> {
@@ -249,62 +537,87 @@ impl User {
         }
     }
 
-    pub async fn add_band(&self, id_user: i32, id_band: i32, admin: bool) -> Result<()> {
-        let client = self.0.get().await?;
-        let stmt = client
-            .prepare_cached("INSERT INTO user_band(id_user, id_band, is_admin) VALUES($1, $2, $3)")
+    pub async fn add_band(&self, id_user: i32, id_band: i32, id_role: i32) -> Result<()> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
+            .prepare_cached("INSERT INTO user_band(id_user, id_band, id_role) VALUES($1, $2, $3)")
+            .await?;
+        transaction
+            .query(&stmt, &[&id_user, &id_band, &id_role])
             .await?;
-        client.query(&stmt, &[&id_user, &id_band, &admin]).await?;
+        event::record(
+            &transaction,
+            id_band,
+            Some(id_user),
+            EventKind::MemberJoined,
+            None,
+            Some(id_user),
+            json!({ "idRole": id_role }),
+        )
+        .await?;
+        transaction.commit().await?;
         Ok(())
     }
 
-    pub async fn exit_band(&self, id_user: i32, id_band: i32) -> Result<()> {
-        let client = self.0.get().await?;
-        let stmt = client
+    /// Removes the user from the band, the member left if they are the
+    /// actor and was kicked otherwise.
+    pub async fn exit_band(&self, id_actor: i32, id_user: i32, id_band: i32) -> Result<()> {
+        let mut client = self.0.get().await?;
+        let transaction = client.transaction().await?;
+        let stmt = transaction
             .prepare_cached("DELETE FROM user_band WHERE id_user = $1 AND id_band = $2")
             .await?;
-        client.query(&stmt, &[&id_user, &id_band]).await?;
-        let stmt = client
+        transaction.query(&stmt, &[&id_user, &id_band]).await?;
+        let stmt = transaction
             .prepare_cached(
                 "
-                DELETE FROM org_assign WHERE id_user = $1 AND id_band = $2
+                UPDATE org_assign SET id_user = NULL WHERE id_user = $1 AND id_band = $2
             ",
             )
             .await?;
-        client.query(&stmt, &[&id_user, &id_band]).await?;
-        let stmt = client
-            .prepare_cached(
-                "SELECT CAST(COUNT(id_user) as INT) FROM user_band WHERE id_band = $1 AND is_admin = true",
-            )
-            .await?;
-        let rows = client.query(&stmt, &[&id_band]).await?;
-        let count: i32 = rows[0].get(0);
-
-        if count == 0 {
-            let stmt = client
-                .prepare_cached("DELETE FROM band WHERE id = $1")
-                .await?;
-            client.query(&stmt, &[&id_band]).await?;
-        }
-
+        transaction.query(&stmt, &[&id_user, &id_band]).await?;
+        event::record(
+            &transaction,
+            id_band,
+            Some(id_actor),
+            if id_actor == id_user {
+                EventKind::MemberLeft
+            } else {
+                EventKind::MemberKicked
+            },
+            None,
+            Some(id_user),
+            json!({}),
+        )
+        .await?;
+        transaction.commit().await?;
         Ok(())
     }
 
-    pub async fn verify(&self, id: i32, chain: String) -> Result<VerifyResponse> {
-        let client = self.0.get().await?;
-        let stmt = client
+    pub async fn verify(&self, id: i32, token: String) -> Result<VerifyResponse> {
+        let mut client = self.0.get().await?;
+        let tx = client.transaction().await?;
+        if !consume_token(&tx, id, TokenPurpose::Verify, &token).await? {
+            return Ok(VerifyResponse {
+                id: None,
+                verified: false,
+            });
+        }
+
+        let stmt = tx
             .prepare_cached(
                 "
                 UPDATE cnm_user 
                 SET 
                     verified = true 
-                WHERE id = $1 AND verify_chain = $2
+                WHERE id = $1
                 RETURNING id, verified
             ",
             )
             .await?;
-        let rows = client
-            .query(&stmt, &[&id, &chain])
+        let rows = tx
+            .query(&stmt, &[&id])
             .await?
             .iter()
             .map(|row| VerifyResponse {
@@ -312,6 +625,7 @@ impl User {
                 verified: row.get(1),
             })
             .collect::<Vec<VerifyResponse>>();
+        tx.commit().await?;
         if rows.is_empty() {
             Ok(VerifyResponse {
                 id: None,
@@ -322,52 +636,28 @@ impl User {
         }
     }
 
-    pub async fn deactivate(&self, id_user: i32) -> Result<VerifyResponse> {
-        let client = self.0.get().await?;
-        let stmt = client
-            .prepare_cached("
-                UPDATE cnm_user SET verified = false, verify_chain = md5(random()::text) WHERE id = $1
-                RETURNING id, verified
-            ")
-            .await?;
-        let rows = client
-            .query(&stmt, &[&id_user])
-            .await?
-            .iter()
-            .map(|row| VerifyResponse {
-                id: row.get(0),
-                verified: row.get(1),
-            })
-            .collect::<Vec<VerifyResponse>>();
-        if rows.is_empty() {
-            Ok(VerifyResponse {
-                id: None,
-                verified: false,
-            })
-        } else {
-            Ok(rows[0].clone())
-        }
-    }
-
-    pub async fn get_bands(&self, id_user: i32) -> Result<Vec<BandInterface>> {
+    /// Bands the user belongs to, archived ones only when asked for.
+    pub async fn get_bands(
+        &self,
+        id_user: i32,
+        include_archived: bool,
+    ) -> Result<Vec<BandInterface>> {
         let client = self.0.get().await?;
         let stmt = client
             .prepare_cached(
-                "SELECT b.id, b.name, b.creation_stamp 
+                "SELECT b.id, b.name, b.creation_stamp, b.archived_stamp, b.deleted_stamp
             FROM band b 
             JOIN user_band ub ON b.id = ub.id_band 
-            WHERE ub.id_user = $1",
+            WHERE ub.id_user = $1
+                AND b.deleted_stamp IS NULL
+                AND ($2 OR b.archived_stamp IS NULL)",
             )
             .await?;
         let rows = client
-            .query(&stmt, &[&id_user])
+            .query(&stmt, &[&id_user, &include_archived])
             .await?
             .iter()
-            .map(|row| BandInterface {
-                id: row.get(0),
-                name: row.get(1),
-                creation_stamp: row.get(2),
-            })
+            .map(BandInterface::from)
             .collect();
         Ok(rows)
     }
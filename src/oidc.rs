@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Oidc;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default = "default_signing_algs")]
+    id_token_signing_alg_values_supported: Vec<String>,
+}
+
+fn default_signing_algs() -> Vec<String> {
+    vec!["RS256".to_string()]
+}
+
+impl Discovery {
+    /// Algorithms ID tokens may be signed with: the RSA ones the provider
+    /// declares, whatever the header of the token says.
+    fn signing_algorithms(&self) -> Vec<Algorithm> {
+        self.id_token_signing_alg_values_supported
+            .iter()
+            .filter_map(|alg| Algorithm::from_str(alg).ok())
+            .filter(|alg| {
+                matches!(
+                    alg,
+                    Algorithm::RS256
+                        | Algorithm::RS384
+                        | Algorithm::RS512
+                        | Algorithm::PS256
+                        | Algorithm::PS384
+                        | Algorithm::PS512
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub preferred_username: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+/// Authorization code flow with PKCE against the provider described by the
+/// `oidc` configuration section. Every endpoint is taken from the
+/// provider's discovery document, so a local mock provider only has to
+/// serve `/.well-known/openid-configuration`, a JWKS, an authorization and
+/// a token endpoint.
+pub struct OidcClient {
+    config: Oidc,
+    http: reqwest::Client,
+}
+
+impl OidcClient {
+    pub fn new(config: Oidc) -> Self {
+        OidcClient {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    async fn discover(&self) -> Result<Discovery> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer().trim_end_matches('/')
+        );
+        let discovery: Discovery = self
+            .http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if discovery.issuer.trim_end_matches('/') != self.config.issuer().trim_end_matches('/') {
+            Err(anyhow!("Issuer mismatch in discovery document"))
+        } else {
+            Ok(discovery)
+        }
+    }
+
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String> {
+        let discovery = self.discover().await?;
+        let url = Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id()),
+                ("redirect_uri", &self.config.redirect_url()),
+                ("scope", &self.config.scopes().join(" ")),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+        Ok(url.to_string())
+    }
+
+    /// Exchanges an authorization code and returns the claims of the
+    /// verified ID token.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims> {
+        let discovery = self.discover().await?;
+        let response: TokenResponse = self
+            .http
+            .post(&discovery.token_endpoint)
+            .basic_auth(self.config.client_id(), Some(self.config.client_secret()))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.config.redirect_url()),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let header = decode_header(&response.id_token)?;
+        let jwks: JwkSet = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or_else(|| anyhow!("No matching key in the provider JWKS"))?;
+        let key = match &jwk.algorithm {
+            AlgorithmParameters::RSA(rsa) => DecodingKey::from_rsa_components(&rsa.n, &rsa.e)?,
+            _ => return Err(anyhow!("Unsupported key type in the provider JWKS")),
+        };
+
+        let algorithms = discovery.signing_algorithms();
+        if !algorithms.contains(&header.alg) {
+            return Err(anyhow!("Unexpected ID token algorithm {:?}", header.alg));
+        }
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = algorithms;
+        validation.set_audience(&[self.config.client_id()]);
+        validation.set_issuer(&[discovery.issuer]);
+        let claims = decode::<IdTokenClaims>(&response.id_token, &key, &validation)?.claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            Err(anyhow!("Nonce mismatch in ID token"))
+        } else {
+            Ok(claims)
+        }
+    }
+}
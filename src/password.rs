@@ -0,0 +1,233 @@
+use std::{collections::HashSet, fmt::Display, fs, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+/// bcrypt silently truncates its input after 72 bytes.
+const BCRYPT_MAX_LENGTH: usize = 72;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum PasswordRule {
+    #[serde(rename = "tooShort")]
+    TooShort,
+    #[serde(rename = "tooLong")]
+    TooLong,
+    #[serde(rename = "missingLowercase")]
+    MissingLowercase,
+    #[serde(rename = "missingUppercase")]
+    MissingUppercase,
+    #[serde(rename = "missingDigit")]
+    MissingDigit,
+    #[serde(rename = "missingSymbol")]
+    MissingSymbol,
+    #[serde(rename = "matchesEmail")]
+    MatchesEmail,
+    #[serde(rename = "matchesPseudo")]
+    MatchesPseudo,
+    #[serde(rename = "commonPassword")]
+    CommonPassword,
+}
+
+impl Display for PasswordRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PasswordRule::TooShort => "tooShort",
+                PasswordRule::TooLong => "tooLong",
+                PasswordRule::MissingLowercase => "missingLowercase",
+                PasswordRule::MissingUppercase => "missingUppercase",
+                PasswordRule::MissingDigit => "missingDigit",
+                PasswordRule::MissingSymbol => "missingSymbol",
+                PasswordRule::MatchesEmail => "matchesEmail",
+                PasswordRule::MatchesPseudo => "matchesPseudo",
+                PasswordRule::CommonPassword => "commonPassword",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    #[serde(rename = "minLength")]
+    min_length: usize,
+    #[serde(rename = "maxLength")]
+    max_length: usize,
+    #[serde(rename = "requireLowercase")]
+    require_lowercase: bool,
+    #[serde(rename = "requireUppercase")]
+    require_uppercase: bool,
+    #[serde(rename = "requireDigit")]
+    require_digit: bool,
+    #[serde(rename = "requireSymbol")]
+    require_symbol: bool,
+    #[serde(rename = "commonPasswords")]
+    common_passwords: Option<String>,
+    #[serde(skip)]
+    common: Arc<HashSet<String>>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 10,
+            max_length: BCRYPT_MAX_LENGTH,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: false,
+            common_passwords: None,
+            common: Arc::new(HashSet::new()),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Loads the list of common passwords referenced by `commonPasswords`,
+    /// one password per line.
+    pub fn load_common_passwords(&mut self) -> Result<()> {
+        if let Some(path) = &self.common_passwords {
+            let contents = fs::read_to_string(path)?;
+            self.common = Arc::new(
+                contents
+                    .lines()
+                    .map(|l| l.trim().to_lowercase())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .collect(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns every rule the password breaks, an empty list meaning the
+    /// password is acceptable.
+    pub fn check(&self, pwd: &str, email: &str, pseudo: &str) -> Vec<PasswordRule> {
+        let mut failed = Vec::new();
+        let length = pwd.chars().count();
+        let lowered = pwd.to_lowercase();
+        let email = email.trim().to_lowercase();
+        let local_part = email.split('@').next().unwrap_or_default();
+        let pseudo = pseudo.trim().to_lowercase();
+
+        if length < self.min_length {
+            failed.push(PasswordRule::TooShort);
+        }
+        if pwd.len() > self.max_length.min(BCRYPT_MAX_LENGTH) {
+            failed.push(PasswordRule::TooLong);
+        }
+        if self.require_lowercase && !pwd.chars().any(|c| c.is_lowercase()) {
+            failed.push(PasswordRule::MissingLowercase);
+        }
+        if self.require_uppercase && !pwd.chars().any(|c| c.is_uppercase()) {
+            failed.push(PasswordRule::MissingUppercase);
+        }
+        if self.require_digit && !pwd.chars().any(|c| c.is_ascii_digit()) {
+            failed.push(PasswordRule::MissingDigit);
+        }
+        if self.require_symbol && pwd.chars().all(|c| c.is_alphanumeric()) {
+            failed.push(PasswordRule::MissingSymbol);
+        }
+        if !email.is_empty() && (lowered == email || lowered == local_part) {
+            failed.push(PasswordRule::MatchesEmail);
+        }
+        if !pseudo.is_empty() && lowered == pseudo {
+            failed.push(PasswordRule::MatchesPseudo);
+        }
+        if self.common.contains(&lowered) {
+            failed.push(PasswordRule::CommonPassword);
+        }
+
+        failed
+    }
+
+    /// Same as `check`, but folds the failed rules into a validation error
+    /// ready to be sent back to the client.
+    pub fn validate(&self, pwd: &str, email: &str, pseudo: &str) -> std::result::Result<(), Error> {
+        let failed = self.check(pwd, email, pseudo);
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(
+                failed.iter().map(|rule| rule.to_string()).collect(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strong_password_passes() {
+        let policy = PasswordPolicy::default();
+        assert!(policy
+            .check("Correct horse 42", "jane@example.org", "jane")
+            .is_empty());
+    }
+
+    #[test]
+    fn every_broken_rule_is_reported() {
+        let policy = PasswordPolicy {
+            require_symbol: true,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(
+            policy.check("short", "", ""),
+            vec![
+                PasswordRule::TooShort,
+                PasswordRule::MissingUppercase,
+                PasswordRule::MissingDigit,
+                PasswordRule::MissingSymbol,
+            ]
+        );
+    }
+
+    #[test]
+    fn length_is_capped_by_bcrypt() {
+        let policy = PasswordPolicy {
+            max_length: 200,
+            ..PasswordPolicy::default()
+        };
+        let pwd = format!("Aa1{}", "x".repeat(BCRYPT_MAX_LENGTH));
+        assert_eq!(policy.check(&pwd, "", ""), vec![PasswordRule::TooLong]);
+    }
+
+    #[test]
+    fn identity_and_common_passwords_are_rejected() {
+        let policy = PasswordPolicy {
+            require_uppercase: false,
+            require_digit: false,
+            common: Arc::new(HashSet::from(["sunshine1234".to_string()])),
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(
+            policy.check("Jane.Doe@Example.org", "jane.doe@example.org", ""),
+            vec![PasswordRule::MatchesEmail]
+        );
+        assert_eq!(
+            policy.check("jane.doe123", "Jane.Doe123@example.org", ""),
+            vec![PasswordRule::MatchesEmail]
+        );
+        assert_eq!(
+            policy.check("rockandroll", "", " RockAndRoll "),
+            vec![PasswordRule::MatchesPseudo]
+        );
+        assert_eq!(
+            policy.check("SunShine1234", "", ""),
+            vec![PasswordRule::CommonPassword]
+        );
+    }
+
+    #[test]
+    fn validation_error_lists_rule_codes() {
+        match PasswordPolicy::default().validate("Abcdefghij", "", "") {
+            Err(Error::Validation(codes)) => assert_eq!(codes, vec!["missingDigit"]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod attachment;
 pub mod band;
+pub mod dashboard;
+pub mod interaction;
 pub mod note;
+pub mod notification;
 pub mod org;
+pub mod privacy;
 pub mod user;
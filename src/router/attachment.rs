@@ -0,0 +1,348 @@
+use bytes::Buf;
+use deadpool_postgres::Pool;
+use futures_util::TryStreamExt;
+use warp::{
+    http::{header, Response},
+    hyper::Body,
+    multipart::FormData,
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    auth::{require_permission, with_jwt, Claims},
+    config::{Config, Storage},
+    db_error_to_warp,
+    errors::Error,
+    etointlog,
+    models::{
+        attachment::{Attachment, AttachmentTarget},
+        role::Permission,
+    },
+    storage,
+};
+
+/// Permission needed to add or remove the files of a target.
+fn write_permission(target: AttachmentTarget) -> Permission {
+    match target {
+        AttachmentTarget::Contact(_) => Permission::ManageContacts,
+        AttachmentTarget::Note(_) | AttachmentTarget::Org(_) => Permission::WriteNotes,
+    }
+}
+
+/// A file sent in the `file` field of a multipart form.
+pub struct Upload {
+    /// Name given by the client, made safe to put in a header.
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Reads the uploaded file, rejecting it when it is over the size limit or
+/// flagged by the virus scanner, and when `check_type` is set, when its
+/// content type is not accepted.
+pub async fn read_upload(
+    storage: &Storage,
+    form: FormData,
+    check_type: bool,
+) -> Result<Upload, Rejection> {
+    let parts: Vec<warp::multipart::Part> = form.try_collect().await.map_err(etointlog)?;
+    let part = parts
+        .into_iter()
+        .find(|p| p.name() == "file")
+        .ok_or_else(|| Error::Validation(vec!["missingFile".to_string()]))?;
+    let filename = part.filename().map(|f| f.replace(['"', '/', '\\'], "_"));
+    let content_type = part
+        .content_type()
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    if check_type && !storage.accepts(&content_type) {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "unsupportedFileType".to_string(),
+        ])));
+    }
+    let data = part
+        .stream()
+        .try_fold(Vec::new(), |mut acc, buf| async move {
+            acc.extend_from_slice(buf.chunk());
+            Ok(acc)
+        })
+        .await
+        .map_err(etointlog)?;
+    if data.len() as u64 > storage.max_upload_bytes() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "fileTooLarge".to_string(),
+        ])));
+    }
+    if storage::is_infected(storage, &data)
+        .await
+        .map_err(etointlog)?
+    {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "infectedFile".to_string(),
+        ])));
+    }
+    Ok(Upload {
+        filename,
+        content_type,
+        data,
+    })
+}
+
+/// Streams a stored file as a download instead of loading it in memory.
+pub async fn file_response(
+    storage: &Storage,
+    key: &str,
+    content_type: &str,
+    size: i64,
+    filename: &str,
+) -> Result<Response<Body>, Rejection> {
+    let stream = storage::open(storage)
+        .stream(key)
+        .await
+        .map_err(etointlog)?;
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, size)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Body::wrap_stream(stream))
+        .map_err(|e| warp::reject::custom(etointlog(e)))
+}
+
+async fn attachment_list(
+    id_band: i32,
+    kind: String,
+    id_target: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    let target = AttachmentTarget::parse(&kind, id_target).ok_or_else(warp::reject::not_found)?;
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    Ok(warp::reply::json(
+        &Attachment::new(pool)
+            .list(id_band, target)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn attachment_upload(
+    id_band: i32,
+    kind: String,
+    id_target: i32,
+    pool: Pool,
+    claims: Claims,
+    storage: Storage,
+    form: FormData,
+) -> Result<impl Reply, Rejection> {
+    let target = AttachmentTarget::parse(&kind, id_target).ok_or_else(warp::reject::not_found)?;
+    require_permission(pool.clone(), &claims, id_band, write_permission(target)).await?;
+    let attachment = Attachment::new(pool);
+    if !attachment
+        .target_exists(id_band, target)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+
+    let upload = read_upload(&storage, form, true).await?;
+
+    let (file, key) = attachment
+        .create(
+            id_band,
+            claims.id_user,
+            target,
+            upload.filename.unwrap_or_else(|| target.to_string()),
+            upload.content_type,
+            upload.data.len() as i64,
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    if let Err(e) = storage::open(&storage).save(&key, &upload.data).await {
+        attachment
+            .delete(file.id, id_band)
+            .await
+            .map_err(db_error_to_warp)?;
+        return Err(warp::reject::custom(etointlog(e)));
+    }
+    Ok(warp::reply::json(&file))
+}
+
+async fn attachment_download(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    storage: Storage,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let (file, key) = Attachment::new(pool)
+        .get(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    file_response(
+        &storage,
+        &key,
+        &file.content_type,
+        file.size,
+        &file.filename,
+    )
+    .await
+}
+
+async fn attachment_delete(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    storage: Storage,
+) -> Result<impl Reply, Rejection> {
+    let attachment = Attachment::new(pool.clone());
+    let (file, _) = attachment
+        .get(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    let target = AttachmentTarget::parse(&file.target, file.id_target).ok_or(Error::NotFound)?;
+    require_permission(pool, &claims, id_band, write_permission(target)).await?;
+    let key = attachment
+        .delete(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    storage::open(&storage)
+        .delete(&key)
+        .await
+        .map_err(etointlog)?;
+    Ok(warp::reply())
+}
+
+pub fn attachment_routes(
+    config: Config,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
+    let list_route = warp::path!(i32 / String / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(attachment_list);
+
+    let upload_route = warp::path!(i32 / String / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and(warp::multipart::form().max_length(config.storage().max_upload_bytes() + 64 * 1024))
+        .and_then(attachment_upload);
+
+    let download_route = warp::path!(i32 / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and_then(attachment_download);
+
+    let delete_route = warp::path!(i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and_then(attachment_delete);
+
+    list_route
+        .or(upload_route)
+        .or(download_route)
+        .or(delete_route)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(max: u64, scan: &[&str]) -> Storage {
+        serde_json::from_value(serde_json::json!({
+            "maxUploadBytes": max,
+            "scanCommand": scan,
+        }))
+        .unwrap()
+    }
+
+    /// Runs `read_upload` on a form holding a single file.
+    async fn upload(
+        storage: Storage,
+        content_type: &str,
+        data: &str,
+        check_type: bool,
+    ) -> Result<Upload, Rejection> {
+        let body = format!(
+            "--XX\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"a/b\\\"c\"\r\n\
+             Content-Type: {}\r\n\r\n{}\r\n--XX--\r\n",
+            content_type, data
+        );
+        let filter = warp::multipart::form().and_then(move |form| {
+            let storage = storage.clone();
+            async move { read_upload(&storage, form, check_type).await }
+        });
+        warp::test::request()
+            .method("POST")
+            .header("content-type", "multipart/form-data; boundary=XX")
+            .body(body)
+            .filter(&filter)
+            .await
+    }
+
+    fn code(rejection: Rejection) -> Vec<String> {
+        match rejection.find::<Error>() {
+            Some(Error::Validation(codes)) => codes.clone(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn accepted_files_are_read() {
+        let upload = upload(
+            storage(10, &["sh", "-c", "cat > /dev/null"]),
+            "text/plain",
+            "hello",
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(upload.filename.as_deref(), Some("a_b__c"));
+        assert_eq!(upload.content_type, "text/plain");
+        assert_eq!(upload.data, b"hello");
+    }
+
+    #[tokio::test]
+    async fn limits_and_scan_are_applied() {
+        let rejected = |r: Result<Upload, Rejection>| code(r.err().unwrap());
+        assert_eq!(
+            rejected(upload(storage(10, &[]), "text/html", "<p>", true).await),
+            vec!["unsupportedFileType"]
+        );
+        assert!(upload(storage(10, &[]), "text/vcard", "x", false)
+            .await
+            .is_ok());
+        assert_eq!(
+            rejected(upload(storage(3, &[]), "text/plain", "hello", true).await),
+            vec!["fileTooLarge"]
+        );
+        assert_eq!(
+            rejected(
+                upload(
+                    storage(10, &["sh", "-c", "cat > /dev/null; exit 1"]),
+                    "text/plain",
+                    "hello",
+                    true
+                )
+                .await
+            ),
+            vec!["infectedFile"]
+        );
+    }
+}
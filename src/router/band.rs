@@ -1,16 +1,30 @@
 use deadpool_postgres::Pool;
 use serde::{Deserialize, Serialize};
-use warp::{Filter, Rejection, Reply};
+use tinytemplate::TinyTemplate;
+use warp::{multipart::FormData, Filter, Rejection, Reply};
 
 use crate::{
-    auth::{with_jwt, Claims},
-    config::Config,
+    auth::{require_permission, require_scope, require_session, with_jwt, Claims},
+    config::{Bands, Config, Storage, Tokens},
     db_error_to_warp,
     errors::Error,
-    models::{band::Band, user::UserInterface},
+    etointlog,
+    mailer::Mailer,
+    models::{
+        api_token::Scope,
+        band::Band,
+        band_file::{BandFile, FileKind},
+        band_profile::{BandProfile, BandProfileInterface, BandTemplateContext},
+        event::{Event, EventInterface, EventKind, FeedFilter},
+        invitation::Invitation,
+        role::{Permission, Role, RoleInterface, ADMIN_ROLE, BOOKER_ROLE, VIEWER_ROLE},
+        user::{User, UserInterface},
+    },
+    paginator::{Paginator, DEFAULT_SIZE},
+    storage, unique_error_to_warp,
 };
 
-use super::org::is_user_in_band;
+use super::attachment::{file_response, read_upload};
 
 #[derive(Deserialize)]
 struct BandCreateRequest {
@@ -27,6 +41,7 @@ async fn band_create(
     claims: Claims,
     body: BandCreateRequest,
 ) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
     let band = Band::new(pool);
     let id = band
         .create(claims.id_user, body.name)
@@ -36,16 +51,104 @@ async fn band_create(
 }
 
 async fn band_remove(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::DeleteBand).await?;
     let band = Band::new(pool);
-    if band
-        .is_admin(claims.id_user, id_band)
+    band.remove(id_band, claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply())
+}
+
+async fn band_archive(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageBand).await?;
+    let band = Band::new(pool);
+    band.archive(id_band).await.map_err(db_error_to_warp)?;
+    Ok(warp::reply())
+}
+
+/// Bringing a band back from hiatus is reserved to its owner.
+async fn band_unarchive(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    let my_role =
+        require_permission(pool.clone(), &claims, id_band, Permission::DeleteBand).await?;
+    if !my_role.is_owner() {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    let band = Band::new(pool);
+    band.unarchive(id_band).await.map_err(db_error_to_warp)?;
+    Ok(warp::reply())
+}
+
+async fn band_deleted(pool: Pool, claims: Claims, bands: Bands) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let band = Band::new(pool);
+    Ok(warp::reply::json(
+        &band
+            .get_deleted_owned(claims.id_user, bands.deletion_grace_days())
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn band_restore(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    bands: Bands,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let band = Band::new(pool);
+    let restorable = band
+        .get_deleted_owned(claims.id_user, bands.deletion_grace_days())
+        .await
+        .map_err(db_error_to_warp)?
+        .iter()
+        .any(|b| b.id == id_band);
+    if restorable
+        && band
+            .restore(id_band, bands.deletion_grace_days())
+            .await
+            .map_err(db_error_to_warp)?
+    {
+        Ok(warp::reply())
+    } else {
+        Err(warp::reject::custom(Error::NotFound))
+    }
+}
+
+#[derive(Deserialize)]
+struct TransferRequest {
+    #[serde(rename = "idUser")]
+    id_user: i32,
+    pwd: String,
+}
+
+async fn band_transfer(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: TransferRequest,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let my_role =
+        require_permission(pool.clone(), &claims, id_band, Permission::DeleteBand).await?;
+    if !my_role.is_owner() || body.id_user == claims.id_user {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    if !User::new(pool.clone())
+        .authenticate_with_id(claims.id_user, body.pwd)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    if Band::new(pool)
+        .transfer_ownership(id_band, claims.id_user, body.id_user)
         .await
         .map_err(db_error_to_warp)?
     {
-        band.remove(id_band).await.map_err(|_| Error::Internal)?;
         Ok(warp::reply())
     } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+        Err(warp::reject::custom(Error::NotFound))
     }
 }
 
@@ -56,6 +159,7 @@ struct BandIsAdminResponse {
 }
 
 async fn band_is_admin(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_scope(&claims, Scope::ReadOrgs, Some(id_band))?;
     let band = Band::new(pool);
     Ok(warp::reply::json(&BandIsAdminResponse {
         is_admin: band
@@ -70,24 +174,18 @@ async fn get_band_admins(
     pool: Pool,
     claims: Claims,
 ) -> Result<impl Reply, Rejection> {
-    let band = Band::new(pool.clone());
-    if is_user_in_band(pool, claims, id_band)
-        .await
-        .map_err(|e| Error::Database(e.to_string()))?
-    {
-        Ok(warp::reply::json(
-            &band
-                .get_band_members(id_band)
-                .await
-                .map_err(db_error_to_warp)?
-                .iter()
-                .filter(|user| user.is_admin.unwrap_or(false))
-                .cloned()
-                .collect::<Vec<UserInterface>>(),
-        ))
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
-    }
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let band = Band::new(pool);
+    Ok(warp::reply::json(
+        &band
+            .get_band_members(id_band)
+            .await
+            .map_err(db_error_to_warp)?
+            .iter()
+            .filter(|user| user.is_admin.unwrap_or(false))
+            .cloned()
+            .collect::<Vec<UserInterface>>(),
+    ))
 }
 
 async fn band_edit(
@@ -96,19 +194,12 @@ async fn band_edit(
     claims: Claims,
     body: BandCreateRequest,
 ) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageBand).await?;
     let band = Band::new(pool);
-    if band
-        .is_admin(claims.id_user, id_band)
+    band.edit(id_band, body.name)
         .await
-        .map_err(db_error_to_warp)?
-    {
-        band.edit(id_band, body.name)
-            .await
-            .map_err(db_error_to_warp)?;
-        Ok(warp::reply())
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
-    }
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply())
 }
 
 #[derive(Serialize)]
@@ -131,7 +222,8 @@ struct BandMembersResponse {
     members: Vec<UserInterface>,
 }
 
-async fn band_members(id_band: i32, pool: Pool, _claims: Claims) -> Result<impl Reply, Rejection> {
+async fn band_members(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
     let band = Band::new(pool);
 
     Ok(warp::reply::json(&BandMembersResponse {
@@ -142,26 +234,586 @@ async fn band_members(id_band: i32, pool: Pool, _claims: Claims) -> Result<impl
     }))
 }
 
+/// Finds the role an invitation or a role change refers to. Older clients
+/// only send the `administrator` flag, which maps to the built-in roles.
+pub async fn resolve_role(
+    pool: Pool,
+    id_band: i32,
+    id_role: Option<i32>,
+    administrator: bool,
+) -> Result<RoleInterface, Rejection> {
+    let role = Role::new(pool);
+    let id_role = match id_role {
+        Some(id) => id,
+        None => role
+            .builtin(if administrator {
+                ADMIN_ROLE
+            } else {
+                BOOKER_ROLE
+            })
+            .await
+            .map_err(db_error_to_warp)?,
+    };
+    Ok(role
+        .get(id_role, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?)
+}
+
+/// Invites an email address to join a band and mails it the invitation
+/// link. The email does not need to match an existing account.
+pub async fn invite_to_band(
+    pool: Pool,
+    claims: &Claims,
+    tokens: &Tokens,
+    id_band: i32,
+    email: String,
+    id_role: Option<i32>,
+    administrator: bool,
+) -> Result<i32, Rejection> {
+    let my_role =
+        require_permission(pool.clone(), claims, id_band, Permission::ManageMembers).await?;
+    let role = resolve_role(pool.clone(), id_band, id_role, administrator).await?;
+    if role.is_owner() || !my_role.covers(&role.permissions) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    if email.parse::<lettre::Address>().is_err() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "invalidEmail".to_string(),
+        ])));
+    }
+    if let Some(uid) = User::new(pool.clone())
+        .get_id_from_email(email.clone())
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        if Band::new(pool.clone())
+            .is_member(uid, id_band)
+            .await
+            .map_err(db_error_to_warp)?
+        {
+            return Err(warp::reject::custom(Error::Conflict));
+        }
+    }
+
+    let (id, token) = Invitation::new(pool.clone())
+        .create(
+            id_band,
+            claims.id_user,
+            email,
+            role.id,
+            tokens.invitation_ttl_minutes(),
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Mailer::BandInvitation(id, token)
+        .send_email(claims.id_user, pool)
+        .await
+        .map_err(etointlog)?;
+    Ok(id)
+}
+
+#[derive(Deserialize)]
+struct BandInviteRequest {
+    email: String,
+    #[serde(rename = "idRole")]
+    id_role: Option<i32>,
+    #[serde(default)]
+    administrator: bool,
+}
+
+#[derive(Serialize)]
+struct BandInviteResponse {
+    id: i32,
+}
+
+async fn band_invite(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    tokens: Tokens,
+    body: BandInviteRequest,
+) -> Result<impl Reply, Rejection> {
+    let id = invite_to_band(
+        pool,
+        &claims,
+        &tokens,
+        id_band,
+        body.email,
+        body.id_role,
+        body.administrator,
+    )
+    .await?;
+    Ok(warp::reply::json(&BandInviteResponse { id }))
+}
+
+async fn band_invitations(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageMembers).await?;
+    let invitation = Invitation::new(pool);
+    Ok(warp::reply::json(
+        &invitation
+            .list_pending(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn band_resend_invitation(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    tokens: Tokens,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageMembers).await?;
+    let invitation = Invitation::new(pool.clone());
+    let token = invitation
+        .renew(id, id_band, tokens.invitation_ttl_minutes())
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    Mailer::BandInvitation(id, token)
+        .send_email(claims.id_user, pool)
+        .await
+        .map_err(etointlog)?;
+    Ok(warp::reply())
+}
+
+async fn band_revoke_invitation(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageMembers).await?;
+    let invitation = Invitation::new(pool);
+    if invitation
+        .revoke(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        Ok(warp::reply())
+    } else {
+        Err(warp::reject::custom(Error::NotFound))
+    }
+}
+
+async fn band_roles(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let role = Role::new(pool);
+    Ok(warp::reply::json(
+        &role.list(id_band).await.map_err(db_error_to_warp)?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct RoleRequest {
+    name: String,
+    permissions: Vec<String>,
+}
+
+/// Returns the permissions of the role, unknown ones are rejected.
+fn validate_role_request(
+    my_role: &RoleInterface,
+    body: &RoleRequest,
+) -> Result<Vec<Permission>, Rejection> {
+    if body.name.trim().is_empty() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "emptyName".to_string()
+        ])));
+    }
+    let permissions = body
+        .permissions
+        .iter()
+        .cloned()
+        .map(Permission::try_from)
+        .collect::<Result<Vec<Permission>, String>>()
+        .map_err(|e| Error::Validation(vec![e]))?;
+    if !my_role.covers(&permissions) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    Ok(permissions)
+}
+
+async fn band_create_role(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: RoleRequest,
+) -> Result<impl Reply, Rejection> {
+    let my_role =
+        require_permission(pool.clone(), &claims, id_band, Permission::ManageRoles).await?;
+    let permissions = validate_role_request(&my_role, &body)?;
+    let role = Role::new(pool);
+    Ok(warp::reply::json(
+        &role
+            .create(id_band, body.name, permissions)
+            .await
+            .map_err(unique_error_to_warp)?,
+    ))
+}
+
+async fn band_update_role(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    body: RoleRequest,
+) -> Result<impl Reply, Rejection> {
+    let my_role =
+        require_permission(pool.clone(), &claims, id_band, Permission::ManageRoles).await?;
+    let permissions = validate_role_request(&my_role, &body)?;
+    let role = Role::new(pool);
+    let current = role
+        .get(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    if current.builtin || !my_role.covers(&current.permissions) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    Ok(warp::reply::json(
+        &role
+            .update(id, id_band, body.name, permissions)
+            .await
+            .map_err(unique_error_to_warp)?
+            .ok_or(Error::NotFound)?,
+    ))
+}
+
+async fn band_delete_role(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    let my_role =
+        require_permission(pool.clone(), &claims, id_band, Permission::ManageRoles).await?;
+    let role = Role::new(pool);
+    let current = role
+        .get(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    if current.builtin || !my_role.covers(&current.permissions) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    let fallback = role.builtin(VIEWER_ROLE).await.map_err(db_error_to_warp)?;
+    role.delete(id, id_band, fallback)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply())
+}
+
+#[derive(Deserialize)]
+struct MemberRoleRequest {
+    #[serde(rename = "idRole")]
+    id_role: i32,
+}
+
+async fn band_set_member_role(
+    id_band: i32,
+    id_user: i32,
+    pool: Pool,
+    claims: Claims,
+    body: MemberRoleRequest,
+) -> Result<impl Reply, Rejection> {
+    let my_role =
+        require_permission(pool.clone(), &claims, id_band, Permission::ManageMembers).await?;
+    if id_user == claims.id_user {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    let role = Role::new(pool.clone());
+    let current = role
+        .of_member(id_user, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    let target = resolve_role(pool, id_band, Some(body.id_role), false).await?;
+    // Ownership only changes hands through an explicit transfer
+    if current.is_owner()
+        || target.is_owner()
+        || !my_role.covers(&current.permissions)
+        || !my_role.covers(&target.permissions)
+    {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    role.assign(id_user, id_band, target.id)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&target))
+}
+
+async fn band_profile(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let profile = BandProfile::new(pool);
+    Ok(warp::reply::json(
+        &profile.get(id_band).await.map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn band_update_profile(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: BandProfileInterface,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageBand).await?;
+    let failed = body.check();
+    if !failed.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(failed)));
+    }
+    let profile = BandProfile::new(pool);
+    Ok(warp::reply::json(
+        &profile
+            .save(id_band, body)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct RenderRequest {
+    template: String,
+}
+
+#[derive(Serialize)]
+struct RenderResponse {
+    rendered: String,
+}
+
+#[derive(Serialize)]
+struct RenderContext {
+    band: BandTemplateContext,
+}
+
+/// Fills a message written by a member with the band profile, e.g.
+/// `{band.shortBio}` or `{band.website}`, the same variables invitation
+/// mails get.
+async fn band_render(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: RenderRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let context = RenderContext {
+        band: BandProfile::new(pool)
+            .template_context(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    };
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&tinytemplate::format_unescaped);
+    let invalid = |_| Error::Validation(vec!["invalidTemplate".to_string()]);
+    tt.add_template("message", &body.template)
+        .map_err(invalid)?;
+    Ok(warp::reply::json(&RenderResponse {
+        rendered: tt.render("message", &context).map_err(invalid)?,
+    }))
+}
+
+async fn band_files(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let band_file = BandFile::new(pool);
+    Ok(warp::reply::json(
+        &band_file.list(id_band).await.map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn band_upload_file(
+    id_band: i32,
+    kind: String,
+    pool: Pool,
+    claims: Claims,
+    storage: Storage,
+    form: FormData,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageBand).await?;
+    let kind = match kind.as_ref() {
+        "press_kit" | "rider" => FileKind::from(kind),
+        _ => return Err(warp::reject::not_found()),
+    };
+    let upload = read_upload(&storage, form, true).await?;
+
+    let band_file = BandFile::new(pool);
+    let (file, key) = band_file
+        .create(
+            id_band,
+            claims.id_user,
+            kind,
+            upload.filename.unwrap_or_else(|| kind.to_string()),
+            upload.content_type,
+            upload.data.len() as i64,
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    if let Err(e) = storage::open(&storage).save(&key, &upload.data).await {
+        band_file
+            .delete(file.id, id_band)
+            .await
+            .map_err(db_error_to_warp)?;
+        return Err(warp::reject::custom(etointlog(e)));
+    }
+    Ok(warp::reply::json(&file))
+}
+
+async fn band_download_file(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    storage: Storage,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let (file, key) = BandFile::new(pool)
+        .get(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    file_response(
+        &storage,
+        &key,
+        &file.content_type,
+        file.size,
+        &file.filename,
+    )
+    .await
+}
+
+async fn band_delete_file(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    storage: Storage,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageBand).await?;
+    let key = BandFile::new(pool)
+        .delete(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    storage::open(&storage)
+        .delete(&key)
+        .await
+        .map_err(etointlog)?;
+    Ok(warp::reply())
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+    #[serde(default)]
+    page: i32,
+    size: Option<i32>,
+    /// Comma separated event kinds.
+    kind: Option<String>,
+    actor: Option<i32>,
+    org: Option<i32>,
+    since: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct FeedResponse {
+    events: Vec<EventInterface>,
+    pagination: Paginator,
+}
+
+async fn band_feed(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    query: FeedQuery,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let filter = FeedFilter {
+        kinds: query
+            .kind
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(|k| EventKind::try_from(k.to_string()))
+            .collect::<Result<Vec<EventKind>, String>>()
+            .map_err(|e| Error::Validation(vec![e]))?,
+        actor: query.actor,
+        org: query.org,
+        since: query.since,
+    };
+    let (events, pagination) = Event::new(pool)
+        .feed(
+            id_band,
+            filter,
+            Some(Paginator {
+                page: query.page,
+                size: query.size.unwrap_or(DEFAULT_SIZE),
+                page_count: None,
+                item_count: None,
+            }),
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&FeedResponse { events, pagination }))
+}
+
 pub fn band_routes(
     config: Config,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
     let create_route = warp::path!("add")
         .and(warp::post())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(band_create);
 
     let remove_route = warp::path!("del" / i32)
         .and(warp::delete())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(band_remove);
 
+    let archive_route = warp::path!("archive" / i32)
+        .and(warp::patch())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_archive);
+
+    let unarchive_route = warp::path!("unarchive" / i32)
+        .and(warp::patch())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_unarchive);
+
+    let deleted_route = warp::path!("deleted")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_bands())
+        .and_then(band_deleted);
+
+    let restore_route = warp::path!("restore" / i32)
+        .and(warp::patch())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_bands())
+        .and_then(band_restore);
+
+    let transfer_route = warp::path!("transfer" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(band_transfer);
+
     let update_route = warp::path!("upd" / i32)
         .and(warp::put())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(band_edit);
 
@@ -169,26 +821,163 @@ pub fn band_routes(
         .and(config.with_pool())
         .and_then(band_admin_count);
 
+    let feed_route = warp::path!("feed" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<FeedQuery>())
+        .and_then(band_feed);
+
     let members_route = warp::path!("members" / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(band_members);
 
     let is_admin_route = warp::path!("isadmin" / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(band_is_admin);
 
     let admins_route = warp::path!("admins" / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(get_band_admins);
 
+    let invite_route = warp::path!("invite" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_tokens())
+        .and(warp::body::json())
+        .and_then(band_invite);
+
+    let invitations_route = warp::path!("invitations" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_invitations);
+
+    let resend_invitation_route = warp::path!("invitations" / i32 / i32 / "resend")
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_tokens())
+        .and_then(band_resend_invitation);
+
+    let revoke_invitation_route = warp::path!("invitations" / i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_revoke_invitation);
+
+    let roles_route = warp::path!("roles" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_roles);
+
+    let create_role_route = warp::path!("roles" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(band_create_role);
+
+    let update_role_route = warp::path!("roles" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(band_update_role);
+
+    let delete_role_route = warp::path!("roles" / i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_delete_role);
+
+    let member_role_route = warp::path!("members" / i32 / i32 / "role")
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(band_set_member_role);
+
+    let profile_route = warp::path!("profile" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_profile);
+
+    let update_profile_route = warp::path!("profile" / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(band_update_profile);
+
+    let render_route = warp::path!("render" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(band_render);
+
+    let files_route = warp::path!("files" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(band_files);
+
+    let upload_file_route = warp::path!("files" / i32 / String)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and(warp::multipart::form().max_length(config.storage().max_upload_bytes() + 64 * 1024))
+        .and_then(band_upload_file);
+
+    let download_file_route = warp::path!("files" / i32 / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and_then(band_download_file);
+
+    let delete_file_route = warp::path!("files" / i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and_then(band_delete_file);
+
     create_route
         .or(remove_route)
+        .or(archive_route)
+        .or(unarchive_route)
+        .or(deleted_route)
+        .or(restore_route)
+        .or(transfer_route)
         .or(update_route)
         .or(ba_count_route)
         .or(members_route)
         .or(is_admin_route)
         .or(admins_route)
+        .or(invite_route)
+        .or(invitations_route)
+        .or(resend_invitation_route)
+        .or(revoke_invitation_route)
+        .or(roles_route)
+        .or(create_role_route)
+        .or(update_role_route)
+        .or(delete_role_route)
+        .or(member_role_route)
+        .or(profile_route)
+        .or(update_profile_route)
+        .or(render_route)
+        .or(files_route)
+        .or(upload_file_route)
+        .or(download_file_route)
+        .or(delete_file_route)
+        .or(feed_route)
 }
@@ -0,0 +1,187 @@
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+
+use crate::{
+    auth::{with_jwt, Claims},
+    config::Config,
+    db_error_to_warp,
+    models::{
+        api_token::Scope,
+        band::BandInterface,
+        dashboard::{Dashboard, DashboardNoteInterface, DashboardOrgInterface},
+        org::Status,
+        role::{Permission, Role},
+        user::User,
+    },
+};
+
+const DEFAULT_NOTE_LIMIT: i64 = 20;
+const MAX_NOTE_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+struct DashboardQuery {
+    #[serde(default)]
+    archived: bool,
+    status: Option<String>,
+    limit: Option<i64>,
+}
+
+impl DashboardQuery {
+    fn note_limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_NOTE_LIMIT)
+            .clamp(1, MAX_NOTE_LIMIT)
+    }
+}
+
+#[derive(Serialize)]
+struct DashboardResponse {
+    bands: Vec<BandInterface>,
+    orgs: Vec<DashboardOrgInterface>,
+    reminders: Vec<DashboardOrgInterface>,
+    notes: Vec<DashboardNoteInterface>,
+}
+
+/// Bands of the user whose orgs can be read with the current token.
+async fn readable_bands(
+    pool: Pool,
+    claims: &Claims,
+    include_archived: bool,
+) -> Result<Vec<BandInterface>, Rejection> {
+    let bands = User::new(pool.clone())
+        .get_bands(claims.id_user, include_archived)
+        .await
+        .map_err(db_error_to_warp)?;
+    let role = Role::new(pool);
+    let mut readable = vec![];
+    for band in bands {
+        if !claims.allows(Scope::ReadOrgs, Some(band.id)) {
+            continue;
+        }
+        if role
+            .of_member(claims.id_user, band.id)
+            .await
+            .map_err(db_error_to_warp)?
+            .map(|r| r.has(Permission::ReadOrgs))
+            .unwrap_or(false)
+        {
+            readable.push(band);
+        }
+    }
+    Ok(readable)
+}
+
+fn band_ids(bands: &[BandInterface]) -> Vec<i32> {
+    bands.iter().map(|b| b.id).collect()
+}
+
+async fn dashboard_all(
+    pool: Pool,
+    claims: Claims,
+    query: DashboardQuery,
+) -> Result<impl Reply, Rejection> {
+    let bands = readable_bands(pool.clone(), &claims, query.archived).await?;
+    let ids = band_ids(&bands);
+    let dashboard = Dashboard::new(pool);
+    let orgs = dashboard
+        .orgs(claims.id_user, &ids, query.status.clone().map(Status::from))
+        .await
+        .map_err(db_error_to_warp)?;
+    let reminders = dashboard
+        .orgs(claims.id_user, &ids, Some(Status::Raise))
+        .await
+        .map_err(db_error_to_warp)?;
+    let notes = dashboard
+        .recent_notes(&ids, query.note_limit())
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&DashboardResponse {
+        bands,
+        orgs,
+        reminders,
+        notes,
+    }))
+}
+
+async fn dashboard_orgs(
+    pool: Pool,
+    claims: Claims,
+    query: DashboardQuery,
+) -> Result<impl Reply, Rejection> {
+    let bands = readable_bands(pool.clone(), &claims, query.archived).await?;
+    Ok(warp::reply::json(
+        &Dashboard::new(pool)
+            .orgs(
+                claims.id_user,
+                &band_ids(&bands),
+                query.status.map(Status::from),
+            )
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+/// Orgs to follow up on, that is the ones assigned to the user with the
+/// raise status.
+async fn dashboard_reminders(
+    pool: Pool,
+    claims: Claims,
+    query: DashboardQuery,
+) -> Result<impl Reply, Rejection> {
+    let bands = readable_bands(pool.clone(), &claims, query.archived).await?;
+    Ok(warp::reply::json(
+        &Dashboard::new(pool)
+            .orgs(claims.id_user, &band_ids(&bands), Some(Status::Raise))
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn dashboard_notes(
+    pool: Pool,
+    claims: Claims,
+    query: DashboardQuery,
+) -> Result<impl Reply, Rejection> {
+    let bands = readable_bands(pool.clone(), &claims, query.archived).await?;
+    Ok(warp::reply::json(
+        &Dashboard::new(pool)
+            .recent_notes(&band_ids(&bands), query.note_limit())
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+pub fn dashboard_routes(
+    config: Config,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let all_route = warp::path::end()
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<DashboardQuery>())
+        .and_then(dashboard_all);
+
+    let orgs_route = warp::path!("orgs")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<DashboardQuery>())
+        .and_then(dashboard_orgs);
+
+    let reminders_route = warp::path!("reminders")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<DashboardQuery>())
+        .and_then(dashboard_reminders);
+
+    let notes_route = warp::path!("notes")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<DashboardQuery>())
+        .and_then(dashboard_notes);
+
+    all_route.or(orgs_route).or(reminders_route).or(notes_route)
+}
@@ -0,0 +1,209 @@
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+
+use crate::{
+    auth::{require_permission, with_jwt, Claims},
+    config::Config,
+    db_error_to_warp,
+    errors::Error,
+    models::{
+        interaction::{
+            Interaction, InteractionFilter, InteractionInterface, InteractionKind,
+            InteractionRequest,
+        },
+        org::Org,
+        role::Permission,
+    },
+    paginator::{Paginator, DEFAULT_SIZE},
+};
+
+#[derive(Deserialize)]
+struct InteractionQuery {
+    #[serde(default)]
+    page: i32,
+    size: Option<i32>,
+    org: Option<i32>,
+    contact: Option<i32>,
+    user: Option<i32>,
+    /// Comma separated interaction kinds.
+    kind: Option<String>,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct InteractionListResponse {
+    interactions: Vec<InteractionInterface>,
+    pagination: Paginator,
+}
+
+/// The org must exist and the contact, when given, must be one of the band
+/// contacts of this org.
+async fn check_request(
+    pool: Pool,
+    id_band: i32,
+    body: &mut InteractionRequest,
+) -> Result<(), Rejection> {
+    let org = Org::new(pool);
+    org.get_org_name(body.id_org)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    if let Some(id_contact) = body.id_contact {
+        if !org
+            .get_contacts(body.id_org, id_band)
+            .await
+            .map_err(db_error_to_warp)?
+            .iter()
+            .any(|c| c.id == id_contact)
+        {
+            return Err(warp::reject::custom(Error::Validation(vec![
+                "contactNotInOrg".to_string(),
+            ])));
+        }
+    }
+    body.summary = body
+        .summary
+        .take()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    Ok(())
+}
+
+async fn interaction_list(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    query: InteractionQuery,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let filter = InteractionFilter {
+        org: query.org,
+        contact: query.contact,
+        user: query.user,
+        kinds: query
+            .kind
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(|k| InteractionKind::try_from(k.to_string()))
+            .collect::<Result<Vec<InteractionKind>, String>>()
+            .map_err(|e| Error::Validation(vec![e]))?,
+        since: query.since,
+        until: query.until,
+    };
+    let (interactions, pagination) = Interaction::new(pool)
+        .list(
+            id_band,
+            filter,
+            Some(Paginator {
+                page: query.page,
+                size: query.size.unwrap_or(DEFAULT_SIZE),
+                page_count: None,
+                item_count: None,
+            }),
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&InteractionListResponse {
+        interactions,
+        pagination,
+    }))
+}
+
+async fn interaction_create(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    mut body: InteractionRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    check_request(pool.clone(), id_band, &mut body).await?;
+    Ok(warp::reply::json(
+        &Interaction::new(pool)
+            .create(id_band, claims.id_user, body)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn interaction_update(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    mut body: InteractionRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    check_request(pool.clone(), id_band, &mut body).await?;
+    Ok(warp::reply::json(
+        &Interaction::new(pool)
+            .update(id, id_band, claims.id_user, body)
+            .await
+            .map_err(db_error_to_warp)?
+            .ok_or(Error::NotFound)?,
+    ))
+}
+
+/// Authors remove their own interactions, members allowed to delete notes
+/// remove any of them.
+async fn interaction_delete(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    let interaction = Interaction::new(pool.clone());
+    let target = interaction
+        .get(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    if target.user_id != Some(claims.id_user) {
+        require_permission(pool, &claims, id_band, Permission::DeleteNotes).await?;
+    }
+    interaction
+        .delete(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&target))
+}
+
+pub fn interaction_routes(
+    config: Config,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
+    let list_route = warp::path!(i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<InteractionQuery>())
+        .and_then(interaction_list);
+
+    let create_route = warp::path!(i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(interaction_create);
+
+    let update_route = warp::path!(i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(interaction_update);
+
+    let delete_route = warp::path!(i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(interaction_delete);
+
+    list_route
+        .or(create_route)
+        .or(update_route)
+        .or(delete_route)
+}
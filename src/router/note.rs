@@ -1,34 +1,113 @@
 use deadpool_postgres::Pool;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use warp::{Filter, Rejection, Reply};
 
 use crate::{
-    auth::{with_jwt, Claims},
+    auth::{require_permission, with_jwt, Claims},
     config::Config,
     db_error_to_warp,
     errors::Error,
-    models::{band::Band, note::Note},
+    mailer::Mailer,
+    models::{
+        note::{valid_reaction, Note, NoteInterface},
+        notification::Notification,
+        role::Permission,
+    },
+    paginator::{Paginator, DEFAULT_SIZE},
 };
 
+/// Notifies the members newly mentioned in a note, by email too unless
+/// they opted out. A failed email does not fail the note.
+async fn notify_mentions(
+    pool: Pool,
+    id_band: i32,
+    id_note: i32,
+    id_actor: i32,
+    mentions: &[i32],
+) -> Result<(), Rejection> {
+    if mentions.is_empty() {
+        return Ok(());
+    }
+    let notification = Notification::new(pool.clone());
+    let notified = notification
+        .notify_mentions(id_band, id_note, id_actor, mentions)
+        .await
+        .map_err(db_error_to_warp)?;
+    for id_user in notification
+        .email_recipients(&notified)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        if let Err(e) = Mailer::Mention(id_note)
+            .send_email(id_user, pool.clone())
+            .await
+        {
+            eprintln!("Unable to send mention email to {} {}", id_user, e);
+        }
+    }
+    Ok(())
+}
+
 #[derive(Deserialize)]
 struct NoteCreateRequest {
     #[serde(rename = "idBand")]
     id_band: i32,
     #[serde(rename = "idActivity")]
     id_activity: i32,
+    /// Note answered, none to start a thread.
+    #[serde(rename = "idParent", default)]
+    id_parent: Option<i32>,
     note: String,
 }
 
+#[derive(Deserialize)]
+struct NoteReactionRequest {
+    emoji: String,
+}
+
+#[derive(Deserialize)]
+struct NoteQuery {
+    #[serde(default)]
+    page: i32,
+    size: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct NoteListResponse {
+    notes: Vec<NoteInterface>,
+    pagination: Paginator,
+}
+
 async fn note_create(
     pool: Pool,
     claims: Claims,
     body: NoteCreateRequest,
 ) -> Result<impl Reply, Rejection> {
-    let note = Note::new(pool);
+    require_permission(pool.clone(), &claims, body.id_band, Permission::WriteNotes).await?;
+    let note = Note::new(pool.clone());
+    if let Some(id_parent) = body.id_parent {
+        if note
+            .get_thread_activity(id_parent, body.id_band)
+            .await
+            .map_err(db_error_to_warp)?
+            != Some(body.id_activity)
+        {
+            return Err(warp::reject::custom(Error::Validation(vec![
+                "invalidParent".to_string(),
+            ])));
+        }
+    }
     let res = note
-        .create(claims.id_user, body.id_band, body.id_activity, body.note)
+        .create(
+            claims.id_user,
+            body.id_band,
+            body.id_activity,
+            body.id_parent,
+            body.note,
+        )
         .await
         .map_err(db_error_to_warp)?;
+    notify_mentions(pool, body.id_band, res.id, claims.id_user, &res.mentions).await?;
     Ok(warp::reply::json(&res))
 }
 
@@ -43,76 +122,262 @@ async fn note_edit(
     claims: Claims,
     body: NoteUpdateRequest,
 ) -> Result<impl Reply, Rejection> {
+    let note = Note::new(pool.clone());
+    let id_band = note
+        .get_band_id(body.id)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    let res = note
+        .edit(body.id, id_band, claims.id_user, body.note)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    notify_mentions(pool, id_band, res.id, claims.id_user, &res.mentions).await?;
+    Ok(warp::reply::json(&res))
+}
+
+async fn note_delete(
+    id: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::DeleteNotes).await?;
     let note = Note::new(pool);
     let res = note
-        .edit(body.id, claims.id_user, body.note)
+        .delete(id, id_band, claims.id_user)
         .await
         .map_err(db_error_to_warp)?;
     Ok(warp::reply::json(&res))
 }
 
-async fn note_delete(
+async fn note_restore(
     id: i32,
     id_band: i32,
     pool: Pool,
     claims: Claims,
 ) -> Result<impl Reply, Rejection> {
-    let band = Band::new(pool.clone());
+    require_permission(pool.clone(), &claims, id_band, Permission::DeleteNotes).await?;
     let note = Note::new(pool);
+    let res = note
+        .restore(id, id_band, claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    Ok(warp::reply::json(&res))
+}
 
-    if band
-        .is_admin(claims.id_user, id_band)
+async fn note_pin(
+    id: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    let note = Note::new(pool);
+    let res = note
+        .pin(id, id_band, claims.id_user)
         .await
         .map_err(db_error_to_warp)?
-    {
-        let res = note.delete(id).await.map_err(db_error_to_warp)?;
-        Ok(warp::reply::json(&res))
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
-    }
+        .ok_or(Error::NotFound)?;
+    Ok(warp::reply::json(&res))
 }
 
-async fn note_read_all(
-    id_activity: i32,
+async fn note_unpin(
+    id: i32,
     id_band: i32,
     pool: Pool,
-    _claims: Claims,
+    claims: Claims,
 ) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
     let note = Note::new(pool);
     let res = note
-        .read_all(id_activity, id_band)
+        .unpin(id, id_band, claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    Ok(warp::reply::json(&res))
+}
+
+async fn note_react(
+    id: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: NoteReactionRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    let emoji = body.emoji.trim();
+    if !valid_reaction(emoji) {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "invalidReaction".to_string(),
+        ])));
+    }
+    let res = Note::new(pool)
+        .react(id, id_band, claims.id_user, emoji)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    Ok(warp::reply::json(&res))
+}
+
+async fn note_unreact(
+    id: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: NoteReactionRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::WriteNotes).await?;
+    let res = Note::new(pool)
+        .unreact(id, id_band, claims.id_user, body.emoji.trim())
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    Ok(warp::reply::json(&res))
+}
+
+async fn note_revisions(
+    id: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let res = Note::new(pool)
+        .revisions(id, id_band)
         .await
         .map_err(db_error_to_warp)?;
+    if res.is_empty() {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
     Ok(warp::reply::json(&res))
 }
 
+/// Deleted notes are only listed to the members who may restore them.
+async fn note_read_deleted(
+    id_activity: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::DeleteNotes).await?;
+    Ok(warp::reply::json(
+        &Note::new(pool)
+            .read_deleted(id_activity, id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn note_read_all(
+    id_activity: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    query: NoteQuery,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let (notes, pagination) = Note::new(pool)
+        .read_all(
+            id_activity,
+            id_band,
+            Some(Paginator {
+                page: query.page,
+                size: query.size.unwrap_or(DEFAULT_SIZE),
+                page_count: None,
+                item_count: None,
+            }),
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&NoteListResponse { notes, pagination }))
+}
+
 pub fn note_routes(
     config: Config,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
     let create = warp::path!("create")
         .and(warp::post())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(note_create);
 
     let edit = warp::path("edit")
         .and(warp::put())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(note_edit);
 
     let delete = warp::path!("delete" / i32 / i32)
         .and(warp::delete())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(note_delete);
 
+    let restore = warp::path!("restore" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(note_restore);
+
+    let pin = warp::path!("pin" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(note_pin);
+
+    let unpin = warp::path!("unpin" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(note_unpin);
+
+    let react = warp::path!("react" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(note_react);
+
+    let unreact = warp::path!("unreact" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(note_unreact);
+
+    let revisions = warp::path!("revisions" / i32 / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(note_revisions);
+
+    let read_deleted = warp::path!("deleted" / i32 / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(note_read_deleted);
+
     let read_all = warp::path!("all" / i32 / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
+        .and(warp::query::<NoteQuery>())
         .and_then(note_read_all);
 
-    create.or(edit).or(delete).or(read_all)
+    create
+        .or(edit)
+        .or(delete)
+        .or(restore)
+        .or(pin)
+        .or(unpin)
+        .or(react)
+        .or(unreact)
+        .or(revisions)
+        .or(read_deleted)
+        .or(read_all)
 }
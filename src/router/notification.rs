@@ -0,0 +1,125 @@
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+
+use crate::{
+    auth::{require_session, with_jwt, Claims},
+    config::Config,
+    db_error_to_warp,
+    errors::Error,
+    models::notification::Notification,
+};
+
+#[derive(Deserialize)]
+struct NotificationQuery {
+    #[serde(default)]
+    unread: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NotificationSettings {
+    #[serde(rename = "mentionEmails")]
+    mention_emails: bool,
+}
+
+async fn notification_list(
+    pool: Pool,
+    claims: Claims,
+    query: NotificationQuery,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    Ok(warp::reply::json(
+        &Notification::new(pool)
+            .list(claims.id_user, query.unread)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn notification_read(id: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    if Notification::new(pool)
+        .mark_read(claims.id_user, Some(id))
+        .await
+        .map_err(db_error_to_warp)?
+        == 0
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    Ok(warp::reply::json(&true))
+}
+
+async fn notification_read_all(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    Ok(warp::reply::json(
+        &Notification::new(pool)
+            .mark_read(claims.id_user, None)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn notification_settings(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    Ok(warp::reply::json(&NotificationSettings {
+        mention_emails: Notification::new(pool)
+            .mention_emails(claims.id_user)
+            .await
+            .map_err(db_error_to_warp)?,
+    }))
+}
+
+async fn notification_update_settings(
+    pool: Pool,
+    claims: Claims,
+    body: NotificationSettings,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    Notification::new(pool)
+        .set_mention_emails(claims.id_user, body.mention_emails)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&body))
+}
+
+pub fn notification_routes(
+    config: Config,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
+    let list_route = warp::path::end()
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<NotificationQuery>())
+        .and_then(notification_list);
+
+    let read_route = warp::path!(i32 / "read")
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(notification_read);
+
+    let read_all_route = warp::path!("read")
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(notification_read_all);
+
+    let settings_route = warp::path!("settings")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(notification_settings);
+
+    let update_settings_route = warp::path!("settings")
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(notification_update_settings);
+
+    list_route
+        .or(read_route)
+        .or(read_all_route)
+        .or(settings_route)
+        .or(update_settings_route)
+}
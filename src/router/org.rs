@@ -1,21 +1,39 @@
+use std::collections::HashMap;
+
 use deadpool_postgres::Pool;
 use serde::{Deserialize, Serialize};
-use warp::{Filter, Rejection, Reply};
+use warp::{
+    http::{header, Response},
+    multipart::FormData,
+    Filter, Rejection, Reply,
+};
 
 use crate::{
-    auth::{with_jwt, Claims},
-    config::Config,
+    auth::{require_permission, require_platform_admin, require_scope, with_jwt, Claims},
+    config::{Config, Storage},
     db_error_to_warp,
     errors::Error,
+    etointlog,
     models::{
+        api_token::Scope,
+        assignment::{plan, Assignment, Strategy},
         band::Band,
+        contact::{find_duplicates, merge_values, BandContact, Contact, ContactEmail, MergeField},
         filter,
-        org::{ContactInterface, ContactShort, Org, OrgRawInterface, Status},
+        label::{is_color, parse_labels, Label},
+        org::{ContactInterface, ContactShort, Org, OrgCriteria, OrgRawInterface, Status},
+        role::{Permission, Role},
+        saved_search::{SavedSearch, SavedSearchRequest},
+        shared_contact::{SharedContact, SharedStatus},
         user::User,
     },
     paginator::Paginator,
+    unique_error_to_warp,
+    vcard::{self, Card, OrgIndex, OrgMatch, Version},
 };
 
+use super::attachment::read_upload;
+
 #[derive(Serialize)]
 struct ListResponse {
     orgs: Vec<OrgRawInterface>,
@@ -27,9 +45,13 @@ pub async fn is_user_in_band(
     claims: Claims,
     id_band: i32,
 ) -> anyhow::Result<bool, Error> {
+    if claims.band_restriction.is_some() && claims.band_restriction != Some(id_band) {
+        return Ok(false);
+    }
+
     let user = User::new(pool);
     let bands = user
-        .get_bands(claims.id_user)
+        .get_bands(claims.id_user, true)
         .await
         .map_err(db_error_to_warp)?;
     if bands.iter().any(|bi| bi.id == id_band) {
@@ -39,24 +61,119 @@ pub async fn is_user_in_band(
     }
 }
 
+/// Listing parameters sent as headers: either a saved search id, or the
+/// filters, labels and sort to apply.
+struct ListingOptions {
+    filters: Option<String>,
+    labels: Option<String>,
+    contacted: Option<String>,
+    sort: Option<String>,
+    search: Option<i32>,
+}
+
+fn with_listing_options() -> impl Filter<Extract = (ListingOptions,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("filters")
+        .and(warp::header::optional::<String>("labels"))
+        .and(warp::header::optional::<String>("contacted"))
+        .and(warp::header::optional::<String>("sort"))
+        .and(warp::header::optional::<i32>("search"))
+        .map(|filters, labels, contacted, sort, search| ListingOptions {
+            filters,
+            labels,
+            contacted,
+            sort,
+            search,
+        })
+}
+
+/// Filters on unknown columns are rejected.
+fn check_filters(
+    filters: Vec<filter::FilterIntermediate>,
+) -> Result<Vec<filter::Filter>, Rejection> {
+    filters
+        .into_iter()
+        .map(filter::Filter::try_from)
+        .collect::<Result<Vec<filter::Filter>, String>>()
+        .map_err(|e| warp::reject::custom(Error::Validation(vec![e])))
+}
+
+/// Validation error for a malformed JSON parameter.
+fn invalid(code: &str) -> impl FnOnce(serde_json::Error) -> Error + '_ {
+    move |_| Error::Validation(vec![code.to_string()])
+}
+
+fn parse_filters(filters_str: Option<String>) -> Result<Vec<filter::Filter>, Rejection> {
+    check_filters(
+        serde_json::from_str(&filters_str.unwrap_or_else(|| "[]".to_string()))
+            .map_err(invalid("invalidFilters"))?,
+    )
+}
+
+/// Filters, labels and sort of a listing, a saved search being marked as
+/// opened by the user. The last contacted filter applies to saved searches
+/// too.
+async fn resolve_listing(
+    pool: Pool,
+    claims: &Claims,
+    id_band: i32,
+    options: ListingOptions,
+) -> Result<(OrgCriteria, Option<filter::Sort>), Rejection> {
+    let contacted = match options.contacted {
+        Some(contacted) => {
+            Some(serde_json::from_str(&contacted).map_err(invalid("invalidContactedFilter"))?)
+        }
+        None => None,
+    };
+    if let Some(id) = options.search {
+        let saved_search = SavedSearch::new(pool);
+        let search = saved_search
+            .get(id, id_band, claims.id_user)
+            .await
+            .map_err(db_error_to_warp)?
+            .ok_or(Error::NotFound)?;
+        saved_search
+            .mark_opened(id, claims.id_user)
+            .await
+            .map_err(db_error_to_warp)?;
+        return Ok((
+            OrgCriteria {
+                filters: search.filters().map_err(|e| Error::Validation(vec![e]))?,
+                labels: search.labels.clone(),
+                contacted,
+            },
+            Some(search.sort),
+        ));
+    }
+    let sort = match options.sort {
+        Some(sort) => Some(serde_json::from_str(&sort).map_err(invalid("invalidSort"))?),
+        None => None,
+    };
+    Ok((
+        OrgCriteria {
+            filters: parse_filters(options.filters)?,
+            labels: parse_labels(options.labels),
+            contacted,
+        },
+        sort,
+    ))
+}
+
 async fn org_all_list(
     id_band: i32,
     page: i32,
     size: i32,
     pool: Pool,
-    _claims: Claims,
-    filters_str: String,
+    claims: Claims,
+    options: ListingOptions,
 ) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let (criteria, sort) = resolve_listing(pool.clone(), &claims, id_band, options).await?;
     let org = Org::new(pool);
-    let filters: Vec<filter::FilterIntermediate> =
-        serde_json::from_str(&filters_str).map_err(|_| Error::Internal)?;
     let (res, pag) = org
         .all_orgs(
             id_band,
-            filters
-                .iter()
-                .map(|f| filter::Filter::from(f.clone()))
-                .collect(),
+            criteria,
+            sort,
             Some(Paginator {
                 page,
                 size,
@@ -78,19 +195,17 @@ async fn org_list(
     size: i32,
     pool: Pool,
     claims: Claims,
-    filters_str: String,
+    options: ListingOptions,
 ) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let (criteria, sort) = resolve_listing(pool.clone(), &claims, id_band, options).await?;
     let org = Org::new(pool);
-    let filters: Vec<filter::FilterIntermediate> =
-        serde_json::from_str(&filters_str).map_err(|_| Error::Internal)?;
     let (res, pag) = org
         .band_related_orgs_and_statuses(
             claims.id_user,
             id_band,
-            filters
-                .iter()
-                .map(|f| filter::Filter::from(f.clone()))
-                .collect(),
+            criteria,
+            sort,
             Some(Paginator {
                 page,
                 size,
@@ -106,6 +221,133 @@ async fn org_list(
     }))
 }
 
+#[derive(Serialize)]
+struct SavedSearchResponse {
+    id: i32,
+}
+
+#[derive(Serialize)]
+struct NewMatchesResponse {
+    count: i32,
+    #[serde(rename = "lastOpened")]
+    last_opened: Option<chrono::NaiveDateTime>,
+}
+
+fn validate_search_request(body: &SavedSearchRequest) -> Result<(), Rejection> {
+    let mut failed = vec![];
+    if body.name.trim().is_empty() {
+        failed.push("emptyName".to_string());
+    }
+    if !body.sort.is_valid() {
+        failed.push("invalidSort".to_string());
+    }
+    for f in body.filters.iter().cloned() {
+        if let Err(e) = filter::Filter::try_from(f) {
+            if !failed.contains(&e) {
+                failed.push(e);
+            }
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(warp::reject::custom(Error::Validation(failed)))
+    }
+}
+
+async fn org_searches(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    Ok(warp::reply::json(
+        &SavedSearch::new(pool)
+            .list(id_band, claims.id_user)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn org_create_search(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: SavedSearchRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    validate_search_request(&body)?;
+    let id = SavedSearch::new(pool)
+        .create(id_band, claims.id_user, body)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&SavedSearchResponse { id }))
+}
+
+async fn org_update_search(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    body: SavedSearchRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    validate_search_request(&body)?;
+    if !SavedSearch::new(pool)
+        .update(id, id_band, claims.id_user, body)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    Ok(warp::reply::json(&SavedSearchResponse { id }))
+}
+
+async fn org_delete_search(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    if !SavedSearch::new(pool)
+        .delete(id, id_band, claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    Ok(warp::reply::json(&true))
+}
+
+/// Orgs matching a saved search which appeared since the user last opened
+/// it, the search is not marked as opened.
+async fn org_search_new_matches(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let search = SavedSearch::new(pool.clone())
+        .get(id, id_band, claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    let count = Org::new(pool)
+        .count_new(
+            id_band,
+            OrgCriteria {
+                filters: search.filters().map_err(|e| Error::Validation(vec![e]))?,
+                labels: search.labels.clone(),
+                contacted: None,
+            },
+            search.last_opened,
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&NewMatchesResponse {
+        count,
+        last_opened: search.last_opened,
+    }))
+}
+
 #[derive(Deserialize)]
 struct TagRequest {
     status: String,
@@ -125,8 +367,9 @@ async fn org_tag(
     claims: Claims,
     body: TagRequest,
 ) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::TagOrgs).await?;
     let org = Org::new(pool.clone());
-    let band = Band::new(pool);
+    let band = Band::new(pool.clone());
     let users = band
         .get_band_members(id_band)
         .await
@@ -142,7 +385,8 @@ async fn org_tag(
     let is_assigned = assigned.iter().any(|u| u.id == id_user);
 
     if users.iter().any(|u| u.id == id_user) && (is_admin || is_assigned) {
-        org.tag_orgs(id_user, id_band, body.orgs, Status::from(body.status))
+        let status = Status::from(body.status);
+        org.tag_orgs(claims.id_user, id_user, id_band, body.orgs.clone(), status)
             .await
             .map_err(db_error_to_warp)?;
 
@@ -162,7 +406,8 @@ async fn org_tag(
     }
 }
 
-async fn org_categories(pool: Pool, _: Claims) -> Result<impl Reply, Rejection> {
+async fn org_categories(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_scope(&claims, Scope::ReadOrgs, None)?;
     let org = Org::new(pool);
     Ok(warp::reply::json(
         &org.get_categories().await.map_err(db_error_to_warp)?,
@@ -174,73 +419,654 @@ async fn org_assigned_users(
     pool: Pool,
     claims: Claims,
 ) -> Result<impl Reply, Rejection> {
-    let org = Org::new(pool.clone());
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let org = Org::new(pool);
+    Ok(warp::reply::json(
+        &org.get_assigned_users(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
 
-    if is_user_in_band(pool, claims, id_band).await? {
-        Ok(warp::reply::json(
-            &org.get_assigned_users(id_band)
-                .await
-                .map_err(db_error_to_warp)?,
-        ))
+#[derive(Deserialize)]
+struct AssignRequest {
+    #[serde(rename = "idUser")]
+    id_user: i32,
+    orgs: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+struct UnassignRequest {
+    orgs: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+struct ReassignRequest {
+    from: i32,
+    to: i32,
+    orgs: Option<Vec<i32>>,
+}
+
+#[derive(Deserialize)]
+struct DistributeRequest {
+    members: Vec<i32>,
+    strategy: Strategy,
+    #[serde(rename = "onlyUnassigned", default = "default_only_unassigned")]
+    only_unassigned: bool,
+}
+
+fn default_only_unassigned() -> bool {
+    true
+}
+
+#[derive(Serialize)]
+struct AssignResponse {
+    assigned: u64,
+}
+
+async fn check_members(pool: Pool, id_band: i32, members: &[i32]) -> Result<(), Rejection> {
+    let role = Role::new(pool);
+    for id_user in members {
+        if role
+            .of_member(*id_user, id_band)
+            .await
+            .map_err(db_error_to_warp)?
+            .is_none()
+        {
+            return Err(warp::reject::custom(Error::Validation(vec![
+                "notMember".to_string()
+            ])));
+        }
+    }
+    Ok(())
+}
+
+async fn org_assign(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: AssignRequest,
+) -> Result<impl Reply, Rejection> {
+    // Taking orgs for oneself only needs to be able to tag them.
+    let permission = if body.id_user == claims.id_user {
+        Permission::TagOrgs
+    } else {
+        Permission::AssignOrgs
+    };
+    require_permission(pool.clone(), &claims, id_band, permission).await?;
+    check_members(pool.clone(), id_band, &[body.id_user]).await?;
+    let assigned = Assignment::new(pool)
+        .assign(id_band, body.id_user, body.orgs)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&AssignResponse { assigned }))
+}
+
+async fn org_unassign(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: UnassignRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::AssignOrgs).await?;
+    let assigned = Assignment::new(pool)
+        .unassign(id_band, body.orgs)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&AssignResponse { assigned }))
+}
+
+async fn org_reassign(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: ReassignRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::AssignOrgs).await?;
+    check_members(pool.clone(), id_band, &[body.to]).await?;
+    let assigned = Assignment::new(pool)
+        .reassign(id_band, body.from, body.to, body.orgs)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&AssignResponse { assigned }))
+}
+
+async fn org_distribute(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    filters_str: String,
+    body: DistributeRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::AssignOrgs).await?;
+    if body.members.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "noMembers".to_string()
+        ])));
+    }
+    check_members(pool.clone(), id_band, &body.members).await?;
+    let filters = parse_filters(Some(filters_str))?;
+    let assignment = Assignment::new(pool);
+    let candidates = assignment
+        .candidates(id_band, filters, body.only_unassigned)
+        .await
+        .map_err(db_error_to_warp)?;
+    let workload = assignment
+        .workload(id_band)
+        .await
+        .map_err(db_error_to_warp)?;
+    let members = body
+        .members
+        .iter()
+        .map(|id| {
+            let load = workload
+                .iter()
+                .find(|w| w.id_user == *id)
+                .map(|w| w.total)
+                .unwrap_or(0);
+            (*id, load)
+        })
+        .collect();
+    let shares = plan(body.strategy, members, candidates);
+    let assigned = assignment
+        .assign_all(id_band, &shares)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&AssignResponse { assigned }))
+}
+
+async fn org_workload(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    Ok(warp::reply::json(
+        &Assignment::new(pool)
+            .workload(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct LabelRequest {
+    name: String,
+    color: String,
+}
+
+#[derive(Deserialize)]
+struct LabelOrgsRequest {
+    orgs: Option<Vec<i32>>,
+}
+
+#[derive(Serialize)]
+struct LabelOrgsResponse {
+    updated: u64,
+}
+
+fn validate_label_request(body: &LabelRequest) -> Result<(), Rejection> {
+    let mut failed = vec![];
+    if body.name.trim().is_empty() {
+        failed.push("emptyName".to_string());
+    }
+    if !is_color(&body.color) {
+        failed.push("invalidColor".to_string());
+    }
+    if failed.is_empty() {
+        Ok(())
     } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+        Err(warp::reject::custom(Error::Validation(failed)))
     }
 }
 
-async fn org_contacts(
+async fn org_labels(id_band: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    Ok(warp::reply::json(
+        &Label::new(pool)
+            .list(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn org_create_label(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    body: LabelRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::TagOrgs).await?;
+    validate_label_request(&body)?;
+    Ok(warp::reply::json(
+        &Label::new(pool)
+            .create(id_band, body.name.trim().to_string(), body.color)
+            .await
+            .map_err(unique_error_to_warp)?,
+    ))
+}
+
+async fn org_update_label(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    body: LabelRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::TagOrgs).await?;
+    validate_label_request(&body)?;
+    Ok(warp::reply::json(
+        &Label::new(pool)
+            .update(id, id_band, body.name.trim().to_string(), body.color)
+            .await
+            .map_err(unique_error_to_warp)?
+            .ok_or(Error::NotFound)?,
+    ))
+}
+
+async fn org_delete_label(
+    id_band: i32,
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::TagOrgs).await?;
+    if !Label::new(pool)
+        .delete(id, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    Ok(warp::reply::json(&true))
+}
+
+/// Orgs given in the body, or when there are none the selection matching the
+/// filters header.
+async fn label_selection(
+    pool: Pool,
+    id_band: i32,
+    orgs: Option<Vec<i32>>,
+    filters_str: Option<String>,
+) -> Result<Vec<i32>, Rejection> {
+    if let Some(orgs) = orgs {
+        return Ok(orgs);
+    }
+    Ok(Assignment::new(pool)
+        .candidates(id_band, parse_filters(filters_str)?, false)
+        .await
+        .map_err(db_error_to_warp)?
+        .into_iter()
+        .map(|c| c.id_org)
+        .collect())
+}
+
+async fn org_label_orgs(
+    id_band: i32,
+    id: i32,
+    attach: bool,
+    pool: Pool,
+    claims: Claims,
+    filters_str: Option<String>,
+    body: LabelOrgsRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::TagOrgs).await?;
+    let label = Label::new(pool.clone());
+    if !label
+        .list(id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .iter()
+        .any(|l| l.id == id)
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    let orgs = label_selection(pool, id_band, body.orgs, filters_str).await?;
+    let updated = if attach {
+        label.attach(id, orgs).await
+    } else {
+        label.detach(id, orgs).await
+    }
+    .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&LabelOrgsResponse { updated }))
+}
+
+#[derive(Deserialize)]
+struct VcardExportQuery {
+    org: Option<i32>,
+    #[serde(default)]
+    version: Version,
+}
+
+async fn org_export_vcard(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    query: VcardExportQuery,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let org = Org::new(pool);
+    let contacts = match query.org {
+        Some(id_org) => {
+            let name = org
+                .get_org_name(id_org)
+                .await
+                .map_err(db_error_to_warp)?
+                .ok_or(Error::NotFound)?;
+            org.get_contacts(id_org, id_band)
+                .await
+                .map_err(db_error_to_warp)?
+                .into_iter()
+                .map(|c| (id_org, name.clone(), c))
+                .collect()
+        }
+        None => org
+            .get_band_contacts(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    };
+    let body: String = contacts
+        .iter()
+        .map(|(_, org_name, contact)| vcard::to_vcard(contact, org_name, query.version))
+        .collect();
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/vcard; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"contacts.vcf\"",
+        )
+        .body(body)
+        .map_err(|e| warp::reject::custom(etointlog(e)))
+}
+
+#[derive(Deserialize)]
+struct VcardImportQuery {
+    /// Org receiving every card, matched by name from the cards otherwise.
+    org: Option<i32>,
+    #[serde(rename = "dryRun", default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct ImportedContact {
+    #[serde(rename = "idOrg")]
     id_org: i32,
+    contact: Option<ContactInterface>,
+    card: Card,
+}
+
+#[derive(Serialize)]
+struct ImportIssue {
+    index: usize,
+    card: Card,
+    #[serde(rename = "idOrg")]
+    id_org: Option<i32>,
+    #[serde(rename = "idContact")]
+    id_contact: Option<i32>,
+    candidates: Vec<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failed: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct VcardImportResponse {
+    imported: Vec<ImportedContact>,
+    duplicates: Vec<ImportIssue>,
+    unmatched: Vec<ImportIssue>,
+    invalid: Vec<ImportIssue>,
+}
+
+/// Contact a card is checked against: one of the band, or a card of the
+/// same file imported before it, known by its position among the imported
+/// ones.
+enum KnownId {
+    Contact(i32),
+    Imported(usize),
+}
+
+struct KnownContact {
+    id_org: i32,
+    id: KnownId,
+    name: String,
+    first_name: Option<String>,
+    emails: Vec<ContactEmail>,
+}
+
+/// A card duplicates a contact of the same org sharing one of its emails,
+/// or its full name when either has no email.
+fn find_duplicate<'a>(known: &'a [KnownContact], id_org: i32, card: &Card) -> Option<&'a KnownId> {
+    let same = |a: &Option<String>, b: &Option<String>| {
+        vcard::normalize(a.as_deref().unwrap_or_default())
+            == vcard::normalize(b.as_deref().unwrap_or_default())
+    };
+    known
+        .iter()
+        .filter(|c| c.id_org == id_org)
+        .find(|c| match (c.emails.is_empty(), card.emails.is_empty()) {
+            (false, false) => c.emails.iter().any(|a| {
+                card.emails
+                    .iter()
+                    .any(|b| a.value.eq_ignore_ascii_case(b.value.trim()))
+            }),
+            _ => {
+                vcard::normalize(&c.name) == vcard::normalize(&card.name)
+                    && same(&c.first_name, &card.first_name)
+            }
+        })
+        .map(|c| &c.id)
+}
+
+async fn org_import_vcard(
     id_band: i32,
     pool: Pool,
     claims: Claims,
+    storage: Storage,
+    query: VcardImportQuery,
+    form: FormData,
 ) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    let upload = read_upload(&storage, form, false).await?;
+    let text = String::from_utf8(upload.data)
+        .map_err(|_| Error::Validation(vec!["invalidEncoding".to_string()]))?;
+    let cards = vcard::parse(&text);
+    if cards.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "noCard".to_string()
+        ])));
+    }
+
     let org = Org::new(pool.clone());
-    if is_user_in_band(pool, claims, id_band).await? {
-        Ok(warp::reply::json(
-            &org.get_contacts(id_org, id_band)
+    let index = match query.org {
+        Some(id_org) => {
+            org.get_org_name(id_org)
                 .await
-                .map_err(db_error_to_warp)?,
-        ))
+                .map_err(db_error_to_warp)?
+                .ok_or(Error::NotFound)?;
+            None
+        }
+        None => Some(OrgIndex::new(
+            org.get_org_names().await.map_err(db_error_to_warp)?,
+        )),
+    };
+    let mut known: Vec<KnownContact> = org
+        .get_band_contacts(id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .into_iter()
+        .map(|(id_org, _, c)| KnownContact {
+            id_org,
+            id: KnownId::Contact(c.id),
+            name: c.name,
+            first_name: c.first_name,
+            emails: c.details.emails,
+        })
+        .collect();
+    // Contacts to import with their org and card, nothing is saved until
+    // every card is checked. Duplicates of these cards are kept as the
+    // positions of the duplicate and of the imported card.
+    let mut accepted: Vec<(i32, ContactShort)> = vec![];
+    let mut accepted_cards: Vec<Card> = vec![];
+    let mut pending_duplicates: Vec<(usize, usize)> = vec![];
+    let mut res = VcardImportResponse {
+        imported: vec![],
+        duplicates: vec![],
+        unmatched: vec![],
+        invalid: vec![],
+    };
+    for (i, card) in cards.into_iter().enumerate() {
+        let found = match (&index, query.org) {
+            (_, Some(id_org)) => OrgMatch::Found(id_org),
+            (Some(index), None) => card
+                .org
+                .as_deref()
+                .map(|name| index.find(name))
+                .unwrap_or(OrgMatch::NotFound),
+            (None, None) => OrgMatch::NotFound,
+        };
+        let id_org = match found {
+            OrgMatch::Found(id_org) => id_org,
+            OrgMatch::Ambiguous(candidates) => {
+                res.unmatched.push(ImportIssue {
+                    index: i,
+                    card,
+                    id_org: None,
+                    id_contact: None,
+                    candidates,
+                    failed: vec![],
+                });
+                continue;
+            }
+            OrgMatch::NotFound => {
+                res.unmatched.push(ImportIssue {
+                    index: i,
+                    card,
+                    id_org: None,
+                    id_contact: None,
+                    candidates: vec![],
+                    failed: vec![],
+                });
+                continue;
+            }
+        };
+        if let Some(id) = find_duplicate(&known, id_org, &card) {
+            let id_contact = match id {
+                KnownId::Contact(id_contact) => Some(*id_contact),
+                KnownId::Imported(j) => {
+                    pending_duplicates.push((res.duplicates.len(), *j));
+                    None
+                }
+            };
+            res.duplicates.push(ImportIssue {
+                index: i,
+                card,
+                id_org: Some(id_org),
+                id_contact,
+                candidates: vec![],
+                failed: vec![],
+            });
+            continue;
+        }
+        let mut contact = ContactShort::from(card.clone());
+        let failed = contact.normalize();
+        if !failed.is_empty() {
+            res.invalid.push(ImportIssue {
+                index: i,
+                card,
+                id_org: Some(id_org),
+                id_contact: None,
+                candidates: vec![],
+                failed,
+            });
+            continue;
+        }
+        known.push(KnownContact {
+            id_org,
+            id: KnownId::Imported(accepted.len()),
+            name: contact.name.clone(),
+            first_name: contact.first_name.clone(),
+            emails: contact.details.emails.clone(),
+        });
+        accepted.push((id_org, contact));
+        accepted_cards.push(card);
+    }
+
+    let contacts: Vec<Option<ContactInterface>> = if query.dry_run {
+        vec![None; accepted.len()]
     } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+        org.add_contacts(claims.id_user, id_band, &accepted)
+            .await
+            .map_err(db_error_to_warp)?
+            .into_iter()
+            .map(Some)
+            .collect()
+    };
+    for (d, j) in pending_duplicates {
+        res.duplicates[d].id_contact = contacts[j].as_ref().map(|c| c.id);
     }
+    res.imported = accepted
+        .into_iter()
+        .zip(accepted_cards)
+        .zip(contacts)
+        .map(|(((id_org, _), card), contact)| ImportedContact {
+            id_org,
+            contact,
+            card,
+        })
+        .collect();
+    Ok(warp::reply::json(&res))
 }
 
-async fn org_create_contact(
+async fn org_contacts(
     id_org: i32,
     id_band: i32,
     pool: Pool,
     claims: Claims,
-    body: ContactShort,
 ) -> Result<impl Reply, Rejection> {
-    let org = Org::new(pool.clone());
-    if is_user_in_band(pool, claims, id_band).await? {
-        let res = org
-            .add_contact(id_org, id_band, body)
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    Ok(warp::reply::json(
+        &SharedContact::new(pool)
+            .org_contacts(id_org, id_band)
             .await
-            .map_err(db_error_to_warp)?;
-        Ok(warp::reply::json(&res))
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn org_create_contact(
+    id_org: i32,
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    mut body: ContactShort,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    let failed = body.normalize();
+    if !failed.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(failed)));
     }
+    let org = Org::new(pool);
+    let res = org
+        .add_contact(claims.id_user, id_org, id_band, body)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&res))
 }
 
 async fn org_update_contact(
     pool: Pool,
     claims: Claims,
-    body: ContactInterface,
+    mut body: ContactInterface,
 ) -> Result<impl Reply, Rejection> {
     let org = Org::new(pool.clone());
     let id_band = org
         .get_contact_band_id(body.id)
         .await
         .map_err(db_error_to_warp)?;
-
-    if is_user_in_band(pool, claims, id_band).await? {
-        let res = org.update_contact(body).await.map_err(db_error_to_warp)?;
-        Ok(warp::reply::json(&res))
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    let failed = body.normalize();
+    if !failed.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(failed)));
     }
+    let res = org
+        .update_contact(claims.id_user, body)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&res))
 }
 
 async fn org_delete_contact(
@@ -253,16 +1079,281 @@ async fn org_delete_contact(
         .get_contact_band_id(id_contact)
         .await
         .map_err(db_error_to_warp)?;
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    let res = org
+        .remove_contact(claims.id_user, id_contact)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&res))
+}
+
+async fn org_contact_duplicates(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let contacts = Org::new(pool)
+        .get_band_contacts(id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .into_iter()
+        .map(|(id_org, org_name, contact)| BandContact {
+            id_org,
+            org_name,
+            contact,
+        })
+        .collect();
+    Ok(warp::reply::json(&find_duplicates(contacts)))
+}
+
+/// Contacts the retention job flagged, for the band to review or delete.
+async fn org_stale_contacts(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    let contacts: Vec<BandContact> = Org::new(pool)
+        .get_band_contacts(id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .into_iter()
+        .filter(|(_, _, contact)| contact.stale_since.is_some())
+        .map(|(id_org, org_name, contact)| BandContact {
+            id_org,
+            org_name,
+            contact,
+        })
+        .collect();
+    Ok(warp::reply::json(&contacts))
+}
+
+async fn org_review_contact(
+    id_band: i32,
+    id_contact: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    Ok(warp::reply::json(
+        &Contact::new(pool)
+            .review(id_contact, id_band)
+            .await
+            .map_err(db_error_to_warp)?
+            .ok_or(Error::NotFound)?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    target: i32,
+    sources: Vec<i32>,
+    /// Contact each picked field is taken from, see `merge_values`.
+    #[serde(default)]
+    fields: HashMap<MergeField, i32>,
+}
+
+async fn org_merge_contacts(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+    mut body: MergeRequest,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    body.sources.sort_unstable();
+    body.sources.dedup();
+    if body.sources.is_empty() || body.sources.contains(&body.target) {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "invalidSources".to_string(),
+        ])));
+    }
+    let contacts: HashMap<i32, (i32, ContactInterface)> = Org::new(pool.clone())
+        .get_band_contacts(id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .into_iter()
+        .map(|(id_org, _, c)| (c.id, (id_org, c)))
+        .collect();
+    let (_, target) = contacts.get(&body.target).ok_or(Error::NotFound)?;
+    let sources = body
+        .sources
+        .iter()
+        .map(|id| contacts.get(id).map(|(_, c)| c))
+        .collect::<Option<Vec<&ContactInterface>>>()
+        .ok_or(Error::NotFound)?;
+    let picks = body
+        .fields
+        .iter()
+        .map(|(field, id)| {
+            if *id == body.target || body.sources.contains(id) {
+                contacts.get(id).map(|(_, c)| (*field, c))
+            } else {
+                None
+            }
+        })
+        .collect::<Option<HashMap<MergeField, &ContactInterface>>>()
+        .ok_or_else(|| Error::Validation(vec!["invalidField".to_string()]))?;
+    let mut merged = merge_values(target, &sources, &picks);
+    let failed = merged.normalize();
+    if !failed.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(failed)));
+    }
+    Contact::new(pool)
+        .merge(claims.id_user, id_band, &merged, &body.sources)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&merged))
+}
 
-    if is_user_in_band(pool, claims, id_band).await? {
-        let res = org
-            .remove_contact(id_contact)
+async fn org_share_contact(
+    id_band: i32,
+    id_contact: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    Ok(warp::reply::json(
+        &SharedContact::new(pool)
+            .share(id_contact, id_band, claims.id_user)
             .await
-            .map_err(db_error_to_warp)?;
-        Ok(warp::reply::json(&res))
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+            .map_err(db_error_to_warp)?
+            .ok_or(Error::NotFound)?,
+    ))
+}
+
+async fn org_unshare_contact(
+    id_band: i32,
+    id_contact: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    if !SharedContact::new(pool)
+        .withdraw(id_contact, id_band)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
     }
+    Ok(warp::reply::json(&true))
+}
+
+async fn org_band_contributions(
+    id_band: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ReadOrgs).await?;
+    Ok(warp::reply::json(
+        &SharedContact::new(pool)
+            .band_contributions(id_band)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+/// Copies an approved shared contact into the band contacts, so that the
+/// band can log its interactions with it.
+async fn org_adopt_contact(
+    id_band: i32,
+    id_shared: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_permission(pool.clone(), &claims, id_band, Permission::ManageContacts).await?;
+    let shared = SharedContact::new(pool.clone())
+        .get(id_shared)
+        .await
+        .map_err(db_error_to_warp)?
+        .filter(|s| s.status == SharedStatus::Approved)
+        .ok_or(Error::NotFound)?;
+    let contact = shared.contact;
+    let res = Org::new(pool)
+        .add_contact(
+            claims.id_user,
+            shared.id_org,
+            id_band,
+            ContactShort {
+                name: contact.name,
+                first_name: contact.first_name,
+                email: contact.email,
+                phone: contact.phone,
+                address: contact.address,
+                zip_code: contact.zip_code,
+                city: contact.city,
+                details: contact.details,
+            },
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&res))
+}
+
+#[derive(Deserialize)]
+struct ModerationQuery {
+    status: Option<SharedStatus>,
+}
+
+async fn shared_moderation_list(
+    pool: Pool,
+    claims: Claims,
+    query: ModerationQuery,
+) -> Result<impl Reply, Rejection> {
+    require_platform_admin(pool.clone(), &claims).await?;
+    Ok(warp::reply::json(
+        &SharedContact::new(pool)
+            .by_status(query.status.unwrap_or(SharedStatus::Pending))
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct ModerationRequest {
+    status: SharedStatus,
+    reason: Option<String>,
+}
+
+async fn shared_moderate(
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+    body: ModerationRequest,
+) -> Result<impl Reply, Rejection> {
+    require_platform_admin(pool.clone(), &claims).await?;
+    if body.status == SharedStatus::Pending {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "invalidStatus".to_string(),
+        ])));
+    }
+    let shared = SharedContact::new(pool);
+    if !shared
+        .moderate(id, claims.id_user, body.status, body.reason)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    Ok(warp::reply::json(
+        &shared
+            .get(id)
+            .await
+            .map_err(db_error_to_warp)?
+            .ok_or(Error::NotFound)?,
+    ))
+}
+
+async fn shared_delete(id: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_platform_admin(pool.clone(), &claims).await?;
+    if !SharedContact::new(pool)
+        .delete(id)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::NotFound));
+    }
+    Ok(warp::reply::json(&true))
 }
 
 pub fn org_routes(
@@ -270,65 +1361,286 @@ pub fn org_routes(
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let list_route = warp::path!("list" / i32 / i32 / i32)
         .and(config.with_pool())
-        .and(with_jwt())
-        .and(warp::header("filters"))
+        .and(with_jwt(&config))
+        .and(with_listing_options())
         .and_then(org_list);
 
     let all_route = warp::path!("all" / i32 / i32 / i32)
         .and(config.with_pool())
-        .and(with_jwt())
-        .and(warp::header("filters"))
+        .and(with_jwt(&config))
+        .and(with_listing_options())
         .and_then(org_all_list);
 
     let tag_route = warp::path!("tag" / i32 / i32)
         .and(warp::patch())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(org_tag);
 
     let cat_route = warp::path("categories")
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(org_categories);
 
     let assigned_route = warp::path!("assigned" / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(org_assigned_users);
 
+    let assign_route = warp::path!("assign" / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_assign);
+
+    let unassign_route = warp::path!("unassign" / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_unassign);
+
+    let reassign_route = warp::path!("reassign" / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_reassign);
+
+    let distribute_route = warp::path!("distribute" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::header("filters"))
+        .and(warp::body::json())
+        .and_then(org_distribute);
+
+    let workload_route = warp::path!("workload" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_workload);
+
+    let labels_route = warp::path!("labels" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_labels);
+
+    let create_label_route = warp::path!("labels" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_create_label);
+
+    let update_label_route = warp::path!("labels" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_update_label);
+
+    let delete_label_route = warp::path!("labels" / i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_delete_label);
+
+    let label_orgs_route = warp::path!("label" / i32 / i32)
+        .and(warp::put())
+        .map(|id_band, id| (id_band, id, true))
+        .untuple_one()
+        .or(warp::path!("unlabel" / i32 / i32)
+            .and(warp::put())
+            .map(|id_band, id| (id_band, id, false))
+            .untuple_one())
+        .unify()
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::header::optional("filters"))
+        .and(warp::body::json())
+        .and_then(org_label_orgs);
+
+    let searches_route = warp::path!("searches" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_searches);
+
+    let create_search_route = warp::path!("searches" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_create_search);
+
+    let update_search_route = warp::path!("searches" / i32 / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_update_search);
+
+    let delete_search_route = warp::path!("searches" / i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_delete_search);
+
+    let search_new_route = warp::path!("searches" / i32 / i32 / "new")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_search_new_matches);
+
+    let export_vcard_route = warp::path!("vcard" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<VcardExportQuery>())
+        .and_then(org_export_vcard);
+
+    let import_vcard_route = warp::path!("vcard" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_storage())
+        .and(warp::query::<VcardImportQuery>())
+        .and(warp::multipart::form().max_length(config.storage().max_upload_bytes() + 64 * 1024))
+        .and_then(org_import_vcard);
+
     let get_contacts_route = warp::path!("contact" / i32 / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(org_contacts);
 
     let create_contact_route = warp::path!("ccontact" / i32 / i32)
         .and(warp::post())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(org_create_contact);
 
     let update_contact_route = warp::path!("ucontact")
         .and(warp::put())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(org_update_contact);
 
     let delete_contact_route = warp::path!("dcontact" / i32)
         .and(warp::delete())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(org_delete_contact);
 
+    let duplicates_route = warp::path!("duplicates" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_contact_duplicates);
+
+    let merge_route = warp::path!("merge" / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(org_merge_contacts);
+
+    let stale_route = warp::path!("stale" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_stale_contacts);
+
+    let review_route = warp::path!("review" / i32 / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_review_contact);
+
+    let share_contact_route = warp::path!("share" / i32 / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_share_contact);
+
+    let unshare_contact_route = warp::path!("share" / i32 / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_unshare_contact);
+
+    let contributions_route = warp::path!("shared" / i32)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_band_contributions);
+
+    let adopt_contact_route = warp::path!("adopt" / i32 / i32)
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(org_adopt_contact);
+
+    let moderation_list_route = warp::path!("moderation" / "shared")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<ModerationQuery>())
+        .and_then(shared_moderation_list);
+
+    let moderate_route = warp::path!("moderation" / "shared" / i32)
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(shared_moderate);
+
+    let moderation_delete_route = warp::path!("moderation" / "shared" / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(shared_delete);
+
     list_route
         .or(all_route)
         .or(tag_route)
         .or(cat_route)
         .or(assigned_route)
+        .or(assign_route)
+        .or(unassign_route)
+        .or(reassign_route)
+        .or(distribute_route)
+        .or(workload_route)
+        .or(labels_route)
+        .or(create_label_route)
+        .or(update_label_route)
+        .or(delete_label_route)
+        .or(label_orgs_route)
+        .or(searches_route)
+        .or(create_search_route)
+        .or(update_search_route)
+        .or(delete_search_route)
+        .or(search_new_route)
+        .or(export_vcard_route)
+        .or(import_vcard_route)
         .or(get_contacts_route)
         .or(create_contact_route)
         .or(update_contact_route)
         .or(delete_contact_route)
+        .or(duplicates_route)
+        .or(merge_route)
+        .or(stale_route)
+        .or(review_route)
+        .or(share_contact_route)
+        .or(unshare_contact_route)
+        .or(contributions_route)
+        .or(adopt_contact_route)
+        .or(moderation_list_route)
+        .or(moderate_route)
+        .or(moderation_delete_route)
 }
@@ -0,0 +1,103 @@
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use warp::{Filter, Rejection, Reply};
+
+use crate::{
+    auth::{require_platform_admin, require_session, with_jwt, Claims},
+    config::Config,
+    db_error_to_warp,
+    errors::Error,
+    models::{contact::is_email, privacy::Privacy, user::User},
+};
+
+#[derive(Deserialize)]
+struct SubjectRequest {
+    email: String,
+}
+
+fn check_email(email: &str) -> Result<String, Rejection> {
+    let email = email.trim();
+    if is_email(email) {
+        Ok(email.to_string())
+    } else {
+        Err(warp::reject::custom(Error::Validation(vec![
+            "invalidEmail".to_string(),
+        ])))
+    }
+}
+
+/// Data subject access request, answered by a platform admin.
+async fn privacy_export(
+    pool: Pool,
+    claims: Claims,
+    query: SubjectRequest,
+) -> Result<impl Reply, Rejection> {
+    require_platform_admin(pool.clone(), &claims).await?;
+    let email = check_email(&query.email)?;
+    Ok(warp::reply::json(
+        &Privacy::new(pool)
+            .export(&email)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+/// Users export what is stored about their own email.
+async fn privacy_export_me(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let me = User::new(pool.clone())
+        .read(claims.id_user)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(
+        &Privacy::new(pool)
+            .export(&me.email)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn privacy_erase(
+    pool: Pool,
+    claims: Claims,
+    body: SubjectRequest,
+) -> Result<impl Reply, Rejection> {
+    require_platform_admin(pool.clone(), &claims).await?;
+    let email = check_email(&body.email)?;
+    let report = Privacy::new(pool)
+        .erase(&email)
+        .await
+        .map_err(db_error_to_warp)?;
+    if !report.blocking_bands.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "transferOwnership".to_string(),
+        ])));
+    }
+    Ok(warp::reply::json(&report))
+}
+
+pub fn privacy_routes(
+    config: Config,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
+    let export_route = warp::path!("export")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::query::<SubjectRequest>())
+        .and_then(privacy_export);
+
+    let export_me_route = warp::path!("export" / "me")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(privacy_export_me);
+
+    let erase_route = warp::path!("erase")
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(privacy_erase);
+
+    export_route.or(export_me_route).or(erase_route)
+}
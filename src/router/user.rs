@@ -1,20 +1,31 @@
+use chrono::NaiveDateTime;
 use deadpool_postgres::Pool;
 use serde::{Deserialize, Serialize};
 use warp::{Filter, Rejection, Reply};
 
 use crate::{
-    auth::{create_jwt, with_jwt, Claims},
-    config::Config,
+    auth::{
+        create_jwt, require_active_band, require_permission, require_session, with_jwt, Claims,
+    },
+    config::{Config, Oidc, Tokens},
     db_error_to_warp,
     errors::Error,
     etointlog,
     mailer::Mailer,
     models::{
+        api_token::{ApiToken, ApiTokenInterface, Scope},
         band::Band,
-        user::{User, UserInterface, VerifyResponse},
+        identity::Identity,
+        invitation::{Invitation, InvitationInterface, InvitationStatus},
+        role::{Permission, Role},
+        user::{TokenPurpose, User, UserInterface, VerifyResponse},
     },
+    oidc::{IdTokenClaims, OidcClient},
+    password::PasswordPolicy,
 };
 
+use super::{band::invite_to_band, org::is_user_in_band};
+
 #[derive(Deserialize)]
 struct UserCreationRequest {
     pub pseudo: String,
@@ -29,9 +40,15 @@ struct UserCreationResponse {
     id: i32,
 }
 
-async fn user_create(pool: Pool, body: UserCreationRequest) -> Result<impl Reply, Rejection> {
+async fn user_create(
+    pool: Pool,
+    policy: PasswordPolicy,
+    tokens: Tokens,
+    body: UserCreationRequest,
+) -> Result<impl Reply, Rejection> {
     let user = User::new(pool.clone());
-    let mailer = Mailer::Verify;
+
+    policy.validate(&body.pwd, &body.email, &body.pseudo)?;
 
     let resp = UserCreationResponse {
         id: user
@@ -39,6 +56,11 @@ async fn user_create(pool: Pool, body: UserCreationRequest) -> Result<impl Reply
             .await
             .map_err(db_error_to_warp)?,
     };
+    let token = user
+        .issue_token(resp.id, TokenPurpose::Verify, tokens.verify_ttl_minutes())
+        .await
+        .map_err(db_error_to_warp)?;
+    let mailer = Mailer::Verify(token);
     mailer.send_email(resp.id, pool).await.map_err(|e| {
         eprintln!("Email sender problem {}", e);
         Error::Internal
@@ -46,9 +68,9 @@ async fn user_create(pool: Pool, body: UserCreationRequest) -> Result<impl Reply
     Ok(warp::reply::json(&resp))
 }
 
-async fn user_verify(id: i32, chain: String, pool: Pool) -> Result<impl Reply, Rejection> {
+async fn user_verify(id: i32, token: String, pool: Pool) -> Result<impl Reply, Rejection> {
     let user = User::new(pool);
-    let resp = user.verify(id, chain).await.map_err(db_error_to_warp)?;
+    let resp = user.verify(id, token).await.map_err(db_error_to_warp)?;
     Ok(warp::reply::json(&resp))
 }
 
@@ -61,22 +83,98 @@ struct UserUpdateRequest {
 async fn user_update(
     pool: Pool,
     claims: Claims,
+    policy: PasswordPolicy,
     body: UserUpdateRequest,
 ) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
     let user = User::new(pool);
+    if body.field == "pwd" {
+        let me = user.read(claims.id_user).await.map_err(db_error_to_warp)?;
+        policy.validate(&body.value, &me.email, &me.pseudo)?;
+    }
     user.update(claims.id_user, body.field, body.value)
         .await
         .map_err(db_error_to_warp)?;
     Ok(warp::reply())
 }
 
+#[derive(Deserialize)]
+struct EmailChangeRequest {
+    email: String,
+    pwd: String,
+}
+
+#[derive(Serialize)]
+struct EmailChangeRequestResponse {
+    requested: bool,
+}
+
+async fn user_change_email(
+    pool: Pool,
+    claims: Claims,
+    tokens: Tokens,
+    body: EmailChangeRequest,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let user = User::new(pool.clone());
+    if body.email.parse::<lettre::Address>().is_err() {
+        return Err(warp::reject::custom(Error::Validation(vec![
+            "invalidEmail".to_string(),
+        ])));
+    }
+    if !user
+        .authenticate_with_id(claims.id_user, body.pwd)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    if user
+        .get_id_from_email(body.email.clone())
+        .await
+        .map_err(db_error_to_warp)?
+        .is_some()
+    {
+        return Err(warp::reject::custom(Error::Conflict));
+    }
+
+    let token = user
+        .request_email_change(
+            claims.id_user,
+            body.email,
+            tokens.email_change_ttl_minutes(),
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Mailer::EmailChangeConfirm(token)
+        .send_email(claims.id_user, pool.clone())
+        .await
+        .map_err(etointlog)?;
+    Mailer::EmailChangeNotice
+        .send_email(claims.id_user, pool)
+        .await
+        .map_err(etointlog)?;
+    Ok(warp::reply::json(&EmailChangeRequestResponse {
+        requested: true,
+    }))
+}
+
+async fn user_confirm_email(id: i32, token: String, pool: Pool) -> Result<impl Reply, Rejection> {
+    let user = User::new(pool);
+    let resp = user
+        .confirm_email_change(id, token)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&resp))
+}
+
 async fn user_read(id: i32, pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
     let user = User::new(pool);
     let my_bands = user
-        .get_bands(claims.id_user)
+        .get_bands(claims.id_user, true)
         .await
         .map_err(db_error_to_warp)?;
-    let them_bands = user.get_bands(id).await.map_err(db_error_to_warp)?;
+    let them_bands = user.get_bands(id, true).await.map_err(db_error_to_warp)?;
 
     if my_bands
         .iter()
@@ -97,6 +195,7 @@ struct UserPasswordForgotRequest {
 
 async fn user_forgot_password_request(
     pool: Pool,
+    tokens: Tokens,
     body: UserPasswordForgotRequest,
 ) -> Result<impl Reply, Rejection> {
     let user = User::new(pool.clone());
@@ -105,11 +204,17 @@ async fn user_forgot_password_request(
         .await
         .map_err(db_error_to_warp)?
     {
-        let resp = user.deactivate(uid).await.map_err(db_error_to_warp)?;
-        let mailer = Mailer::ForgotPassword;
+        let token = user
+            .issue_token(uid, TokenPurpose::Reset, tokens.reset_ttl_minutes())
+            .await
+            .map_err(db_error_to_warp)?;
+        let mailer = Mailer::ForgotPassword(token);
 
         mailer.send_email(uid, pool).await.map_err(etointlog)?;
-        Ok(warp::reply::json(&resp))
+        Ok(warp::reply::json(&VerifyResponse {
+            id: Some(uid),
+            verified: false,
+        }))
     } else {
         Ok(warp::reply::json(&VerifyResponse {
             id: None,
@@ -122,16 +227,32 @@ async fn user_forgot_password_request(
 struct ForgotPasswordModRequest {
     id: i32,
     pwd: String,
-    chain: String,
+    #[serde(alias = "chain")]
+    token: String,
 }
 
 async fn user_forgot_password_verify(
     pool: Pool,
+    policy: PasswordPolicy,
     body: ForgotPasswordModRequest,
 ) -> Result<impl Reply, Rejection> {
     let user = User::new(pool);
+    // Unknown users and bad tokens get the same answer, and the policy is
+    // only checked for the holder of a valid token.
+    if !user
+        .check_token(body.id, TokenPurpose::Reset, body.token.clone())
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        return Ok(warp::reply::json(&VerifyResponse {
+            id: None,
+            verified: false,
+        }));
+    }
+    let them = user.read(body.id).await.map_err(db_error_to_warp)?;
+    policy.validate(&body.pwd, &them.email, &them.pseudo)?;
     let resp = user
-        .forgot_password(body.id, body.pwd, body.chain)
+        .forgot_password(body.id, body.pwd, body.token)
         .await
         .map_err(db_error_to_warp)?;
 
@@ -143,36 +264,120 @@ struct UserAddBandRequest {
     email: String,
     #[serde(rename = "idBand")]
     id_band: i32,
+    #[serde(rename = "idRole")]
+    id_role: Option<i32>,
+    #[serde(default)]
     administrator: bool,
 }
 
+#[derive(Serialize)]
+struct UserAddBandResponse {
+    #[serde(rename = "idInvitation")]
+    id_invitation: i32,
+}
+
+/// Kept for older clients: membership now goes through an invitation the
+/// invitee has to accept.
 async fn user_add_band(
     pool: Pool,
     claims: Claims,
+    tokens: Tokens,
     body: UserAddBandRequest,
 ) -> Result<impl Reply, Rejection> {
+    let id_invitation = invite_to_band(
+        pool,
+        &claims,
+        &tokens,
+        body.id_band,
+        body.email,
+        body.id_role,
+        body.administrator,
+    )
+    .await?;
+    Ok(warp::reply::json(&UserAddBandResponse { id_invitation }))
+}
+
+#[derive(Serialize)]
+struct InvitationResponse {
+    invitation: InvitationInterface,
+    #[serde(rename = "hasAccount")]
+    has_account: bool,
+}
+
+async fn user_read_invitation(id: i32, token: String, pool: Pool) -> Result<impl Reply, Rejection> {
+    let invitation = Invitation::new(pool.clone())
+        .find_pending(id, token)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    let has_account = User::new(pool)
+        .get_id_from_email(invitation.email.clone())
+        .await
+        .map_err(db_error_to_warp)?
+        .is_some();
+    Ok(warp::reply::json(&InvitationResponse {
+        invitation,
+        has_account,
+    }))
+}
+
+async fn user_accept_invitation(
+    id: i32,
+    token: String,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let invitation = Invitation::new(pool.clone());
+    let pending = invitation
+        .find_pending(id, token)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    require_active_band(pool.clone(), pending.id_band).await?;
     let user = User::new(pool.clone());
-    let band = Band::new(pool);
-    if let Some(uid) = user
-        .get_id_from_email(body.email)
+    let me = user.read(claims.id_user).await.map_err(db_error_to_warp)?;
+    if !me.email.eq_ignore_ascii_case(&pending.email) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+
+    if !Band::new(pool.clone())
+        .is_member(claims.id_user, pending.id_band)
         .await
         .map_err(db_error_to_warp)?
     {
-        if band
-            .is_admin(claims.id_user, body.id_band)
+        user.add_band(claims.id_user, pending.id_band, pending.id_role)
             .await
-            .map_err(db_error_to_warp)?
-        {
-            user.add_band(uid, body.id_band, body.administrator)
-                .await
-                .map_err(db_error_to_warp)?;
-            Ok(warp::reply())
-        } else {
-            Err(warp::reject::custom(Error::Unauthorized))
-        }
-    } else {
-        Err(warp::reject::custom(Error::NotFound))
+            .map_err(db_error_to_warp)?;
     }
+    invitation
+        .answer(id, InvitationStatus::Accepted)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(
+        &user
+            .get_bands(claims.id_user, false)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn user_decline_invitation(
+    id: i32,
+    token: String,
+    pool: Pool,
+) -> Result<impl Reply, Rejection> {
+    let invitation = Invitation::new(pool);
+    invitation
+        .find_pending(id, token)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    invitation
+        .answer(id, InvitationStatus::Declined)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply())
 }
 
 #[derive(Deserialize)]
@@ -180,6 +385,16 @@ struct ExitBandRequest {
     #[serde(rename = "idBand")]
     id_band: i32,
     pwd: String,
+    /// Confirms that leaving as owner or last administrator deletes the band.
+    #[serde(rename = "deleteBand", default)]
+    delete_band: bool,
+}
+
+#[derive(Serialize)]
+struct ExitBandResponse {
+    exited: bool,
+    #[serde(rename = "bandDeleted")]
+    band_deleted: bool,
 }
 
 async fn user_exit_band(
@@ -187,18 +402,55 @@ async fn user_exit_band(
     claims: Claims,
     body: ExitBandRequest,
 ) -> Result<impl Reply, Rejection> {
-    let user = User::new(pool);
-    if user
+    require_session(&claims)?;
+    require_active_band(pool.clone(), body.id_band).await?;
+    let user = User::new(pool.clone());
+    let band = Band::new(pool.clone());
+    if !user
         .authenticate_with_id(claims.id_user, body.pwd)
         .await
         .map_err(db_error_to_warp)?
     {
-        user.exit_band(claims.id_user, body.id_band)
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+
+    let role = Role::new(pool.clone())
+        .of_member(claims.id_user, body.id_band)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::NotFound)?;
+    let last_admin = role.has(Permission::ManageMembers)
+        && band
+            .get_admin_count(body.id_band)
+            .await
+            .map_err(db_error_to_warp)?
+            <= 1;
+
+    if role.is_owner() || last_admin {
+        if !body.delete_band {
+            return Err(warp::reject::custom(Error::Validation(vec![
+                if role.is_owner() {
+                    "transferOwnership".to_string()
+                } else {
+                    "promoteAdmin".to_string()
+                },
+            ])));
+        }
+        band.remove(body.id_band, claims.id_user)
             .await
             .map_err(db_error_to_warp)?;
-        Ok(warp::reply())
+        Ok(warp::reply::json(&ExitBandResponse {
+            exited: false,
+            band_deleted: true,
+        }))
     } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+        user.exit_band(claims.id_user, claims.id_user, body.id_band)
+            .await
+            .map_err(db_error_to_warp)?;
+        Ok(warp::reply::json(&ExitBandResponse {
+            exited: true,
+            band_deleted: false,
+        }))
     }
 }
 
@@ -222,19 +474,33 @@ async fn user_kick_band(
     claims: Claims,
     body: KickBandRequest,
 ) -> Result<impl Reply, Rejection> {
+    let mine = require_permission(
+        pool.clone(),
+        &claims,
+        body.id_band,
+        Permission::ManageMembers,
+    )
+    .await?;
     let user = User::new(pool.clone());
-    let band = Band::new(pool);
     if user
         .authenticate_with_id(claims.id_user, body.pwd)
         .await
         .map_err(db_error_to_warp)?
     {
-        if band
-            .is_admin(claims.id_user, body.id_band)
+        let their_role = Role::new(pool.clone())
+            .of_member(body.id_user, body.id_band)
             .await
-            .map_err(db_error_to_warp)?
-        {
-            user.exit_band(body.id_user, body.id_band)
+            .map_err(db_error_to_warp)?;
+        let allowed = match their_role {
+            Some(theirs) => {
+                body.id_user != claims.id_user
+                    && !theirs.is_owner()
+                    && mine.covers(&theirs.permissions)
+            }
+            _ => false,
+        };
+        if allowed {
+            user.exit_band(claims.id_user, body.id_user, body.id_band)
                 .await
                 .map_err(db_error_to_warp)?;
             Ok(warp::reply::json(&KickBandResponse {
@@ -244,13 +510,13 @@ async fn user_kick_band(
         } else {
             Ok(warp::reply::json(&KickBandResponse {
                 kicked: false,
-                reason: Some("Mauvais mot de passe".to_string()),
+                reason: Some("Droits insuffisants".to_string()),
             }))
         }
     } else {
         Ok(warp::reply::json(&KickBandResponse {
             kicked: false,
-            reason: Some("Droits insuffisants".to_string()),
+            reason: Some("Mauvais mot de passe".to_string()),
         }))
     }
 }
@@ -259,6 +525,9 @@ async fn user_kick_band(
 struct AuthenticateRequest {
     email: String,
     pwd: String,
+    /// Also lists archived bands in the token claims.
+    #[serde(rename = "includeArchived", default)]
+    include_archived: bool,
 }
 
 #[derive(Serialize)]
@@ -280,7 +549,10 @@ async fn user_authenticate(pool: Pool, body: AuthenticateRequest) -> Result<impl
             .await
             .map_err(db_error_to_warp)?
             .unwrap();
-        let bands = user.get_bands(id).await.map_err(db_error_to_warp)?;
+        let bands = user
+            .get_bands(id, body.include_archived)
+            .await
+            .map_err(db_error_to_warp)?;
         Ok(warp::reply::json(&AuthenticateResponse {
             status: true,
             jwt: Some(create_jwt(id, bands).map_err(|_| Error::Internal)?),
@@ -293,12 +565,22 @@ async fn user_authenticate(pool: Pool, body: AuthenticateRequest) -> Result<impl
     }
 }
 
-async fn user_get_bands(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+#[derive(Deserialize)]
+struct BandsQuery {
+    #[serde(default)]
+    archived: bool,
+}
+
+async fn user_get_bands(
+    pool: Pool,
+    claims: Claims,
+    query: BandsQuery,
+) -> Result<impl Reply, Rejection> {
     let user = User::new(pool);
 
     Ok(warp::reply::json(
         &user
-            .get_bands(claims.id_user)
+            .get_bands(claims.id_user, query.archived)
             .await
             .map_err(db_error_to_warp)?,
     ))
@@ -319,12 +601,256 @@ async fn user_exists(email: String, pool: Pool, _claims: Claims) -> Result<impl
     }))
 }
 
+#[derive(Deserialize)]
+struct ApiTokenCreateRequest {
+    name: String,
+    scopes: Vec<String>,
+    #[serde(rename = "idBand")]
+    id_band: Option<i32>,
+    #[serde(rename = "expiryStamp")]
+    expiry_stamp: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct ApiTokenCreateResponse {
+    token: String,
+    #[serde(rename = "apiToken")]
+    api_token: ApiTokenInterface,
+}
+
+async fn user_create_api_token(
+    pool: Pool,
+    claims: Claims,
+    body: ApiTokenCreateRequest,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let scopes = body
+        .scopes
+        .into_iter()
+        .map(Scope::try_from)
+        .collect::<Result<Vec<Scope>, String>>()
+        .map_err(|e| Error::Validation(vec![e]))?;
+    if let Some(id_band) = body.id_band {
+        if !is_user_in_band(pool.clone(), claims.clone(), id_band).await? {
+            return Err(warp::reject::custom(Error::Unauthorized));
+        }
+    }
+
+    let api_token = ApiToken::new(pool);
+    let (info, token) = api_token
+        .create(
+            claims.id_user,
+            body.name,
+            scopes,
+            body.id_band,
+            body.expiry_stamp,
+        )
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&ApiTokenCreateResponse {
+        token,
+        api_token: info,
+    }))
+}
+
+async fn user_list_api_tokens(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let api_token = ApiToken::new(pool);
+    Ok(warp::reply::json(
+        &api_token
+            .list(claims.id_user)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn user_revoke_api_token(
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let api_token = ApiToken::new(pool);
+    if api_token
+        .revoke(claims.id_user, id)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        Ok(warp::reply())
+    } else {
+        Err(warp::reject::custom(Error::NotFound))
+    }
+}
+
+#[derive(Serialize)]
+struct OidcUrlResponse {
+    url: String,
+}
+
+async fn oidc_authorization_url(
+    pool: Pool,
+    oidc: Oidc,
+    id_user: Option<i32>,
+) -> Result<OidcUrlResponse, Rejection> {
+    let identity = Identity::new(pool);
+    let pending = identity
+        .start_login(id_user)
+        .await
+        .map_err(db_error_to_warp)?;
+    let url = OidcClient::new(oidc)
+        .authorization_url(&pending.state, &pending.nonce, &pending.code_challenge)
+        .await
+        .map_err(etointlog)?;
+    Ok(OidcUrlResponse { url })
+}
+
+async fn user_oidc_login(pool: Pool, oidc: Oidc) -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(
+        &oidc_authorization_url(pool, oidc, None).await?,
+    ))
+}
+
+async fn user_oidc_link(pool: Pool, claims: Claims, oidc: Oidc) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    Ok(warp::reply::json(
+        &oidc_authorization_url(pool, oidc, Some(claims.id_user)).await?,
+    ))
+}
+
+/// Maps the verified email of an identity to an account, creating it if
+/// needed. Unverified local accounts are never linked automatically, as
+/// anybody could have registered them with somebody else's address.
+async fn oidc_user_from_email(user: &User, id_claims: &IdTokenClaims) -> Result<i32, Rejection> {
+    let email = match (&id_claims.email, id_claims.email_verified) {
+        (Some(email), Some(true)) => email.clone(),
+        _ => return Err(warp::reject::custom(Error::Auth)),
+    };
+
+    if let Some(id) = user
+        .get_id_from_email(email.clone())
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        if user.read(id).await.map_err(db_error_to_warp)?.verified {
+            Ok(id)
+        } else {
+            Err(warp::reject::custom(Error::Conflict))
+        }
+    } else {
+        let pseudo = id_claims
+            .preferred_username
+            .clone()
+            .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+        user.create_external(
+            pseudo,
+            email,
+            id_claims.family_name.clone().unwrap_or_default(),
+            id_claims.given_name.clone().unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| warp::reject::custom(db_error_to_warp(e)))
+    }
+}
+
+#[derive(Deserialize)]
+struct OidcCallbackQuery {
+    code: String,
+    state: String,
+}
+
+async fn user_oidc_callback(
+    pool: Pool,
+    oidc: Oidc,
+    query: OidcCallbackQuery,
+) -> Result<impl Reply, Rejection> {
+    let identity = Identity::new(pool.clone());
+    let user = User::new(pool);
+    let pending = identity
+        .take_login(query.state)
+        .await
+        .map_err(db_error_to_warp)?
+        .ok_or(Error::Auth)?;
+    let id_claims = OidcClient::new(oidc)
+        .exchange_code(&query.code, &pending.code_verifier, &pending.nonce)
+        .await
+        .map_err(|e| {
+            eprintln!("OIDC problem {}", e);
+            Error::Auth
+        })?;
+
+    let id_user = match identity
+        .find_user(id_claims.iss.clone(), id_claims.sub.clone())
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        Some(id) if pending.id_user.is_none() || pending.id_user == Some(id) => id,
+        Some(_) => return Err(warp::reject::custom(Error::Conflict)),
+        None => {
+            let id = match pending.id_user {
+                Some(id) => id,
+                None => oidc_user_from_email(&user, &id_claims).await?,
+            };
+            identity
+                .link(
+                    id,
+                    id_claims.iss.clone(),
+                    id_claims.sub.clone(),
+                    id_claims.email.clone(),
+                )
+                .await
+                .map_err(db_error_to_warp)?;
+            id
+        }
+    };
+
+    user.touch_login(id_user).await.map_err(db_error_to_warp)?;
+    let bands = user
+        .get_bands(id_user, false)
+        .await
+        .map_err(db_error_to_warp)?;
+    Ok(warp::reply::json(&AuthenticateResponse {
+        status: true,
+        jwt: Some(create_jwt(id_user, bands).map_err(|_| Error::Internal)?),
+    }))
+}
+
+async fn user_list_identities(pool: Pool, claims: Claims) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let identity = Identity::new(pool);
+    Ok(warp::reply::json(
+        &identity
+            .list(claims.id_user)
+            .await
+            .map_err(db_error_to_warp)?,
+    ))
+}
+
+async fn user_unlink_identity(
+    id: i32,
+    pool: Pool,
+    claims: Claims,
+) -> Result<impl Reply, Rejection> {
+    require_session(&claims)?;
+    let identity = Identity::new(pool);
+    if identity
+        .unlink(claims.id_user, id)
+        .await
+        .map_err(db_error_to_warp)?
+    {
+        Ok(warp::reply())
+    } else {
+        Err(warp::reject::custom(Error::NotFound))
+    }
+}
+
 pub fn user_routes(
     config: Config,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let register = warp::path!("register")
         .and(warp::post())
         .and(config.with_pool())
+        .and(config.with_password_policy())
+        .and(config.with_tokens())
         .and(warp::body::json())
         .and_then(user_create);
 
@@ -335,26 +861,40 @@ pub fn user_routes(
     let update = warp::path!("update")
         .and(warp::put())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
+        .and(config.with_password_policy())
         .and(warp::body::json())
         .and_then(user_update);
 
+    let change_email = warp::path!("email")
+        .and(warp::put())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_tokens())
+        .and(warp::body::json())
+        .and_then(user_change_email);
+
+    let confirm_email = warp::path!("confirmemail" / i32 / String)
+        .and(config.with_pool())
+        .and_then(user_confirm_email);
+
     let read = warp::path!("read" / i32)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(user_read);
 
     let add_band = warp::path!("addband")
         .and(warp::patch())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
+        .and(config.with_tokens())
         .and(warp::body::json())
         .and_then(user_add_band);
 
     let exit_band = warp::path!("exitband")
         .and(warp::patch())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(user_exit_band);
 
@@ -366,36 +906,106 @@ pub fn user_routes(
 
     let bands = warp::path!("bands")
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
+        .and(warp::query::<BandsQuery>())
         .and_then(user_get_bands);
 
     let exists = warp::path!("exists" / String)
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and_then(user_exists);
 
     let kick = warp::path!("kick")
         .and(warp::patch())
         .and(config.with_pool())
-        .and(with_jwt())
+        .and(with_jwt(&config))
         .and(warp::body::json())
         .and_then(user_kick_band);
 
     let forgot_password_request = warp::path!("forgotrequest")
         .and(warp::post())
         .and(config.with_pool())
+        .and(config.with_tokens())
         .and(warp::body::json())
         .and_then(user_forgot_password_request);
 
     let forgot_password_verify = warp::path!("forgotverify")
         .and(warp::post())
         .and(config.with_pool())
+        .and(config.with_password_policy())
         .and(warp::body::json())
         .and_then(user_forgot_password_verify);
 
+    let create_api_token = warp::path!("tokens")
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(warp::body::json())
+        .and_then(user_create_api_token);
+
+    let list_api_tokens = warp::path!("tokens")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(user_list_api_tokens);
+
+    let revoke_api_token = warp::path!("tokens" / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(user_revoke_api_token);
+
+    let oidc_login = warp::path!("oidc" / "login")
+        .and(config.with_pool())
+        .and(config.with_oidc())
+        .and_then(user_oidc_login);
+
+    let oidc_link = warp::path!("oidc" / "link")
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and(config.with_oidc())
+        .and_then(user_oidc_link);
+
+    let oidc_callback = warp::path!("oidc" / "callback")
+        .and(config.with_pool())
+        .and(config.with_oidc())
+        .and(warp::query::<OidcCallbackQuery>())
+        .and_then(user_oidc_callback);
+
+    let list_identities = warp::path!("identities")
+        .and(warp::get())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(user_list_identities);
+
+    let unlink_identity = warp::path!("identities" / i32)
+        .and(warp::delete())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(user_unlink_identity);
+
+    let read_invitation = warp::path!("invitation" / i32 / String)
+        .and(warp::get())
+        .and(config.with_pool())
+        .and_then(user_read_invitation);
+
+    let accept_invitation = warp::path!("invitation" / i32 / String / "accept")
+        .and(warp::post())
+        .and(config.with_pool())
+        .and(with_jwt(&config))
+        .and_then(user_accept_invitation);
+
+    let decline_invitation = warp::path!("invitation" / i32 / String / "decline")
+        .and(warp::post())
+        .and(config.with_pool())
+        .and_then(user_decline_invitation);
+
     register
         .or(verify)
         .or(update)
+        .or(change_email)
+        .or(confirm_email)
         .or(read)
         .or(add_band)
         .or(exit_band)
@@ -405,4 +1015,15 @@ pub fn user_routes(
         .or(kick)
         .or(forgot_password_request)
         .or(forgot_password_verify)
+        .or(create_api_token)
+        .or(list_api_tokens)
+        .or(revoke_api_token)
+        .or(oidc_login)
+        .or(oidc_link)
+        .or(oidc_callback)
+        .or(list_identities)
+        .or(unlink_identity)
+        .or(read_invitation)
+        .or(accept_invitation)
+        .or(decline_invitation)
 }
@@ -0,0 +1,178 @@
+use std::{path::PathBuf, process::Stdio};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{stream::BoxStream, StreamExt};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::Command,
+};
+
+use crate::config::{Storage, StorageBackend};
+
+/// Size of the chunks read from the disk when streaming a file.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// Where file contents live, addressed by the random hexadecimal keys
+/// generated when their description is stored in the database. Other
+/// backends, like S3-compatible object stores, implement this trait and
+/// are picked in `open`.
+#[async_trait]
+pub trait FileStorage: Send + Sync {
+    async fn save(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    async fn read(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Content of the file, read as it is sent.
+    async fn stream(&self, key: &str) -> Result<ByteStream>;
+
+    /// Removing a file that is already gone is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// The storage backend chosen in the configuration.
+pub fn open(config: &Storage) -> Box<dyn FileStorage> {
+    match config.backend() {
+        StorageBackend::Local => Box::new(LocalStorage::new(config)),
+    }
+}
+
+/// Files kept on the local disk.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(config: &Storage) -> Self {
+        LocalStorage {
+            root: PathBuf::from(config.path()),
+        }
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            Err(anyhow!("Invalid storage key {}", key))
+        } else {
+            Ok(self.root.join(key))
+        }
+    }
+}
+
+#[async_trait]
+impl FileStorage for LocalStorage {
+    async fn save(&self, key: &str, data: &[u8]) -> Result<()> {
+        tokio::fs::create_dir_all(&self.root).await?;
+        tokio::fs::write(self.path_for(key)?, data).await?;
+        Ok(())
+    }
+
+    async fn read(&self, key: &str) -> Result<Vec<u8>> {
+        Ok(tokio::fs::read(self.path_for(key)?).await?)
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream> {
+        let file = File::open(self.path_for(key)?).await?;
+        Ok(futures_util::stream::unfold(Some(file), |file| async move {
+            let mut file = file?;
+            let mut buf = vec![0; CHUNK_SIZE];
+            match file.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(Bytes::from(buf)), Some(file)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+        .boxed())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path_for(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Runs the configured virus scanner on the data. Files are considered
+/// clean when no scanner is configured; a scanner failing for another
+/// reason than a detection is an error.
+pub async fn is_infected(config: &Storage, data: &[u8]) -> Result<bool> {
+    let command = match config.scan_command() {
+        Some(command) if !command.is_empty() => command,
+        _ => return Ok(false),
+    };
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data).await?;
+    }
+    match child.wait().await?.code() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        code => Err(anyhow!("Virus scanner {} failed {:?}", command[0], code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(root: PathBuf) -> LocalStorage {
+        LocalStorage { root }
+    }
+
+    #[test]
+    fn keys_stay_inside_the_root() {
+        let storage = storage(PathBuf::from("/srv/files"));
+        assert_eq!(
+            storage.path_for("0a1B2c").unwrap(),
+            PathBuf::from("/srv/files/0a1B2c")
+        );
+        for key in [
+            "",
+            "../etc/passwd",
+            "/etc/passwd",
+            "ab/cd",
+            "ab.cd",
+            "abc\0",
+            "ab cd",
+        ] {
+            assert!(storage.path_for(key).is_err(), "{:?} accepted", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn files_are_saved_streamed_and_deleted() {
+        let root = std::env::temp_dir().join(format!("cnm-storage-{}", std::process::id()));
+        let storage = storage(root.clone());
+        let data = vec![7u8; CHUNK_SIZE + 10];
+        storage.save("beef", &data).await.unwrap();
+        assert_eq!(storage.read("beef").await.unwrap(), data);
+
+        let chunks: Vec<Bytes> = storage
+            .stream("beef")
+            .await
+            .unwrap()
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+        assert!(chunks.len() > 1 && chunks.iter().all(|c| c.len() <= CHUNK_SIZE));
+        assert_eq!(chunks.concat(), data);
+
+        storage.delete("beef").await.unwrap();
+        storage.delete("beef").await.unwrap();
+        assert!(storage.read("beef").await.is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,562 @@
+//! Minimal vCard 3.0 and 4.0 support, limited to the fields stored for a
+//! band contact.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    contact::{ContactDetails, ContactEmail, ContactPhone, LineKind},
+    org::{ContactInterface, ContactShort},
+};
+
+const MAX_LINE_OCTETS: usize = 75;
+const MIN_SIMILARITY: f64 = 0.8;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Version {
+    #[serde(rename = "3.0")]
+    V3,
+    #[serde(rename = "4.0")]
+    #[default]
+    V4,
+}
+
+/// A card read from an imported file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Card {
+    pub name: String,
+    #[serde(rename = "firstName")]
+    pub first_name: Option<String>,
+    pub org: Option<String>,
+    #[serde(rename = "jobTitle")]
+    pub job_title: Option<String>,
+    pub language: Option<String>,
+    pub emails: Vec<ContactEmail>,
+    pub phones: Vec<ContactPhone>,
+    pub address: Option<String>,
+    #[serde(rename = "zipCode")]
+    pub zip_code: Option<String>,
+    pub city: Option<String>,
+}
+
+impl From<Card> for ContactShort {
+    fn from(card: Card) -> Self {
+        ContactShort {
+            name: card.name,
+            first_name: card.first_name,
+            email: None,
+            phone: None,
+            address: card.address,
+            zip_code: card.zip_code,
+            city: card.city,
+            details: ContactDetails {
+                job_title: card.job_title,
+                language: card.language,
+                emails: card.emails,
+                phones: card.phones,
+                data_source: Some("vCard import".to_string()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => res.push('\n'),
+                Some(other) => res.push(other),
+                None => {}
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Splits a structured value on unescaped `;`.
+fn components(value: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ';' {
+            res.push(unescape(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    res.push(unescape(&current));
+    res
+}
+
+/// Folds a content line so that no line exceeds 75 octets.
+fn fold(line: String) -> String {
+    if line.len() <= MAX_LINE_OCTETS {
+        return line;
+    }
+    let mut res = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            res.push_str("\r\n ");
+            width = 1;
+        }
+        res.push(c);
+        width += c.len_utf8();
+    }
+    res
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Serializes a contact of the given org.
+pub fn to_vcard(contact: &ContactInterface, org_name: &str, version: Version) -> String {
+    let first_name = non_empty(&contact.first_name).unwrap_or_default();
+    let full_name = if first_name.is_empty() {
+        contact.name.clone()
+    } else {
+        format!("{} {}", first_name, contact.name)
+    };
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        format!(
+            "VERSION:{}",
+            match version {
+                Version::V3 => "3.0",
+                Version::V4 => "4.0",
+            }
+        ),
+        format!("N:{};{};;;", escape(&contact.name), escape(first_name)),
+        format!("FN:{}", escape(&full_name)),
+        format!("ORG:{}", escape(org_name)),
+    ];
+    if let Some(title) = non_empty(&contact.details.job_title) {
+        lines.push(format!("TITLE:{}", escape(title)));
+    }
+    if let (Version::V4, Some(language)) = (version, non_empty(&contact.details.language)) {
+        lines.push(format!("LANG:{}", escape(language)));
+    }
+    let emails = match (contact.details.emails.is_empty(), non_empty(&contact.email)) {
+        (true, Some(email)) => vec![ContactEmail {
+            kind: LineKind::Work,
+            value: email.to_string(),
+        }],
+        _ => contact.details.emails.clone(),
+    };
+    for email in emails {
+        let kind = match email.kind {
+            LineKind::Work => "work",
+            LineKind::Home => "home",
+            LineKind::Mobile | LineKind::Other => "other",
+        };
+        lines.push(match version {
+            Version::V3 => format!(
+                "EMAIL;TYPE=INTERNET,{}:{}",
+                kind.to_ascii_uppercase(),
+                escape(&email.value)
+            ),
+            Version::V4 => format!("EMAIL;TYPE={}:{}", kind, escape(&email.value)),
+        });
+    }
+    let phones = match (contact.details.phones.is_empty(), non_empty(&contact.phone)) {
+        (true, Some(phone)) => vec![ContactPhone {
+            kind: LineKind::Work,
+            value: phone.to_string(),
+        }],
+        _ => contact.details.phones.clone(),
+    };
+    for phone in phones {
+        let kind = match phone.kind {
+            LineKind::Work => "work,voice",
+            LineKind::Home => "home,voice",
+            LineKind::Mobile => "cell",
+            LineKind::Other => "voice",
+        };
+        lines.push(match version {
+            Version::V3 => format!(
+                "TEL;TYPE={}:{}",
+                kind.to_ascii_uppercase(),
+                escape(&phone.value)
+            ),
+            Version::V4 => format!("TEL;TYPE={}:{}", kind, escape(&phone.value)),
+        });
+    }
+    if non_empty(&contact.address).is_some()
+        || non_empty(&contact.zip_code).is_some()
+        || non_empty(&contact.city).is_some()
+    {
+        lines.push(format!(
+            "ADR;TYPE={}:;;{};{};;{};",
+            match version {
+                Version::V3 => "WORK",
+                Version::V4 => "work",
+            },
+            escape(non_empty(&contact.address).unwrap_or_default()),
+            escape(non_empty(&contact.city).unwrap_or_default()),
+            escape(non_empty(&contact.zip_code).unwrap_or_default()),
+        ));
+    }
+    lines.push("END:VCARD".to_string());
+    lines
+        .into_iter()
+        .map(fold)
+        .collect::<Vec<String>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+/// Joins folded lines back, continuation lines start with a space or a tab.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for raw in text.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (
+            raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if raw.is_empty() => {}
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// Kind of an email or a phone, read from the parameters of its line.
+fn line_kind(head: &str) -> LineKind {
+    let params = head.to_ascii_lowercase();
+    if params.contains("cell") {
+        LineKind::Mobile
+    } else if params.contains("work") {
+        LineKind::Work
+    } else if params.contains("home") {
+        LineKind::Home
+    } else {
+        LineKind::Other
+    }
+}
+
+/// Reads every card of a file, cards without any name are skipped.
+pub fn parse(text: &str) -> Vec<Card> {
+    let mut cards = vec![];
+    let mut current: Option<Card> = None;
+    let mut formatted_name = String::new();
+    for line in unfold(text) {
+        let (head, value) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let name = head
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", _) if value.eq_ignore_ascii_case("VCARD") => {
+                current = Some(Card::default());
+                formatted_name.clear();
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VCARD") => {
+                if let Some(mut card) = current.take() {
+                    if card.name.trim().is_empty() {
+                        card.name = formatted_name.trim().to_string();
+                    }
+                    if !card.name.is_empty() {
+                        cards.push(card);
+                    }
+                }
+            }
+            ("N", Some(card)) => {
+                let parts = components(value);
+                card.name = parts
+                    .first()
+                    .cloned()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                card.first_name = parts
+                    .get(1)
+                    .map(|f| f.trim().to_string())
+                    .filter(|f| !f.is_empty());
+            }
+            ("FN", Some(_)) => formatted_name = unescape(value),
+            ("ORG", Some(card)) => {
+                card.org = components(value)
+                    .first()
+                    .map(|o| o.trim().to_string())
+                    .filter(|o| !o.is_empty());
+            }
+            ("EMAIL", Some(card)) => card.emails.push(ContactEmail {
+                kind: line_kind(head),
+                value: unescape(value).trim().to_string(),
+            }),
+            ("TEL", Some(card)) => {
+                let phone = unescape(value);
+                card.phones.push(ContactPhone {
+                    kind: line_kind(head),
+                    value: phone.trim().trim_start_matches("tel:").to_string(),
+                });
+            }
+            ("TITLE", Some(card)) => {
+                card.job_title = Some(unescape(value).trim().to_string()).filter(|t| !t.is_empty());
+            }
+            ("LANG", Some(card)) => {
+                card.language = Some(unescape(value).trim().to_string()).filter(|l| !l.is_empty());
+            }
+            ("ADR", Some(card)) if card.city.is_none() && card.address.is_none() => {
+                let parts = components(value);
+                let part = |i: usize| {
+                    parts
+                        .get(i)
+                        .map(|p| p.trim().to_string())
+                        .filter(|p| !p.is_empty())
+                };
+                card.address = part(2);
+                card.city = part(3);
+                card.zip_code = part(5);
+            }
+            _ => {}
+        }
+    }
+    cards
+}
+
+/// Lower case ASCII form of a name, used to compare org names.
+pub fn normalize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ä' | 'À' | 'Á' | 'Â' | 'Ä' => 'a',
+            'ç' | 'Ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' | 'È' | 'É' | 'Ê' | 'Ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' | 'Ì' | 'Í' | 'Î' | 'Ï' => 'i',
+            'ò' | 'ó' | 'ô' | 'ö' | 'Ò' | 'Ó' | 'Ô' | 'Ö' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' | 'Ù' | 'Ú' | 'Û' | 'Ü' => 'u',
+            c if c.is_alphanumeric() => c.to_ascii_lowercase(),
+            _ => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Similarity between two normalized names, from 0 to 1.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(ca != cb))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrgMatch {
+    Found(i32),
+    Ambiguous(Vec<i32>),
+    NotFound,
+}
+
+/// Org names indexed by word, so that a card only gets compared with the
+/// orgs sharing a word with its own org name.
+pub struct OrgIndex {
+    orgs: Vec<(i32, String)>,
+    words: HashMap<String, Vec<usize>>,
+}
+
+impl OrgIndex {
+    pub fn new(orgs: Vec<(i32, String)>) -> Self {
+        let orgs: Vec<(i32, String)> = orgs
+            .into_iter()
+            .map(|(id, name)| (id, normalize(&name)))
+            .collect();
+        let mut words: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, (_, name)) in orgs.iter().enumerate() {
+            for word in name.split(' ').filter(|w| w.len() >= 3) {
+                words.entry(word.to_string()).or_default().push(i);
+            }
+        }
+        OrgIndex { orgs, words }
+    }
+
+    pub fn find(&self, name: &str) -> OrgMatch {
+        let name = normalize(name);
+        let candidates: HashSet<usize> = name
+            .split(' ')
+            .filter_map(|w| self.words.get(w))
+            .flatten()
+            .copied()
+            .collect();
+        let exact: Vec<i32> = candidates
+            .iter()
+            .filter(|i| self.orgs[**i].1 == name)
+            .map(|i| self.orgs[*i].0)
+            .collect();
+        match exact.len() {
+            1 => return OrgMatch::Found(exact[0]),
+            0 => {}
+            _ => return OrgMatch::Ambiguous(exact),
+        }
+        let mut scored: Vec<(f64, i32)> = candidates
+            .iter()
+            .map(|i| (similarity(&self.orgs[*i].1, &name), self.orgs[*i].0))
+            .filter(|(score, _)| *score >= MIN_SIMILARITY)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        match scored.as_slice() {
+            [] => OrgMatch::NotFound,
+            [(_, id)] => OrgMatch::Found(*id),
+            [(best, id), (second, _), ..] if best - second > 0.05 => OrgMatch::Found(*id),
+            _ => OrgMatch::Ambiguous(scored.iter().map(|(_, id)| *id).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn contact() -> ContactInterface {
+        ContactInterface {
+            id: 1,
+            name: "Doe; Jr".to_string(),
+            first_name: Some("Jane".to_string()),
+            email: Some("jane@example.org".to_string()),
+            phone: None,
+            address: Some(format!(
+                "12 rue de la République\nBâtiment {}",
+                "B".repeat(60)
+            )),
+            zip_code: Some("69001".to_string()),
+            city: Some("Lyon".to_string()),
+            details: ContactDetails {
+                job_title: Some("Booker, programmation".to_string()),
+                language: Some("fr".to_string()),
+                phones: vec![ContactPhone {
+                    kind: LineKind::Mobile,
+                    value: "+33 6 12 34 56 78".to_string(),
+                }],
+                ..Default::default()
+            },
+            creation_stamp: NaiveDateTime::default(),
+            stale_since: None,
+        }
+    }
+
+    #[test]
+    fn exported_cards_read_back() {
+        for version in [Version::V3, Version::V4] {
+            let text = to_vcard(&contact(), "Le Café \\ Bar", version);
+            assert!(text.split("\r\n").all(|l| l.len() <= MAX_LINE_OCTETS));
+
+            let cards = parse(&text);
+            assert_eq!(cards.len(), 1);
+            let card = &cards[0];
+            let expected = contact();
+            assert_eq!(card.name, expected.name);
+            assert_eq!(card.first_name, expected.first_name);
+            assert_eq!(card.org.as_deref(), Some("Le Café \\ Bar"));
+            assert_eq!(card.job_title, expected.details.job_title);
+            assert_eq!(
+                card.language.as_deref(),
+                (version == Version::V4).then_some("fr")
+            );
+            assert_eq!(
+                card.emails,
+                vec![ContactEmail {
+                    kind: LineKind::Work,
+                    value: "jane@example.org".to_string(),
+                }]
+            );
+            assert_eq!(card.phones, expected.details.phones);
+            assert_eq!(card.address, expected.address);
+            assert_eq!(card.zip_code, expected.zip_code);
+            assert_eq!(card.city, expected.city);
+        }
+    }
+
+    #[test]
+    fn cards_from_other_software_are_read() {
+        let text = "BEGIN:VCARD\n\
+                    VERSION:3.0\n\
+                    FN:Jean Dupont\n\
+                    item1.TEL;type=CELL:tel:+33612345678\n\
+                    ADR;TYPE=HOME:;;1 place Bellecour;Lyon;;69002;France\n\
+                    ADR;TYPE=WORK:;;ignored;Paris;;75001;\n\
+                    NOTE:folded\n  across lines\n\
+                    END:VCARD\n\
+                    BEGIN:VCARD\n\
+                    VERSION:4.0\n\
+                    EMAIL:nobody@example.org\n\
+                    END:VCARD\n";
+        let cards = parse(text);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].name, "Jean Dupont");
+        assert_eq!(cards[0].first_name, None);
+        assert_eq!(
+            cards[0].phones,
+            vec![ContactPhone {
+                kind: LineKind::Mobile,
+                value: "+33612345678".to_string(),
+            }]
+        );
+        assert_eq!(cards[0].city.as_deref(), Some("Lyon"));
+        assert_eq!(cards[0].zip_code.as_deref(), Some("69002"));
+    }
+
+    #[test]
+    fn orgs_are_matched_by_name() {
+        let index = OrgIndex::new(vec![
+            (1, "Le Café des Arts".to_string()),
+            (2, "La Cigale".to_string()),
+            (3, "Salle des fêtes".to_string()),
+            (4, "Salle des fetes".to_string()),
+        ]);
+        assert_eq!(index.find("le cafe des arts"), OrgMatch::Found(1));
+        assert_eq!(index.find("Le Cafe des Artz"), OrgMatch::Found(1));
+        assert!(matches!(
+            index.find("Salle des Fêtes"),
+            OrgMatch::Ambiguous(_)
+        ));
+        assert_eq!(index.find("Olympia"), OrgMatch::NotFound);
+    }
+}
@@ -0,0 +1,187 @@
+//! Checks that changes made through the models land in the band feed. Like
+//! `org_listing`, they need a database and are ignored by default:
+//!
+//! `CNM_CONFIG=/path/to/test.json cargo test --test feed -- --ignored`
+
+use chrono::Utc;
+use cnm::{
+    config::Config,
+    models::{
+        event::{Event, EventKind, FeedFilter},
+        note::Note,
+        org::{ContactShort, Org, Status},
+        privacy::Privacy,
+        user::User,
+    },
+};
+use deadpool_postgres::Pool;
+
+fn pool() -> Pool {
+    Config::retrieve(true)
+        .expect("CNM_CONFIG must point to a test configuration")
+        .pool()
+        .unwrap()
+}
+
+/// A user owning a band, and an org.
+async fn fixture(pool: &Pool) -> (i32, i32, i32) {
+    let client = pool.get().await.unwrap();
+    let suffix = Utc::now().timestamp_nanos();
+    let id_user: i32 = client
+        .query_one(
+            "
+            INSERT INTO cnm_user(pseudo, name, firstname, email, pwd)
+            VALUES ($1, 'Test', 'Test', $2, 'x')
+            RETURNING id
+            ",
+            &[
+                &format!("feed{}", suffix),
+                &format!("feed{}@example.org", suffix),
+            ],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_band: i32 = client
+        .query_one(
+            "INSERT INTO band(name, id_creator) VALUES ('Feed', $1) RETURNING id",
+            &[&id_user],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_org: i32 = client
+        .query_one(
+            "INSERT INTO org(name) VALUES ($1) RETURNING id",
+            &[&format!("Feed {}", suffix)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    (id_user, id_band, id_org)
+}
+
+async fn kinds(pool: &Pool, id_band: i32) -> Vec<EventKind> {
+    let (events, _) = Event::new(pool.clone())
+        .feed(id_band, FeedFilter::default(), None)
+        .await
+        .unwrap();
+    events.into_iter().rev().map(|e| e.kind).collect()
+}
+
+fn contact(email: &str) -> ContactShort {
+    serde_json::from_value(serde_json::json!({
+        "name": "Doe",
+        "firstName": "Jane",
+        "email": email,
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+#[ignore]
+async fn changes_are_recorded() {
+    let pool = pool();
+    let (id_user, id_band, id_org) = fixture(&pool).await;
+    let org = Org::new(pool.clone());
+    org.tag_orgs(
+        id_user,
+        id_user,
+        id_band,
+        vec![id_org],
+        Status::from("raise".to_string()),
+    )
+    .await
+    .unwrap();
+    let mut added = org
+        .add_contact(id_user, id_org, id_band, contact("jane@example.org"))
+        .await
+        .unwrap();
+    added.city = Some("Lyon".to_string());
+    org.update_contact(id_user, added.clone()).await.unwrap();
+    org.remove_contact(id_user, added.id).await.unwrap();
+    let user = User::new(pool.clone());
+    let id_role: i32 = pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "SELECT id FROM band_role WHERE id_band IS NULL AND name = 'booker'",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    user.add_band(id_user, id_band, id_role).await.unwrap();
+    user.exit_band(id_user, id_user, id_band).await.unwrap();
+
+    assert_eq!(
+        kinds(&pool, id_band).await,
+        vec![
+            EventKind::OrgTagged,
+            EventKind::ContactAdded,
+            EventKind::ContactEdited,
+            EventKind::ContactRemoved,
+            EventKind::MemberJoined,
+            EventKind::MemberLeft,
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore]
+async fn erasure_is_recorded() {
+    let pool = pool();
+    let (id_user, id_band, id_org) = fixture(&pool).await;
+    let email = format!("erased{}@example.org", Utc::now().timestamp_nanos());
+    Org::new(pool.clone())
+        .add_contact(id_user, id_org, id_band, contact(&email))
+        .await
+        .unwrap();
+    Privacy::new(pool.clone()).erase(&email).await.unwrap();
+
+    let (events, _) = Event::new(pool.clone())
+        .feed(id_band, FeedFilter::default(), None)
+        .await
+        .unwrap();
+    assert_eq!(events[0].kind, EventKind::ContactRemoved);
+    assert_eq!(events[0].actor_id, None);
+    assert!(!events
+        .iter()
+        .any(|e| e.details.to_string().contains(&email)));
+}
+
+#[tokio::test]
+#[ignore]
+async fn note_changes_keep_revisions() {
+    let pool = pool();
+    let (id_user, id_band, id_org) = fixture(&pool).await;
+    let id_activity: i32 = pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "INSERT INTO activity(id_org, name) VALUES ($1, 'Stage') RETURNING id",
+            &[&id_org],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let note = Note::new(pool.clone());
+    let created = note
+        .create(id_user, id_band, id_activity, None, "First".to_string())
+        .await
+        .unwrap();
+    assert_eq!(created.revision_count, 1);
+    let edited = note
+        .edit(created.id, id_band, id_user, "Second".to_string())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edited.revision_count, 2);
+
+    assert_eq!(
+        kinds(&pool, id_band).await,
+        vec![EventKind::NoteCreated, EventKind::NoteEdited]
+    );
+}
@@ -0,0 +1,150 @@
+//! Merges of duplicate contacts. They need a database and are ignored by
+//! default:
+//!
+//! `CNM_CONFIG=/path/to/test.json cargo test --test merge -- --ignored`
+
+use chrono::Utc;
+use cnm::{
+    config::Config,
+    models::{
+        contact::Contact,
+        org::{ContactInterface, ContactShort, Org},
+        shared_contact::{SharedContact, SharedStatus},
+    },
+};
+use deadpool_postgres::Pool;
+
+fn pool() -> Pool {
+    Config::retrieve(true)
+        .expect("CNM_CONFIG must point to a test configuration")
+        .pool()
+        .unwrap()
+}
+
+/// A user, a band and two contacts of an org: the target and a source.
+async fn fixture(pool: &Pool) -> (i32, i32, ContactInterface, ContactInterface) {
+    let client = pool.get().await.unwrap();
+    let suffix = Utc::now().timestamp_nanos();
+    let id_user: i32 = client
+        .query_one(
+            "
+            INSERT INTO cnm_user(pseudo, name, firstname, email, pwd)
+            VALUES ($1, 'Test', 'Test', $2, 'x')
+            RETURNING id
+            ",
+            &[
+                &format!("merge{}", suffix),
+                &format!("merge{}@example.org", suffix),
+            ],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_band: i32 = client
+        .query_one(
+            "INSERT INTO band(name, id_creator) VALUES ('Merge', $1) RETURNING id",
+            &[&id_user],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_org: i32 = client
+        .query_one(
+            "INSERT INTO org(name) VALUES ($1) RETURNING id",
+            &[&format!("Merge {}", suffix)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let org = Org::new(pool.clone());
+    let contact = |email: &str| -> ContactShort {
+        serde_json::from_value(serde_json::json!({
+            "name": "Doe",
+            "firstName": "Jane",
+            "email": email,
+        }))
+        .unwrap()
+    };
+    let target = org
+        .add_contact(id_user, id_org, id_band, contact("jane@example.org"))
+        .await
+        .unwrap();
+    let source = org
+        .add_contact(id_user, id_org, id_band, contact("JANE@example.org"))
+        .await
+        .unwrap();
+    (id_user, id_band, target, source)
+}
+
+#[tokio::test]
+#[ignore]
+async fn attachments_follow_the_target() {
+    let pool = pool();
+    let (id_user, id_band, target, source) = fixture(&pool).await;
+    let client = pool.get().await.unwrap();
+    let id_attachment: i32 = client
+        .query_one(
+            "
+            INSERT INTO attachment(id_band, id_contact, filename, content_type, size, storage_key)
+            VALUES ($1, $2, 'rider.pdf', 'application/pdf', 1, $3)
+            RETURNING id
+            ",
+            &[
+                &id_band,
+                &source.id,
+                &format!("{:x}", Utc::now().timestamp_nanos()),
+            ],
+        )
+        .await
+        .unwrap()
+        .get(0);
+
+    let removed = Contact::new(pool.clone())
+        .merge(id_user, id_band, &target, &[source.id])
+        .await
+        .unwrap();
+    assert_eq!(removed, 1);
+    let id_contact: Option<i32> = client
+        .query_one(
+            "SELECT id_contact FROM attachment WHERE id = $1",
+            &[&id_attachment],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(id_contact, Some(target.id));
+}
+
+#[tokio::test]
+#[ignore]
+async fn approved_copy_follows_the_target() {
+    let pool = pool();
+    let (id_user, id_band, target, source) = fixture(&pool).await;
+    let shared = SharedContact::new(pool.clone());
+    let copy = shared
+        .share(source.id, id_band, id_user)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shared
+        .moderate(copy.id, id_user, SharedStatus::Approved, None)
+        .await
+        .unwrap());
+
+    Contact::new(pool.clone())
+        .merge(id_user, id_band, &target, &[source.id])
+        .await
+        .unwrap();
+    let id_contact: i32 = pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "SELECT id_contact FROM shared_contact WHERE id = $1",
+            &[&copy.id],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(id_contact, target.id);
+}
@@ -0,0 +1,174 @@
+//! Drives the authorization code exchange against a mock provider serving
+//! the discovery document, a token endpoint and a JWKS on a local port.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
+
+use chrono::{Duration, Utc};
+use cnm::{config::Oidc, oidc::OidcClient};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+use warp::{http::StatusCode, Filter};
+
+const PRIVATE_KEY: &str = include_str!("data/oidc_rsa.pem");
+/// Modulus of the key above, encoded as in a JWK.
+const MODULUS: &str = "m7onIzKQUFp99kKNWmKTzfcUZaZXDjj6Q1YnMnLWAU9-YTlx03uI1IBU54HWEiQU1Z5xhitDQ8Wr76_0EV-oiSV84vm7cTvR1tiPy9MSbC_Raq7GOBFLY6yeTzEWHD8JzgRbCYfMEYnz25Smk6MMURycCdAlLHA2o9BfCqpao9gP2ExVW3yfgT3fAr5fdQxhAm6zX3pq9AU852MpbGjlhICPLePQjJftaXUPtnvkXtjWgfUnQvKeGzbmp8HmyFZqjg1-U2LMOJ_OnH4srcW8ywMoxWEgNkk460M7c0f681kz6tD3vvyNRisp69NQ69IfK1rCcaQSiS2HVMFqGaPM4Q";
+const CLIENT_ID: &str = "cnm";
+const NONCE: &str = "n0nce";
+const VERIFIER: &str = "verifier";
+
+/// Claims of a valid ID token issued by `issuer`.
+fn claims(issuer: &str) -> Value {
+    json!({
+        "iss": issuer,
+        "sub": "ext-42",
+        "aud": CLIENT_ID,
+        "exp": (Utc::now() + Duration::minutes(5)).timestamp(),
+        "iat": Utc::now().timestamp(),
+        "nonce": NONCE,
+        "email": "jane@example.org",
+        "email_verified": true,
+    })
+}
+
+fn sign(claims: &Value) -> String {
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("test".to_string());
+    encode(
+        &header,
+        claims,
+        &EncodingKey::from_rsa_pem(PRIVATE_KEY.as_bytes()).unwrap(),
+    )
+    .unwrap()
+}
+
+/// Starts a provider whose token endpoint answers with the ID token built
+/// by `id_token` from the issuer, returns the client configuration.
+fn provider(id_token: fn(&str) -> String) -> Oidc {
+    let issuer = Arc::new(OnceLock::<String>::new());
+
+    let discovery = {
+        let issuer = issuer.clone();
+        warp::path!(".well-known" / "openid-configuration").map(move || {
+            let issuer = issuer.get().unwrap();
+            warp::reply::json(&json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+                "id_token_signing_alg_values_supported": ["RS256"],
+            }))
+        })
+    };
+    let token = {
+        let issuer = issuer.clone();
+        warp::path!("token")
+            .and(warp::post())
+            .and(warp::body::form())
+            .map(move |form: HashMap<String, String>| {
+                if form.get("code").map(String::as_str) != Some("code")
+                    || form.get("code_verifier").map(String::as_str) != Some(VERIFIER)
+                {
+                    return warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": "invalid_grant" })),
+                        StatusCode::BAD_REQUEST,
+                    );
+                }
+                warp::reply::with_status(
+                    warp::reply::json(&json!({
+                        "id_token": id_token(issuer.get().unwrap()),
+                        "token_type": "Bearer",
+                    })),
+                    StatusCode::OK,
+                )
+            })
+    };
+    let jwks = warp::path!("jwks").map(|| {
+        warp::reply::json(&json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test",
+                "use": "sig",
+                "alg": "RS256",
+                "n": MODULUS,
+                "e": "AQAB",
+            }]
+        }))
+    });
+
+    let (addr, server) =
+        warp::serve(discovery.or(token).or(jwks)).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    let base = format!("http://{}", addr);
+    issuer.set(base.clone()).unwrap();
+    serde_json::from_value(json!({
+        "issuer": base,
+        "clientId": CLIENT_ID,
+        "clientSecret": "secret",
+        "redirectUrl": "http://localhost/callback",
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn exchange_returns_verified_claims() {
+    let client = OidcClient::new(provider(|issuer| sign(&claims(issuer))));
+    let claims = client.exchange_code("code", VERIFIER, NONCE).await.unwrap();
+    assert_eq!(claims.sub, "ext-42");
+    assert_eq!(claims.email.as_deref(), Some("jane@example.org"));
+}
+
+#[tokio::test]
+async fn authorization_url_uses_discovery() {
+    let config = provider(|issuer| sign(&claims(issuer)));
+    let url = OidcClient::new(config.clone())
+        .authorization_url("state", NONCE, "challenge")
+        .await
+        .unwrap();
+    assert!(url.starts_with(&format!("{}/authorize?", config.issuer())));
+    assert!(url.contains("code_challenge_method=S256"));
+}
+
+#[tokio::test]
+async fn nonce_mismatch_is_rejected() {
+    let client = OidcClient::new(provider(|issuer| sign(&claims(issuer))));
+    assert!(client
+        .exchange_code("code", VERIFIER, "other")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn wrong_audience_is_rejected() {
+    let client = OidcClient::new(provider(|issuer| {
+        let mut claims = claims(issuer);
+        claims["aud"] = json!("someone-else");
+        sign(&claims)
+    }));
+    assert!(client.exchange_code("code", VERIFIER, NONCE).await.is_err());
+}
+
+#[tokio::test]
+async fn wrong_issuer_is_rejected() {
+    let client = OidcClient::new(provider(|_| sign(&claims("http://elsewhere"))));
+    assert!(client.exchange_code("code", VERIFIER, NONCE).await.is_err());
+}
+
+/// A token signed with HMAC using the public modulus as secret must not be
+/// accepted because its header says so.
+#[tokio::test]
+async fn algorithm_is_not_taken_from_the_token() {
+    let client = OidcClient::new(provider(|issuer| {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test".to_string());
+        encode(
+            &header,
+            &claims(issuer),
+            &EncodingKey::from_secret(MODULUS.as_bytes()),
+        )
+        .unwrap()
+    }));
+    assert!(client.exchange_code("code", VERIFIER, NONCE).await.is_err());
+}
@@ -0,0 +1,206 @@
+//! Runs the org listing queries against a database holding the schema of
+//! `database/cnm.postgres.sql` and every migration. They need a server, so
+//! they are ignored by default:
+//!
+//! `CNM_CONFIG=/path/to/test.json cargo test --test org_listing -- --ignored`
+
+use chrono::Utc;
+use cnm::{
+    config::Config,
+    models::{
+        filter::{Filter, FilterIntermediate, Sort},
+        interaction::ContactedFilter,
+        org::{Org, OrgCriteria},
+    },
+    paginator::Paginator,
+};
+use deadpool_postgres::Pool;
+
+struct Fixture {
+    id_user: i32,
+    id_band: i32,
+    id_org: i32,
+    id_label: i32,
+}
+
+/// A band whose member is assigned one labelled and contacted org.
+async fn fixture(pool: &Pool) -> Fixture {
+    let client = pool.get().await.unwrap();
+    let suffix = Utc::now().timestamp_nanos();
+    let id_user: i32 = client
+        .query_one(
+            "
+            INSERT INTO cnm_user(pseudo, name, firstname, email, pwd)
+            VALUES ($1, 'Test', 'Test', $2, 'x')
+            RETURNING id
+            ",
+            &[
+                &format!("listing{}", suffix),
+                &format!("listing{}@example.org", suffix),
+            ],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_band: i32 = client
+        .query_one(
+            "INSERT INTO band(name, id_creator) VALUES ('Listing', $1) RETURNING id",
+            &[&id_user],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_org: i32 = client
+        .query_one(
+            "INSERT INTO org(name, description) VALUES ($1, 'Club') RETURNING id",
+            &[&format!("O'Brien {}", suffix)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    client
+        .execute(
+            "
+            INSERT INTO activity(id_org, name, category, city, postal_code)
+            VALUES ($1, 'Stage', 'Bar', 'Lyon', '69001')
+            ",
+            &[&id_org],
+        )
+        .await
+        .unwrap();
+    client
+        .execute(
+            "INSERT INTO org_assign(id_org, id_user, id_band, status) VALUES ($1, $2, $3, 'raise')",
+            &[&id_org, &id_user, &id_band],
+        )
+        .await
+        .unwrap();
+    let id_label: i32 = client
+        .query_one(
+            "INSERT INTO band_label(id_band, name) VALUES ($1, 'Jazz') RETURNING id",
+            &[&id_band],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    client
+        .execute(
+            "INSERT INTO org_label(id_org, id_label) VALUES ($1, $2)",
+            &[&id_org, &id_label],
+        )
+        .await
+        .unwrap();
+    client
+        .execute(
+            "
+            INSERT INTO interaction(id_band, id_org, id_user, kind, direction)
+            VALUES ($1, $2, $3, 'call', 'outbound')
+            ",
+            &[&id_band, &id_org, &id_user],
+        )
+        .await
+        .unwrap();
+    Fixture {
+        id_user,
+        id_band,
+        id_org,
+        id_label,
+    }
+}
+
+fn filter(value: serde_json::Value) -> Filter {
+    Filter::try_from(serde_json::from_value::<FilterIntermediate>(value).unwrap()).unwrap()
+}
+
+/// Every criterion of a listing at once, all matching the fixture org.
+fn criteria(f: &Fixture) -> OrgCriteria {
+    OrgCriteria {
+        filters: vec![
+            filter(serde_json::json!({
+                "key": "name", "alias": "o", "op": "like",
+                "filterType": "string", "value": "o'brien", "likeStart": true
+            })),
+            filter(serde_json::json!({
+                "key": "postal_code", "alias": null, "op": "exact",
+                "filterType": "string", "value": "69001", "likeStart": false
+            })),
+        ],
+        // Repeating a label must not hide the orgs carrying it.
+        labels: vec![f.id_label, f.id_label],
+        contacted: Some(ContactedFilter {
+            after: Some(Utc::now().naive_utc() - chrono::Duration::days(1)),
+            before: None,
+            never: false,
+        }),
+    }
+}
+
+fn sort() -> Option<Sort> {
+    Some(Sort {
+        key: "lastContacted".to_string(),
+        desc: true,
+    })
+}
+
+fn pool() -> Pool {
+    Config::retrieve(true)
+        .expect("CNM_CONFIG must point to a test configuration")
+        .pool()
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore]
+async fn band_listing_runs() {
+    let pool = pool();
+    let f = fixture(&pool).await;
+    let (orgs, pag) = Org::new(pool)
+        .band_related_orgs_and_statuses(
+            f.id_user,
+            f.id_band,
+            criteria(&f),
+            sort(),
+            Some(Paginator::default()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(pag.item_count, Some(1));
+    assert_eq!(orgs.len(), 1);
+    assert_eq!(orgs[0].id_org, f.id_org);
+    assert_eq!(orgs[0].status.as_deref(), Some("raise"));
+    assert_eq!(orgs[0].labels, vec![f.id_label]);
+    assert!(orgs[0].last_contacted.is_some());
+}
+
+#[tokio::test]
+#[ignore]
+async fn all_listing_runs() {
+    let pool = pool();
+    let f = fixture(&pool).await;
+    let (orgs, pag) = Org::new(pool)
+        .all_orgs(f.id_band, criteria(&f), sort(), None)
+        .await
+        .unwrap();
+    assert_eq!(pag.item_count, Some(1));
+    assert_eq!(orgs.len(), 1);
+    assert_eq!(orgs[0].id_org, f.id_org);
+    assert_eq!(orgs[0].user_id, Some(f.id_user));
+}
+
+#[tokio::test]
+#[ignore]
+async fn new_matches_run() {
+    let pool = pool();
+    let f = fixture(&pool).await;
+    let org = Org::new(pool);
+    assert_eq!(
+        org.count_new(f.id_band, criteria(&f), None).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        org.count_new(f.id_band, criteria(&f), Some(Utc::now().naive_utc()))
+            .await
+            .unwrap(),
+        0
+    );
+}
@@ -0,0 +1,118 @@
+//! Erasure of accounts which are band members. They need a database and are
+//! ignored by default:
+//!
+//! `CNM_CONFIG=/path/to/test.json cargo test --test privacy -- --ignored`
+
+use chrono::Utc;
+use cnm::{
+    config::Config,
+    models::{privacy::Privacy, user::User},
+};
+use deadpool_postgres::Pool;
+
+fn pool() -> Pool {
+    Config::retrieve(true)
+        .expect("CNM_CONFIG must point to a test configuration")
+        .pool()
+        .unwrap()
+}
+
+/// A member of a new band with the given builtin role, assigned an org.
+/// Returns the email of the member, the band and the org.
+async fn member(pool: &Pool, role: &str) -> (String, i32, i32) {
+    let client = pool.get().await.unwrap();
+    let suffix = Utc::now().timestamp_nanos();
+    let email = format!("privacy{}@example.org", suffix);
+    let id_user: i32 = client
+        .query_one(
+            "
+            INSERT INTO cnm_user(pseudo, name, firstname, email, pwd)
+            VALUES ($1, 'Test', 'Test', $2, 'x')
+            RETURNING id
+            ",
+            &[&format!("privacy{}", suffix), &email],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_band: i32 = client
+        .query_one(
+            "INSERT INTO band(name, id_creator) VALUES ('Privacy', $1) RETURNING id",
+            &[&id_user],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let id_role: i32 = client
+        .query_one(
+            "SELECT id FROM band_role WHERE id_band IS NULL AND name = $1",
+            &[&role],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    User::new(pool.clone())
+        .add_band(id_user, id_band, id_role)
+        .await
+        .unwrap();
+    let id_org: i32 = client
+        .query_one(
+            "INSERT INTO org(name) VALUES ($1) RETURNING id",
+            &[&format!("Privacy {}", suffix)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    client
+        .execute(
+            "INSERT INTO org_assign(id_org, id_user, id_band, status) VALUES ($1, $2, $3, 'raise')",
+            &[&id_org, &id_user, &id_band],
+        )
+        .await
+        .unwrap();
+    (email, id_band, id_org)
+}
+
+#[tokio::test]
+#[ignore]
+async fn sole_owner_is_not_erased() {
+    let pool = pool();
+    let (email, id_band, _) = member(&pool, "owner").await;
+    let report = Privacy::new(pool.clone()).erase(&email).await.unwrap();
+    assert_eq!(report.blocking_bands, vec![id_band]);
+    assert!(!report.account);
+    let members: i64 = pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "SELECT COUNT(*) FROM user_band WHERE id_band = $1",
+            &[&id_band],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(members, 1);
+}
+
+#[tokio::test]
+#[ignore]
+async fn assigned_orgs_are_released() {
+    let pool = pool();
+    let (email, id_band, id_org) = member(&pool, "booker").await;
+    let report = Privacy::new(pool.clone()).erase(&email).await.unwrap();
+    assert!(report.blocking_bands.is_empty());
+    assert!(report.account);
+    let assignee: Option<i32> = pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "SELECT id_user FROM org_assign WHERE id_org = $1 AND id_band = $2",
+            &[&id_org, &id_band],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(assignee, None);
+}